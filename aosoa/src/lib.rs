@@ -186,7 +186,8 @@ macro_rules! soa_simd {
                     }
                 }
 
-                /// Pushes a whole pack
+                /// Pushes a whole pack.
+                /// Returns the index of each lane, counted from the current length.
                 #[inline(always)]
                 pub fn push<const L: usize>(&mut self, item: $name<$($( $generic_type ),*,)? L>)
                     -> ::core::simd::Simd<usize, L>
@@ -194,13 +195,15 @@ macro_rules! soa_simd {
                 {
                     let len = self.len();
                     $( self.$field.extend_from_slice(item.$field.as_array()); )*
-                    let new_len = self.len();
 
-                    $crate::iota() + ::core::simd::Simd::splat(new_len - len)
+                    ::core::simd::Simd::splat(len) + $crate::iota()
                 }
 
                 /// Pushes a whole pack, masked
                 /// This is less performant than [Self::push]
+                ///
+                /// Returns the index of each pushed lane, the pushed lanes being contiguous.
+                /// A masked-out lane gets the index of the next pushed lane.
                 #[inline(always)]
                 pub fn push_masked<const L: usize>(&mut self, item: $name<$($( $generic_type ),*,)? L>, mask: ::core::simd::Mask<isize, L>)
                     -> ::core::simd::Simd<usize, L>
@@ -210,23 +213,27 @@ macro_rules! soa_simd {
                         return self.push(item);
                     }
 
-                    let len = self.len();
+                    let mut indices = [self.len(); L];
                     for i in 0..L {
+                        indices[i] = self.len();
                         if mask.test(i) {
                             $( self.$field.push(item.$field[i]); )*
                         }
                     }
-                    let new_len = self.len();
 
-                    $crate::iota() + ::core::simd::Simd::splat(new_len - len)
+                    ::core::simd::Simd::from_array(indices)
                 }
 
-                /// Extends the vec-backed slice with the data of another one
+                /// Extends the vec-backed slice with the data of another one,
+                /// its items getting the indices from the current length on
                 #[inline(always)]
                 pub fn extend<
                     $([< $field:upper >]),*
-                >(&mut self, other: &$slice_name<$([< $field:upper >]),*>) {
-                    todo!()
+                >(&mut self, other: &$slice_name<$([< $field:upper >]),*>)
+                where
+                    $( [< $field:upper >] : ::core::ops::Deref<Target = [$field_type]> ),*
+                {
+                    $( self.$field.extend_from_slice(&other.$field); )*
                 }
             }
 
@@ -533,11 +533,28 @@ async fn process_image(data: &[u8]) -> Result<PathData> {
         include_start: true,
     };
     
-    // Use nearest-neighbor optimization (optimize_points is commented out in the library)
-    let order = optimized_nearest_neighbor(&points, &optimizer);
+    let x = points.iter().map(|p| p.position.x).collect::<Vec<_>>();
+    let y = points.iter().map(|p| p.position.y).collect::<Vec<_>>();
+    // CPU-bound, kept off the async workers
+    let (tour, lower_bound) = tokio::task::spawn_blocking(move || {
+        let slice = plot_planner::generation::PointSlice { x: &x[..], y: &y[..] };
+        let mut tour = optimizer.optimize_points(slice.clone());
+        optimizer.improve_points(slice.clone(), &mut tour, &plot_planner::optimization::LocalSearchSettings::default());
+        let lower_bound = optimizer.points_lower_bound(slice);
+        (tour, lower_bound)
+    })
+    .await?;
+    let gap = tour.gap(lower_bound);
+    info!(
+        "Optimized path of {} points, cost {} (within {:.1}% of optimal)",
+        tour.order.len(),
+        tour.cost,
+        100.0 * gap
+    );
     
     // Convert to waypoints
-    let waypoints: Vec<Waypoint> = order
+    let waypoints: Vec<Waypoint> = tour
+        .order
         .iter()
         .map(|&idx| {
             let point = &points[idx];
@@ -550,7 +567,7 @@ async fn process_image(data: &[u8]) -> Result<PathData> {
         })
         .collect();
     
-    Ok(PathData { waypoints })
+    Ok(PathData { waypoints, gap })
 }
 
 // Helper struct for screening bounds
@@ -630,70 +647,11 @@ fn prepare_screening_bounds(
     }
 }
 
-// Improved nearest-neighbor optimization with energy cost consideration
-fn optimized_nearest_neighbor(
-    points: &[plot_planner::path::Point],
-    settings: &plot_planner::optimization::OptimizationSettings,
-) -> Vec<usize> {
-    if points.is_empty() {
-        return vec![];
-    }
-
-    let mut visited = vec![false; points.len()];
-    let mut order = Vec::with_capacity(points.len());
-    
-    // Start at the point closest to the start position
-    let mut current = 0;
-    let mut min_dist = f32::MAX;
-    for (i, point) in points.iter().enumerate() {
-        let diff = point.position - settings.start;
-        // Apply energy cost weights: up=1.5, down=0.1, forward=1.0
-        let cost = (diff.x.abs() * 1.0) + (diff.y.abs() * if diff.y < 0.0 { 1.5 } else { 0.1 });
-        if cost < min_dist {
-            min_dist = cost;
-            current = i;
-        }
-    }
-
-    order.push(current);
-    visited[current] = true;
-
-    // Greedy nearest neighbor with energy-aware distance
-    while order.len() < points.len() {
-        let mut best = None;
-        let mut best_cost = f32::MAX;
-
-        for (i, point) in points.iter().enumerate() {
-            if visited[i] {
-                continue;
-            }
-
-            let diff = point.position - points[current].position;
-            // Calculate energy-aware cost: up=1.5, down=0.1, forward=1.0
-            let cost = (diff.x.abs() * 1.0) + (diff.y.abs() * if diff.y < 0.0 { 1.5 } else { 0.1 });
-
-            if cost < best_cost {
-                best_cost = cost;
-                best = Some(i);
-            }
-        }
-
-        if let Some(next) = best {
-            order.push(next);
-            visited[next] = true;
-            current = next;
-        } else {
-            break;
-        }
-    }
-
-    order
-}
-
-
 #[derive(serde::Serialize, serde::Deserialize)]
 struct PathData {
     waypoints: Vec<Waypoint>,
+    /// The path is at most this much (relatively) above the optimal one
+    gap: f32,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Clone)]
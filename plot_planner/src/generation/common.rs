@@ -119,6 +119,7 @@ pub trait GenerationBuffer {
 }
 
 pub enum GenerationControlFlow<E> {
+    /// Nothing was left to do: the work of a call is always reported as [`Self::Ongoing`]
     Finished,
     Ongoing {
         /// Units of work completed in this specific call
@@ -0,0 +1,210 @@
+use super::{GenerationBuffer, GenerationControlFlow, GenerationProcess};
+
+type Cube<'a, S> = crate::generation::hdp_common::memory::Cube<&'a [S]>;
+
+/// A cheap, clonable flag to stop a running process from another thread (e.g. a UI).
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(std::sync::Arc<std::sync::atomic::AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(std::sync::atomic::Ordering::Relaxed)
+    }
+}
+
+/// A snapshot of the progress of a process, as reported to the callback of [`drive`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    /// Units of work done so far, as reported by the deltas of the process
+    pub done: usize,
+    /// Bounds of the work left, see [`GenerationProcess::min_left`]
+    pub left: (usize, Option<usize>),
+    pub elapsed: std::time::Duration,
+}
+
+impl Progress {
+    /// Completion within `[0, 1]`.
+    /// Pessimistic: uses the upper bound of the work left when there is one.
+    pub fn fraction(&self) -> f32 {
+        let left = self.left.1.unwrap_or(self.left.0);
+        let total = self.done + left;
+        if total == 0 {
+            1.0
+        } else {
+            (self.done as f32) / (total as f32)
+        }
+    }
+
+    /// Estimated time left, assuming a constant throughput.
+    /// `None` until some work has been done, or if the work left is unbounded.
+    pub fn eta(&self) -> Option<std::time::Duration> {
+        let left = self.left.1?;
+        if self.done == 0 {
+            return None;
+        }
+        Some(self.elapsed.mul_f64((left as f64) / (self.done as f64)))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DriveError<E> {
+    /// The token was cancelled, the buffer holds what was generated until then
+    Cancelled,
+    Process(E),
+}
+
+/// Runs the process to completion, `chunk` units of work at a time.
+///
+/// The callback is called after each chunk, and the token is checked before each one.
+/// On cancellation, the process can be driven again from where it stopped.
+pub fn drive<S, P, B>(
+    process: &mut P,
+    image: &Cube<'_, S>,
+    buffer: &mut B,
+    chunk: usize,
+    cancel: &CancellationToken,
+    mut progress: impl FnMut(&Progress),
+) -> Result<Progress, DriveError<P::Error>>
+where
+    P: GenerationProcess<S>,
+    B: GenerationBuffer,
+{
+    let start = std::time::Instant::now();
+    let mut done = 0;
+    loop {
+        if cancel.is_cancelled() {
+            return Err(DriveError::Cancelled);
+        }
+
+        let finished = match process.generate(image, buffer, chunk) {
+            GenerationControlFlow::Finished => true,
+            GenerationControlFlow::Ongoing { delta } => {
+                done += delta;
+                false
+            }
+            GenerationControlFlow::Error(e) => return Err(DriveError::Process(e)),
+        };
+
+        let snapshot = Progress {
+            done,
+            left: if finished {
+                (0, Some(0))
+            } else {
+                process.min_left()
+            },
+            elapsed: start.elapsed(),
+        };
+        progress(&snapshot);
+
+        if finished {
+            return Ok(snapshot);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generation::{Dot, GenerationBufferVec, Point};
+    use core::simd;
+
+    /// Generates one dot per unit of work
+    struct CountdownProcess(usize);
+
+    impl GenerationProcess<f32> for CountdownProcess {
+        type Error = ();
+
+        fn generate<B: GenerationBuffer>(
+            &mut self,
+            _image: &Cube<'_, f32>,
+            buffer: &mut B,
+            count: usize,
+        ) -> GenerationControlFlow<Self::Error> {
+            if self.0 == 0 {
+                return GenerationControlFlow::Finished;
+            }
+            let delta = count.min(self.0);
+            for _ in 0..delta {
+                let point = Point::<1> {
+                    x: simd::Simd::splat(self.0 as f32),
+                    y: simd::Simd::splat(0.0),
+                };
+                let indices = buffer.push_points(point, simd::Mask::splat(true));
+                buffer.push_dots(Dot { index: indices }, simd::Mask::splat(true));
+                self.0 -= 1;
+            }
+            GenerationControlFlow::Ongoing { delta }
+        }
+
+        fn min_left(&self) -> (usize, Option<usize>) {
+            (self.0, Some(self.0))
+        }
+    }
+
+    #[test]
+    fn progress_is_monotonic() {
+        let data = [0.0f32; 1];
+        let image = Cube::from_image(&data[..]);
+        let mut buffer = GenerationBufferVec::new();
+
+        let mut reports = Vec::new();
+        let last = drive(
+            &mut CountdownProcess(10),
+            &image,
+            &mut buffer,
+            3,
+            &CancellationToken::new(),
+            |p| reports.push(*p),
+        )
+        .unwrap();
+
+        assert_eq!(last.done, 10);
+        assert_eq!(last.fraction(), 1.0);
+        assert_eq!(buffer.dots.index.len(), 10);
+        assert_eq!(reports[0].done, 3);
+        assert_eq!(reports[0].left, (7, Some(7)));
+        assert!(
+            reports
+                .windows(2)
+                .all(|w| w[0].fraction() <= w[1].fraction())
+        );
+    }
+
+    #[test]
+    fn cancellation_stops_between_chunks() {
+        let data = [0.0f32; 1];
+        let image = Cube::from_image(&data[..]);
+        let mut buffer = GenerationBufferVec::new();
+        let mut process = CountdownProcess(10);
+
+        let cancel = CancellationToken::new();
+        let result = drive(&mut process, &image, &mut buffer, 4, &cancel.clone(), |p| {
+            if p.done >= 4 {
+                cancel.cancel();
+            }
+        });
+        assert_eq!(result, Err(DriveError::Cancelled));
+        assert_eq!(buffer.dots.index.len(), 4);
+
+        // Resumes where it stopped
+        let last = drive(
+            &mut process,
+            &image,
+            &mut buffer,
+            4,
+            &CancellationToken::new(),
+            |_| {},
+        )
+        .unwrap();
+        assert_eq!(last.done, 6);
+        assert_eq!(buffer.dots.index.len(), 10);
+    }
+}
@@ -0,0 +1,573 @@
+// On-disk layout of a finished buffer, all in little endian:
+//
+// | magic (8) | version: u32 | reserved: u32 |
+// | im_width: u32 | im_height: u32 | position: [f32; 2] | ppu: f32 | reserved: u32 |
+// | points: u64 | edges: u64 | dots: u64 | settings length: u64 |
+// | settings (opaque) | padding |
+// | x: [f32] | padding | y: [f32] | padding |
+// | edges.from: [u64] | padding | edges.to: [u64] | padding | dots.index: [u64] |
+//
+// Each column starts on a `COLUMN_ALIGNMENT` boundary, so that it can be used in place once mapped.
+//
+// While generating, each column is spooled to its own file in a directory,
+// as their final offsets are only known once generation is over.
+
+use crate::generation::{Dot, DotSlice, Edge, EdgeSlice, GenerationBuffer, Point, PointSlice};
+use std::io::{Read, Seek, Write};
+
+const MAGIC: [u8; 8] = *b"PLTPLGEN";
+const VERSION: u32 = 1;
+const COLUMN_ALIGNMENT: u64 = 64;
+const FIXED_HEADER_SIZE: u64 = 8 + 4 + 4 + 4 + 4 + 8 + 4 + 4 + 4 * 8;
+
+const HEADER_FILENAME: &str = "header";
+const COLUMN_FILENAMES: [&str; 5] = ["x", "y", "from", "to", "index"];
+
+// Indices are stored as u64, and handed out in place as usize
+const _: () = assert!(core::mem::size_of::<usize>() == core::mem::size_of::<u64>());
+
+/// What was planned, and how: recorded alongside the primitives.
+#[derive(Clone, Debug)]
+pub struct GenerationFileHeader {
+    pub placement: crate::ImageWorldPlacement,
+    /// The generator settings, in whatever encoding the caller sees fit.
+    /// It is stored as-is.
+    pub settings: Vec<u8>,
+}
+
+impl GenerationFileHeader {
+    fn write_to<W: Write>(&self, writer: &mut W, counts: [u64; 3]) -> std::io::Result<()> {
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&0u32.to_le_bytes())?;
+
+        let placement = &self.placement;
+        writer.write_all(&placement.im_width.to_le_bytes())?;
+        writer.write_all(&placement.im_height.to_le_bytes())?;
+        writer.write_all(&placement.position.x.to_le_bytes())?;
+        writer.write_all(&placement.position.y.to_le_bytes())?;
+        writer.write_all(&placement.ppu.to_le_bytes())?;
+        writer.write_all(&0u32.to_le_bytes())?;
+
+        for count in counts {
+            writer.write_all(&count.to_le_bytes())?;
+        }
+        writer.write_all(&(self.settings.len() as u64).to_le_bytes())?;
+        writer.write_all(&self.settings)
+    }
+
+    /// Returns the header, the counts of points, edges & dots, and the size of the header in bytes
+    fn parse(bytes: &[u8]) -> std::io::Result<(Self, [u64; 3], u64)> {
+        let invalid =
+            |message: &'static str| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+
+        if bytes.len() < FIXED_HEADER_SIZE as usize {
+            return Err(invalid("truncated header"));
+        }
+        if bytes[..8] != MAGIC {
+            return Err(invalid("not a generation buffer"));
+        }
+
+        let u32_at =
+            |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let f32_at =
+            |offset: usize| f32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let u64_at =
+            |offset: usize| u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+
+        if u32_at(8) != VERSION {
+            return Err(invalid("unsupported generation buffer version"));
+        }
+
+        let placement = crate::ImageWorldPlacement::new(
+            u32_at(16),
+            u32_at(20),
+            nalgebra::Point2::new(f32_at(24), f32_at(28)),
+            f32_at(32),
+        );
+        let counts = [u64_at(40), u64_at(48), u64_at(56)];
+        let settings_len = u64_at(64);
+
+        let end = FIXED_HEADER_SIZE.saturating_add(settings_len);
+        if (bytes.len() as u64) < end {
+            return Err(invalid("truncated settings"));
+        }
+        let settings = bytes[FIXED_HEADER_SIZE as usize..end as usize].to_vec();
+
+        Ok((
+            Self {
+                placement,
+                settings,
+            },
+            counts,
+            end,
+        ))
+    }
+}
+
+/// A [`GenerationBuffer`] streaming its primitives to disk, so that their number is not bound by the RAM.
+///
+/// The columns are spooled in a directory until [`Self::finish`] packs them in a single file,
+/// to be read back with [`MappedGeneration`].
+/// The spool can be reopened with [`Self::resume`], e.g. to generate the tiles that are missing
+/// (see [`crate::generation::split_tiles`]) after an interruption.
+///
+/// As pushing cannot fail, the first I/O error is kept and returned by [`Self::flush`] & [`Self::finish`].
+/// Nothing is written anymore afterwards.
+pub struct GenerationBufferFile {
+    directory: std::path::PathBuf,
+    header: GenerationFileHeader,
+    /// x, y, from, to, index
+    columns: [std::io::BufWriter<std::fs::File>; 5],
+    points: usize,
+    edges: usize,
+    dots: usize,
+    error: Option<std::io::Error>,
+}
+
+impl GenerationBufferFile {
+    /// Creates the spool directory, that must not exist yet
+    pub fn create(
+        directory: impl Into<std::path::PathBuf>,
+        header: GenerationFileHeader,
+    ) -> std::io::Result<Self> {
+        let directory = directory.into();
+        std::fs::create_dir(&directory)?;
+
+        let mut file = std::fs::File::create_new(directory.join(HEADER_FILENAME))?;
+        header.write_to(&mut file, [0; 3])?;
+        file.sync_all()?;
+
+        Self::open_columns(directory, header, [0; 3])
+    }
+
+    /// Reopens a spool directory, to keep generating after what was already written.
+    ///
+    /// Partially written primitives (e.g. after a crash) are dropped,
+    /// as well as the edges & dots following one whose points were dropped.
+    pub fn resume(directory: impl Into<std::path::PathBuf>) -> std::io::Result<Self> {
+        let directory = directory.into();
+        let bytes = std::fs::read(directory.join(HEADER_FILENAME))?;
+        let (header, _, _) = GenerationFileHeader::parse(&bytes)?;
+
+        let lengths =
+            COLUMN_FILENAMES.map(|name| std::fs::metadata(directory.join(name)).map(|m| m.len()));
+        let [x, y, from, to, index] = lengths;
+        let points = x?.min(y?) / 4;
+        let edges = indices_below(&directory.join("from"), from? / 8, points)?.min(indices_below(
+            &directory.join("to"),
+            to? / 8,
+            points,
+        )?);
+        let dots = indices_below(&directory.join("index"), index? / 8, points)?;
+
+        Self::open_columns(directory, header, [points, edges, dots])
+    }
+
+    fn open_columns(
+        directory: std::path::PathBuf,
+        header: GenerationFileHeader,
+        [points, edges, dots]: [u64; 3],
+    ) -> std::io::Result<Self> {
+        let lengths = [points * 4, points * 4, edges * 8, edges * 8, dots * 8];
+
+        let mut columns = Vec::with_capacity(COLUMN_FILENAMES.len());
+        for (name, length) in COLUMN_FILENAMES.iter().zip(lengths) {
+            let mut file = std::fs::File::options()
+                .create(true)
+                .write(true)
+                .truncate(false)
+                .open(directory.join(name))?;
+            file.set_len(length)?;
+            file.seek(std::io::SeekFrom::End(0))?;
+            columns.push(std::io::BufWriter::new(file));
+        }
+
+        Ok(Self {
+            directory,
+            header,
+            columns: columns.try_into().ok().unwrap(),
+            points: points as usize,
+            edges: edges as usize,
+            dots: dots as usize,
+            error: None,
+        })
+    }
+
+    pub fn header(&self) -> &GenerationFileHeader {
+        &self.header
+    }
+
+    pub fn directory(&self) -> &std::path::Path {
+        &self.directory
+    }
+
+    /// Number of points, edges & dots pushed so far
+    pub fn counts(&self) -> [usize; 3] {
+        [self.points, self.edges, self.dots]
+    }
+
+    fn write(&mut self, column: usize, bytes: &[u8]) {
+        if self.error.is_some() {
+            return;
+        }
+        if let Err(e) = self.columns[column].write_all(bytes) {
+            self.error = Some(e);
+        }
+    }
+
+    /// Flushes the spooled columns to disk
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        for column in self.columns.iter_mut() {
+            column.flush()?;
+            column.get_ref().sync_data()?;
+        }
+        Ok(())
+    }
+
+    /// Packs the columns in a single file, and removes the spool directory
+    pub fn finish(mut self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+        self.flush()?;
+
+        let mut output = std::io::BufWriter::new(std::fs::File::create(path)?);
+        let counts = [self.points, self.edges, self.dots].map(|c| c as u64);
+        self.header.write_to(&mut output, counts)?;
+
+        let mut position = FIXED_HEADER_SIZE + self.header.settings.len() as u64;
+        for name in COLUMN_FILENAMES {
+            let padding = position.next_multiple_of(COLUMN_ALIGNMENT) - position;
+            output.write_all(&[0; COLUMN_ALIGNMENT as usize][..padding as usize])?;
+            position += padding;
+
+            let mut column = std::fs::File::open(self.directory.join(name))?;
+            position += std::io::copy(&mut column, &mut output)?;
+        }
+
+        output
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+
+        drop(self.columns);
+        std::fs::remove_dir_all(&self.directory)
+    }
+}
+
+/// Number of leading indices of the first `count` ones of a column that are below `points`
+fn indices_below(path: &std::path::Path, count: u64, points: u64) -> std::io::Result<u64> {
+    let mut reader = std::io::BufReader::new(std::fs::File::open(path)?);
+    let mut bytes = [0; 8];
+    for k in 0..count {
+        reader.read_exact(&mut bytes)?;
+        if u64::from_le_bytes(bytes) >= points {
+            return Ok(k);
+        }
+    }
+    Ok(count)
+}
+
+impl GenerationBuffer for GenerationBufferFile {
+    fn push_points<const L: usize>(
+        &mut self,
+        point: Point<L>,
+        mask: core::simd::Mask<isize, L>,
+    ) -> core::simd::Simd<usize, L> {
+        let mut indices = [self.points; L];
+        for (i, index) in indices.iter_mut().enumerate() {
+            *index = self.points;
+            if mask.test(i) {
+                self.write(0, &point.x[i].to_le_bytes());
+                self.write(1, &point.y[i].to_le_bytes());
+                self.points += 1;
+            }
+        }
+        core::simd::Simd::from_array(indices)
+    }
+
+    fn push_lines<const L: usize>(
+        &mut self,
+        line: Edge<usize, L>,
+        mask: core::simd::Mask<isize, L>,
+    ) -> core::simd::Simd<usize, L> {
+        let mut indices = [self.edges; L];
+        for (i, index) in indices.iter_mut().enumerate() {
+            *index = self.edges;
+            if mask.test(i) {
+                self.write(2, &(line.from[i] as u64).to_le_bytes());
+                self.write(3, &(line.to[i] as u64).to_le_bytes());
+                self.edges += 1;
+            }
+        }
+        core::simd::Simd::from_array(indices)
+    }
+
+    fn push_dots<const L: usize>(
+        &mut self,
+        dots: Dot<L>,
+        mask: core::simd::Mask<isize, L>,
+    ) -> core::simd::Simd<usize, L> {
+        let mut indices = [self.dots; L];
+        for (i, index) in indices.iter_mut().enumerate() {
+            *index = self.dots;
+            if mask.test(i) {
+                self.write(4, &(dots.index[i] as u64).to_le_bytes());
+                self.dots += 1;
+            }
+        }
+        core::simd::Simd::from_array(indices)
+    }
+}
+
+/// A finished [`GenerationBufferFile`], memory-mapped: the columns are used in place.
+pub struct MappedGeneration {
+    map: memmap2::Mmap,
+    header: GenerationFileHeader,
+    /// Byte offsets of each column: x, y, from, to, index
+    offsets: [usize; 5],
+    counts: [usize; 3],
+}
+
+impl MappedGeneration {
+    pub fn open(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        let invalid =
+            |message: &'static str| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+        if cfg!(target_endian = "big") {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "generation buffers are little endian",
+            ));
+        }
+
+        let mut file = std::fs::File::open(path)?;
+        let file_len = file.metadata()?.len();
+
+        // Only the header is read, the columns are mapped
+        let mut fixed = vec![0; FIXED_HEADER_SIZE as usize];
+        file.read_exact(&mut fixed)?;
+        let settings_len = u64::from_le_bytes(fixed[64..72].try_into().unwrap());
+        if settings_len > file_len.saturating_sub(FIXED_HEADER_SIZE) {
+            return Err(invalid("truncated settings"));
+        }
+        fixed.resize((FIXED_HEADER_SIZE + settings_len) as usize, 0);
+        file.read_exact(&mut fixed[FIXED_HEADER_SIZE as usize..])?;
+        let (header, counts, mut position) = GenerationFileHeader::parse(&fixed)?;
+
+        // SAFETY: the file is not expected to be modified while mapped,
+        // which is the case for a finished buffer.
+        let map = unsafe { memmap2::Mmap::map(&file)? };
+
+        let [points, edges, dots] = counts;
+        let lengths = [(points, 4), (points, 4), (edges, 8), (edges, 8), (dots, 8)];
+        let mut offsets = [0; 5];
+        for (offset, (count, size)) in offsets.iter_mut().zip(lengths) {
+            position = position
+                .checked_next_multiple_of(COLUMN_ALIGNMENT)
+                .and_then(|start| {
+                    *offset = start as usize;
+                    start.checked_add(count.checked_mul(size)?)
+                })
+                .ok_or_else(|| invalid("too many primitives"))?;
+        }
+        if (map.len() as u64) < position {
+            return Err(invalid("truncated columns"));
+        }
+
+        Ok(Self {
+            map,
+            header,
+            offsets,
+            counts: counts.map(|c| c as usize),
+        })
+    }
+
+    pub fn header(&self) -> &GenerationFileHeader {
+        &self.header
+    }
+
+    fn column<T>(&self, column: usize, len: usize) -> &[T] {
+        let ptr = self.map[self.offsets[column]..].as_ptr();
+        debug_assert!(ptr.cast::<T>().is_aligned());
+        // SAFETY: the column is within the map (checked when opening),
+        // aligned (the map is page aligned, and the columns on `COLUMN_ALIGNMENT`),
+        // and any bit pattern is valid for `f32` & `usize`.
+        unsafe { core::slice::from_raw_parts(ptr.cast::<T>(), len) }
+    }
+
+    pub fn points(&self) -> PointSlice<&[f32], &[f32]> {
+        PointSlice {
+            x: self.column(0, self.counts[0]),
+            y: self.column(1, self.counts[0]),
+        }
+    }
+
+    pub fn edges(&self) -> EdgeSlice<&[usize], &[usize]> {
+        EdgeSlice {
+            from: self.column(2, self.counts[1]),
+            to: self.column(3, self.counts[1]),
+        }
+    }
+
+    pub fn dots(&self) -> DotSlice<&[usize]> {
+        DotSlice {
+            index: self.column(4, self.counts[2]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::simd;
+
+    fn directory(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("plot_planner_{}_{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn header() -> GenerationFileHeader {
+        GenerationFileHeader {
+            placement: crate::ImageWorldPlacement::new(
+                640,
+                480,
+                nalgebra::Point2::new(1.0, 2.0),
+                4.0,
+            ),
+            settings: b"resolution=1.0".to_vec(),
+        }
+    }
+
+    fn push(buffer: &mut impl GenerationBuffer, start: f32) {
+        let x = simd::Simd::from_array([start, start + 1.0, start + 2.0, start + 3.0]);
+        let mask = simd::Mask::from_array([true, false, true, true]);
+        let indices = buffer.push_points(Point { x, y: -x }, mask);
+        buffer.push_dots(Dot { index: indices }, mask);
+        buffer.push_lines(
+            Edge {
+                from: indices,
+                to: indices.rotate_elements_left::<1>(),
+            },
+            mask,
+        );
+    }
+
+    #[test]
+    fn roundtrip_matches_vec() {
+        let spool = directory("roundtrip");
+        let packed = directory("roundtrip.gen");
+
+        let mut reference = crate::generation::GenerationBufferVec::new();
+        let mut file = GenerationBufferFile::create(&spool, header()).unwrap();
+        for start in [0.0, 10.0, 20.0] {
+            push(&mut reference, start);
+            push(&mut file, start);
+        }
+        assert_eq!(file.counts(), [9, 9, 9]);
+        file.finish(&packed).unwrap();
+        assert!(!spool.exists());
+
+        let mapped = MappedGeneration::open(&packed).unwrap();
+        assert_eq!(mapped.header().settings, header().settings);
+        assert_eq!(mapped.header().placement.ppu, 4.0);
+        assert_eq!(mapped.points().x, &reference.points.x[..]);
+        assert_eq!(mapped.points().y, &reference.points.y[..]);
+        assert_eq!(mapped.edges().from, &reference.edges.from[..]);
+        assert_eq!(mapped.edges().to, &reference.edges.to[..]);
+        assert_eq!(mapped.dots().index, &reference.dots.index[..]);
+
+        std::fs::remove_file(&packed).unwrap();
+    }
+
+    #[test]
+    fn resume_appends_after_flushed() {
+        let spool = directory("resume");
+        let packed = directory("resume.gen");
+
+        let mut file = GenerationBufferFile::create(&spool, header()).unwrap();
+        push(&mut file, 0.0);
+        file.flush().unwrap();
+        drop(file);
+
+        // A torn write, as after a crash
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(spool.join("x"))
+            .unwrap()
+            .write_all(&[0, 1])
+            .unwrap();
+
+        let mut file = GenerationBufferFile::resume(&spool).unwrap();
+        assert_eq!(file.counts(), [3, 3, 3]);
+        push(&mut file, 10.0);
+        file.finish(&packed).unwrap();
+
+        let mapped = MappedGeneration::open(&packed).unwrap();
+        assert_eq!(mapped.points().x, &[0.0, 2.0, 3.0, 10.0, 12.0, 13.0]);
+        assert_eq!(mapped.dots().index, &[0, 1, 2, 3, 4, 5]);
+
+        std::fs::remove_file(&packed).unwrap();
+    }
+
+    #[test]
+    fn resume_drops_what_refers_to_dropped_points() {
+        let spool = directory("resume_partial");
+        let packed = directory("resume_partial.gen");
+
+        let mut file = GenerationBufferFile::create(&spool, header()).unwrap();
+        push(&mut file, 0.0);
+        push(&mut file, 10.0);
+        file.flush().unwrap();
+        drop(file);
+
+        // The last points were lost, but not their dots & edges
+        let y = std::fs::OpenOptions::new()
+            .write(true)
+            .open(spool.join("y"))
+            .unwrap();
+        y.set_len(4 * 4 + 2).unwrap();
+
+        let mut file = GenerationBufferFile::resume(&spool).unwrap();
+        assert_eq!(file.counts(), [4, 3, 4]);
+        push(&mut file, 20.0);
+        file.finish(&packed).unwrap();
+
+        let mapped = MappedGeneration::open(&packed).unwrap();
+        assert_eq!(mapped.points().x, &[0.0, 2.0, 3.0, 10.0, 20.0, 22.0, 23.0]);
+        assert_eq!(mapped.dots().index, &[0, 1, 2, 3, 4, 5, 6]);
+        assert_eq!(mapped.edges().from, &[0, 1, 2, 4, 5, 6]);
+        let points = mapped.points().x.len();
+        assert!(mapped.edges().to.iter().all(|i| *i < points));
+
+        std::fs::remove_file(&packed).unwrap();
+    }
+
+    #[test]
+    fn open_rejects_corrupt_counts() {
+        let spool = directory("corrupt");
+        let packed = directory("corrupt.gen");
+
+        let mut file = GenerationBufferFile::create(&spool, header()).unwrap();
+        push(&mut file, 0.0);
+        file.finish(&packed).unwrap();
+        let bytes = std::fs::read(&packed).unwrap();
+
+        // Settings longer than the file, then counts whose columns overflow
+        for (offset, value) in [
+            (64, u64::MAX / 2),
+            (40, u64::MAX / 4 + 1),
+            (48, u64::MAX / 8),
+        ] {
+            let mut corrupt = bytes.clone();
+            corrupt[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+            std::fs::write(&packed, &corrupt).unwrap();
+            let error = MappedGeneration::open(&packed).err().unwrap();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        }
+
+        std::fs::remove_file(&packed).unwrap();
+    }
+}
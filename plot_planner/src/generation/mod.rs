@@ -1,7 +1,7 @@
 mod common;
 pub use common::{
-    Dot, DotSlice, Edge, EdgeSlice, GenerationBuffer, GenerationProcess, Generator, Point,
-    PointSlice,
+    Dot, DotSlice, Edge, EdgeSlice, GenerationBuffer, GenerationControlFlow, GenerationProcess,
+    Generator, Point, PointSlice,
 };
 
 mod screening;
@@ -12,6 +12,17 @@ pub use screening::{FMScreeningGenerator, FMScreeningProcess};
 mod vec_generation_buffer;
 pub use vec_generation_buffer::GenerationBufferVec;
 
+mod file_generation_buffer;
+pub use file_generation_buffer::{GenerationBufferFile, GenerationFileHeader, MappedGeneration};
+
+mod driver;
+pub use driver::{drive, CancellationToken, DriveError, Progress};
+
+mod parallel;
+#[cfg(feature = "rayon")]
+pub use parallel::par_generate_tiles;
+pub use parallel::{generate_tiles, split_tiles, SplittableProcess};
+
 #[cfg(feature = "hdp")]
 pub use hdp_iter::common as hdp_common;
 
@@ -0,0 +1,234 @@
+extern crate alloc;
+use super::{GenerationBufferVec, GenerationControlFlow, GenerationProcess};
+
+type Cube<'a, S> = crate::generation::hdp_common::memory::Cube<&'a [S]>;
+
+/// A process whose work can be split in two independent halves,
+/// in the manner of [`rayon::iter::split`].
+///
+/// Splitting must be deterministic: the same process split the same way
+/// must generate the same primitives, whatever the thread that runs each half.
+///
+/// The FM screening (`FMScreeningProcess`) splits along its grid, but, like the whole
+/// screening process, only with the `hdp` feature.
+pub trait SplittableProcess<S>: GenerationProcess<S> + Sized {
+    /// Returns the first half, and the second one if the process could be split
+    fn split(self) -> (Self, Option<Self>);
+}
+
+/// Splits the process in (at most) `tiles` regions, breadth first.
+///
+/// The regions are ordered as the process would have generated them,
+/// and their number does not depend on the number of threads.
+pub fn split_tiles<S, P>(process: P, tiles: usize) -> Vec<P>
+where
+    P: SplittableProcess<S>,
+{
+    let mut regions = vec![process];
+    while regions.len() < tiles {
+        let before = regions.len();
+        let mut count = before;
+        let mut next = Vec::with_capacity(regions.len() * 2);
+        for region in regions {
+            if count >= tiles {
+                // Splitting this one would overshoot
+                next.push(region);
+                continue;
+            }
+
+            let (first, second) = region.split();
+            next.push(first);
+            if let Some(second) = second {
+                next.push(second);
+                count += 1;
+            }
+        }
+
+        regions = next;
+
+        // Nothing could be split anymore
+        if count == before {
+            break;
+        }
+    }
+    regions
+}
+
+fn run_to_end<S, P>(
+    mut process: P,
+    image: &Cube<'_, S>,
+    chunk: usize,
+) -> Result<GenerationBufferVec<alloc::alloc::Global>, P::Error>
+where
+    P: GenerationProcess<S>,
+{
+    let mut buffer = GenerationBufferVec::new();
+    loop {
+        match process.generate(image, &mut buffer, chunk) {
+            GenerationControlFlow::Finished => return Ok(buffer),
+            GenerationControlFlow::Ongoing { .. } => {}
+            GenerationControlFlow::Error(e) => return Err(e),
+        }
+    }
+}
+
+fn merge(
+    buffers: impl IntoIterator<Item = GenerationBufferVec<alloc::alloc::Global>>,
+) -> GenerationBufferVec<alloc::alloc::Global> {
+    let mut merged = GenerationBufferVec::new();
+    for buffer in buffers {
+        merged.append(&buffer);
+    }
+    merged
+}
+
+/// Runs each tile to completion, one after the other.
+///
+/// This is the sequential reference of [`par_generate_tiles`]: both return the same buffer.
+pub fn generate_tiles<S, P>(
+    process: P,
+    image: &Cube<'_, S>,
+    tiles: usize,
+    chunk: usize,
+) -> Result<GenerationBufferVec<alloc::alloc::Global>, P::Error>
+where
+    P: SplittableProcess<S>,
+{
+    let buffers = split_tiles(process, tiles)
+        .into_iter()
+        .map(|tile| run_to_end(tile, image, chunk))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(merge(buffers))
+}
+
+/// Runs each tile to completion in parallel, then merges the per-tile buffers.
+///
+/// The merge follows the tile order, so the point indices are stable
+/// whatever the number of threads.
+#[cfg(feature = "rayon")]
+pub fn par_generate_tiles<S, P>(
+    process: P,
+    image: &Cube<'_, S>,
+    tiles: usize,
+    chunk: usize,
+) -> Result<GenerationBufferVec<alloc::alloc::Global>, P::Error>
+where
+    P: SplittableProcess<S> + Send,
+    P::Error: Send,
+    for<'a> Cube<'a, S>: Sync,
+{
+    use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+    let buffers = split_tiles(process, tiles)
+        .into_par_iter()
+        .map(|tile| run_to_end(tile, image, chunk))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(merge(buffers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generation::{Dot, GenerationBuffer, Point};
+    use core::simd;
+    use rand::{Rng, SeedableRng};
+
+    /// Generates one point per index of its range, keeping it with a probability of one half
+    struct RangeProcess {
+        range: core::ops::Range<usize>,
+        rng: rand_xoshiro::Xoroshiro64Star,
+    }
+
+    impl GenerationProcess<f32> for RangeProcess {
+        type Error = ();
+
+        fn generate<B: GenerationBuffer>(
+            &mut self,
+            _image: &Cube<'_, f32>,
+            buffer: &mut B,
+            count: usize,
+        ) -> GenerationControlFlow<Self::Error> {
+            const L: usize = 4;
+            let left = self.range.len();
+            for _ in 0..count.div_ceil(L) {
+                if self.range.is_empty() {
+                    return GenerationControlFlow::Finished;
+                }
+                let start = self.range.start;
+                self.range.start = (start + L).min(self.range.end);
+
+                let x =
+                    simd::Simd::<f32, L>::from_array(core::array::from_fn(|i| (start + i) as f32));
+                let mask = simd::Mask::<isize, L>::from_array(core::array::from_fn(|i| {
+                    start + i < self.range.end && self.rng.random_bool(0.5)
+                }));
+                let indices = buffer.push_points(Point { x, y: x }, mask);
+                buffer.push_dots(Dot { index: indices }, mask);
+            }
+            GenerationControlFlow::Ongoing {
+                delta: left - self.range.len(),
+            }
+        }
+
+        fn min_left(&self) -> (usize, Option<usize>) {
+            (self.range.len(), Some(self.range.len()))
+        }
+    }
+
+    impl SplittableProcess<f32> for RangeProcess {
+        fn split(mut self) -> (Self, Option<Self>) {
+            if self.range.len() < 2 {
+                return (self, None);
+            }
+            let pivot = self.range.start + self.range.len() / 2;
+            let first = Self {
+                range: self.range.start..pivot,
+                rng: rand_xoshiro::Xoroshiro64Star::from_rng(&mut self.rng),
+            };
+            let second = Self {
+                range: pivot..self.range.end,
+                rng: rand_xoshiro::Xoroshiro64Star::from_rng(&mut self.rng),
+            };
+            (first, Some(second))
+        }
+    }
+
+    fn process() -> RangeProcess {
+        RangeProcess {
+            range: 0..1000,
+            rng: rand_xoshiro::Xoroshiro64Star::seed_from_u64(0),
+        }
+    }
+
+    #[test]
+    fn split_tiles_is_bounded() {
+        assert_eq!(split_tiles(process(), 1).len(), 1);
+        assert_eq!(split_tiles(process(), 7).len(), 7);
+        assert_eq!(split_tiles(process(), 64).len(), 64);
+    }
+
+    #[test]
+    fn tiles_are_merged_in_order() {
+        let data = [0.0f32; 1];
+        let image = Cube::from_image(&data[..]);
+
+        let sequential = generate_tiles(process(), &image, 8, 16).unwrap();
+        assert!(sequential.points.len() > 0);
+        assert_eq!(sequential.dots.index.len(), sequential.points.len());
+
+        // The points are ordered along the range, and the dots reference them
+        assert!(sequential.points.x.is_sorted());
+        for (i, index) in sequential.dots.index.iter().enumerate() {
+            assert_eq!(*index, i);
+        }
+
+        #[cfg(feature = "rayon")]
+        {
+            let parallel = par_generate_tiles(process(), &image, 8, 16).unwrap();
+            assert_eq!(parallel.points, sequential.points);
+            assert_eq!(parallel.dots, sequential.dots);
+        }
+    }
+}
@@ -93,3 +93,75 @@ pub(super) fn prepare_screen(
         im_y_range,
     }
 }
+
+impl ScreeningBounds {
+    /// Splits the grid ranges in two independent halves, rows first.
+    /// The ranges are half-open, as iterated by the screening iterator.
+    ///
+    /// The image ranges are kept as-is, as they are only used for the final containment check.
+    pub(super) fn split(self) -> (Self, Option<Self>) {
+        let [i_min, i_max] = self.i_range;
+        let [j_min, j_max] = self.j_range;
+
+        if j_max - j_min > 1 {
+            let pivot = j_min + (j_max - j_min) / 2;
+            let top = Self {
+                j_range: [j_min, pivot],
+                ..self.clone()
+            };
+            let bottom = Self {
+                j_range: [pivot, j_max],
+                ..self
+            };
+            (top, Some(bottom))
+        } else if i_max - i_min > 1 {
+            let pivot = i_min + (i_max - i_min) / 2;
+            let left = Self {
+                i_range: [i_min, pivot],
+                ..self.clone()
+            };
+            let right = Self {
+                i_range: [pivot, i_max],
+                ..self
+            };
+            (left, Some(right))
+        } else {
+            (self, None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn halves_partition_the_bounds() {
+        let bounds = ScreeningBounds {
+            grid_to_world: nalgebra::Isometry2::identity(),
+            i_range: [-3, 4],
+            j_range: [2, 7],
+            im_x_range: [0.0, 1.0],
+            im_y_range: [0.0, 1.0],
+        };
+
+        // Split all the way down, each cell of the grid should be found once
+        let mut regions = vec![bounds];
+        let mut cells = Vec::new();
+        while let Some(region) = regions.pop() {
+            match region.split() {
+                (first, Some(second)) => regions.extend([first, second]),
+                (cell, None) => {
+                    assert_eq!(cell.i_range[1] - cell.i_range[0], 1);
+                    assert_eq!(cell.j_range[1] - cell.j_range[0], 1);
+                    cells.push((cell.i_range[0], cell.j_range[0]));
+                }
+            }
+        }
+        cells.sort_unstable();
+        let expected = (-3..4)
+            .flat_map(|i| (2..7).map(move |j| (i, j)))
+            .collect::<Vec<_>>();
+        assert_eq!(cells, expected);
+    }
+}
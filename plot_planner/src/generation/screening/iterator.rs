@@ -15,13 +15,17 @@ pub struct ScreeningIterator<const L: usize> {
     inner: hdp_iter::CoordinatesIterator<L>,
     bounds: ScreeningBounds,
     grid: ScreeningGrid,
+    /// Number of grid positions already visited, including the ones outside of the image
+    visited: usize,
 }
 
 #[cfg(feature = "hdp")]
 impl<const L: usize> ScreeningIterator<L> {
     pub fn new(im: &crate::ImageWorldPlacement, grid: ScreeningGrid) -> Self {
-        let bounds = prepare_screen(im, &grid);
+        Self::from_bounds(prepare_screen(im, &grid), grid)
+    }
 
+    pub(super) fn from_bounds(bounds: ScreeningBounds, grid: ScreeningGrid) -> Self {
         // We voluntarily do not iterate on the image, but on the calculated bounds
         // of the grid that lay within our zone of interest.
         let inner = hdp_iter::CoordinatesIterator::new(
@@ -41,8 +45,32 @@ impl<const L: usize> ScreeningIterator<L> {
             inner,
             bounds,
             grid,
+            visited: 0,
         }
     }
+
+    /// Number of grid positions in the bounds, visited or not
+    pub(super) fn total(&self) -> usize {
+        let width = (self.bounds.i_range[1] - self.bounds.i_range[0]).max(0) as usize;
+        let height = (self.bounds.j_range[1] - self.bounds.j_range[0]).max(0) as usize;
+        width * height
+    }
+
+    /// Number of grid positions left to visit.
+    /// Every position is visited, so this is exact, whatever the image.
+    pub(super) fn left(&self) -> usize {
+        self.total().saturating_sub(self.visited)
+    }
+
+    /// Splits the grid in two independent iterators, see [`ScreeningBounds::split`].
+    /// This restarts the iteration, so it should only be done before iterating.
+    pub(super) fn split(self) -> (Self, Option<Self>) {
+        let (first, second) = self.bounds.split();
+        (
+            Self::from_bounds(first, self.grid.clone()),
+            second.map(|second| Self::from_bounds(second, self.grid)),
+        )
+    }
 }
 
 #[cfg(feature = "hdp")]
@@ -55,6 +83,9 @@ where
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
         while let Some((position, mask)) = self.inner.next() {
+            // The last chunk of a row may be partial
+            self.visited += mask.to_bitmask().count_ones() as usize;
+
             // Compute Local Grid Coordinates (P')
             // P' = (i * Pg, j * Pg)
             let resolution = simd::Simd::splat(self.grid.resolution);
@@ -243,18 +274,19 @@ mod tests {
 
         // OPTIMIZE
 
-        let characteristics = crate::optimization::SpecificEnergyCost {
-            up: 1.5,
-            down: 0.1,
-            forward: 1.0,
-        };
+        let characteristics = crate::optimization::SpecificEnergyCost::from_penalties(1.5, 0.1, 1.0);
         let optimizer = crate::optimization::OptimizationSettings {
             specific_energy: characteristics,
+            penalty: crate::optimization::DirectionChangePenalty(200.0),
             start: nalgebra::Point2::new(0.0, 0.0),
             include_start: true,
         };
 
-        let order = optimizer.optimize_points(&path_recorder.points);
+        let x = path_recorder.points.iter().map(|p| p.position.x).collect::<Vec<_>>();
+        let y = path_recorder.points.iter().map(|p| p.position.y).collect::<Vec<_>>();
+        let order = optimizer
+            .optimize_points(crate::generation::PointSlice { x: &x[..], y: &y[..] })
+            .order;
 
         buffer_to_images(&path_recorder, image_output_in_world, order.as_slice());
     }
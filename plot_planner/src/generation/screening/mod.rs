@@ -17,6 +17,8 @@ use std::simd::MaskElement;
 pub struct FMScreeningProcess<R> {
     rng: R,
     inner: iterator::ScreeningIterator<16>,
+    /// Once started, the process cannot be split anymore
+    started: bool,
 }
 
 #[cfg(feature = "hdp")]
@@ -39,9 +41,15 @@ where
         count: usize,
     ) -> super::common::GenerationControlFlow<Self::Error> {
         const L: usize = 16;
+        self.started = true;
 
-        core::iter::Iterator::take(&mut self.inner, count.div_ceil(L)).for_each(
+        let chunks = count.div_ceil(L);
+        let left = self.inner.left();
+        let mut consumed = 0;
+        core::iter::Iterator::take(&mut self.inner, chunks).for_each(
             |(kernel_args, mask)| {
+                consumed += 1;
+
                 #[cfg(feature = "hdp")]
                 let sampled = {
                     use hdp_iter::common::memory::utils::PositionDecimal;
@@ -72,11 +80,67 @@ where
             }
         );
 
-        super::common::GenerationControlFlow::Ongoing { delta: count }
+        if consumed == 0 {
+            super::common::GenerationControlFlow::Finished
+        } else {
+            // The work is counted in grid positions, including the ones skipped outside of the image.
+            // A last, partial chunk is still reported, the next call finishing.
+            super::common::GenerationControlFlow::Ongoing {
+                delta: left - self.inner.left(),
+            }
+        }
     }
 
     fn min_left(&self) -> (usize, Option<usize>) {
-        todo!()
+        let left = self.inner.left();
+        (left, Some(left))
+    }
+}
+
+#[cfg(feature = "hdp")]
+impl<S, R, CmpMask> crate::generation::SplittableProcess<S> for FMScreeningProcess<R>
+where
+    R: rand::RngCore + rand::SeedableRng,
+    S: core::simd::SimdElement
+        + core::default::Default
+        + rand::distr::uniform::SampleUniform
+        + num_traits::Float,
+    simd::Simd<S, 16>: simd::cmp::SimdPartialOrd<Mask = core::simd::Mask<CmpMask, 16>>,
+    CmpMask: simd::MaskElement,
+{
+    fn split(mut self) -> (Self, Option<Self>) {
+        if self.started {
+            return (self, None);
+        }
+
+        match self.inner.split() {
+            (inner, None) => (
+                Self {
+                    rng: self.rng,
+                    inner,
+                    started: false,
+                },
+                None,
+            ),
+            (first, Some(second)) => {
+                // Each half gets its own stream, derived from the parent one,
+                // so that the result only depends on the splits, not on the scheduling.
+                let first_rng = R::from_rng(&mut self.rng);
+                let second_rng = R::from_rng(&mut self.rng);
+                (
+                    Self {
+                        rng: first_rng,
+                        inner: first,
+                        started: false,
+                    },
+                    Some(Self {
+                        rng: second_rng,
+                        inner: second,
+                        started: false,
+                    }),
+                )
+            }
+        }
     }
 }
 
@@ -108,6 +172,46 @@ where
         FMScreeningProcess {
             rng: config.1,
             inner: iterator::ScreeningIterator::new(image, config.0),
+            started: false,
         }
     }
 }
+
+#[cfg(all(test, feature = "hdp"))]
+mod tests {
+    use super::*;
+    use crate::generation::{
+        CancellationToken, GenerationBufferVec, GenerationProcess, Generator, drive,
+    };
+    use rand::SeedableRng;
+
+    #[test]
+    fn last_partial_chunk_is_counted() {
+        let placement = crate::ImageWorldPlacement::new(13, 7, nalgebra::Point2::origin(), 1.0);
+        let grid = ScreeningGrid {
+            resolution: 1.0,
+            ..Default::default()
+        };
+        let rng = rand_xoshiro::Xoroshiro64Star::seed_from_u64(0);
+        let mut process =
+            <FMScreeningGenerator<_> as Generator<f32>>::start(&placement, (grid, rng));
+        let (total, _) = GenerationProcess::<f32>::min_left(&process);
+        assert!(total > 0);
+
+        let data = vec![1.0f32; 13 * 7];
+        let image = crate::generation::hdp_common::memory::Cube::from_image(&data[..]);
+        let mut buffer = GenerationBufferVec::new();
+        let last = drive(
+            &mut process,
+            &image,
+            &mut buffer,
+            // A single, partial chunk
+            1024,
+            &CancellationToken::new(),
+            |_| {},
+        )
+        .unwrap();
+        assert_eq!(last.done, total);
+        assert_eq!(last.fraction(), 1.0);
+    }
+}
@@ -17,6 +17,31 @@ impl GenerationBufferVec<alloc::alloc::Global> {
     }
 }
 
+impl<A> GenerationBufferVec<A>
+where
+    A: core::alloc::Allocator + Clone,
+{
+    /// Appends the primitives of another buffer after ours.
+    /// The point indices referenced by its edges & dots are shifted accordingly.
+    pub fn append<B>(&mut self, other: &GenerationBufferVec<B>)
+    where
+        B: core::alloc::Allocator,
+    {
+        let offset = self.points.len();
+        self.points.extend(&other.points);
+
+        self.edges
+            .from
+            .extend(other.edges.from.iter().map(|i| i + offset));
+        self.edges
+            .to
+            .extend(other.edges.to.iter().map(|i| i + offset));
+        self.dots
+            .index
+            .extend(other.dots.index.iter().map(|i| i + offset));
+    }
+}
+
 impl<A> GenerationBuffer for GenerationBufferVec<A>
 where
     A: core::alloc::Allocator + Clone,
@@ -6,12 +6,14 @@ mod device;
 //pub mod scanning;
 pub mod generation;
 pub mod sketch;
+pub mod palette;
 
 mod common;
 pub use common::ImageWorldPlacement;
 
 pub mod optimization;
 pub mod path;
+pub mod trajectory;
 
 #[derive(Clone, Copy, PartialEq, PartialOrd)]
 pub struct ApplicatorShape {
@@ -8,3 +8,146 @@ pub enum OptimizationControlFlow<E> {
     },
     Error(E),
 }
+
+/// Positions at given times, linearly interpolated in between
+#[derive(Debug, Clone)]
+pub(super) struct Timeline {
+    pub(super) times: Vec<f32>,
+    pub(super) positions: Vec<nalgebra::Point2<f32>>,
+}
+
+impl Timeline {
+    /// Standing at `start` at time zero
+    pub(super) fn new(start: nalgebra::Point2<f32>) -> Self {
+        Self {
+            times: vec![0.0],
+            positions: vec![start],
+        }
+    }
+
+    pub(super) fn end(&self) -> nalgebra::Point2<f32> {
+        *self.positions.last().unwrap()
+    }
+
+    pub(super) fn duration(&self) -> f32 {
+        *self.times.last().unwrap()
+    }
+
+    /// Moves to `position` within `duration`
+    pub(super) fn push(&mut self, position: nalgebra::Point2<f32>, duration: f32) {
+        self.times.push(self.duration() + duration);
+        self.positions.push(position);
+    }
+
+    pub(super) fn at(&self, t: f32) -> nalgebra::Point2<f32> {
+        let next = self.times.partition_point(|time| *time <= t);
+        if next == 0 {
+            return self.positions[0];
+        }
+        if next == self.times.len() {
+            return self.end();
+        }
+        let (t0, t1) = (self.times[next - 1], self.times[next]);
+        let ratio = (t - t0) / (t1 - t0);
+        self.positions[next - 1] + (self.positions[next] - self.positions[next - 1]) * ratio
+    }
+
+    /// The smallest distance to `other` over `[begin, end]`, each timeline being shifted
+    /// by its take-off time (`start`, `other_start`).
+    ///
+    /// Between the breakpoints of both, the offset from one to the other moves linearly,
+    /// so that the closest approach of each interval is that of a segment to the origin.
+    pub(super) fn min_distance(
+        &self,
+        start: f32,
+        other: &Self,
+        other_start: f32,
+        [begin, end]: [f32; 2],
+    ) -> f32 {
+        let mut breakpoints = self
+            .times
+            .iter()
+            .map(|t| t + start)
+            .chain(other.times.iter().map(|t| t + other_start))
+            .filter(|t| begin < *t && *t < end)
+            .chain([begin, end])
+            .collect::<Vec<_>>();
+        breakpoints.sort_unstable_by(f32::total_cmp);
+
+        let offset = |t: f32| self.at(t - start) - other.at(t - other_start);
+        breakpoints
+            .windows(2)
+            .map(|interval| {
+                let (from, to) = (offset(interval[0]), offset(interval[1]));
+                let along = to - from;
+                let ratio = if along.norm_squared() > 0.0 {
+                    (-from.dot(&along) / along.norm_squared()).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+                (from + along * ratio).norm()
+            })
+            .fold(offset(begin).norm(), f32::min)
+    }
+}
+
+/// How a drone flies its tours, shared by [`super::FleetSettings`] & [`super::InkSettings`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlightSettings {
+    /// Speed when moving between the strokes
+    pub transit_speed: f32,
+    /// Speed along the lines
+    pub spray_speed: f32,
+    /// Time to paint a dot
+    pub dot_time: f32,
+    /// Budget of [`super::OptimizationProcess::step`] iterations for each tour
+    pub iterations: usize,
+}
+
+impl FlightSettings {
+    /// Time to paint a primitive going from `from` to `to`
+    pub fn spray_time(
+        &self,
+        item: crate::path::TourItem,
+        from: nalgebra::Point2<f32>,
+        to: nalgebra::Point2<f32>,
+    ) -> f32 {
+        match item {
+            crate::path::TourItem::Dot(_) => self.dot_time,
+            crate::path::TourItem::Edge { .. } => (to - from).norm() / self.spray_speed,
+        }
+    }
+
+    /// Time to move from `from` to `to` without painting
+    pub fn transit_time(&self, from: nalgebra::Point2<f32>, to: nalgebra::Point2<f32>) -> f32 {
+        (to - from).norm() / self.transit_speed
+    }
+
+    /// Steps `process` until it converges or [`Self::iterations`] are spent
+    pub(super) fn optimize(&self, process: &mut super::OptimizationProcess) {
+        const CHUNK: usize = 64;
+
+        let mut spent = 0;
+        while spent < self.iterations {
+            match process.step(CHUNK) {
+                OptimizationControlFlow::Ongoing { .. } => spent += CHUNK,
+                _ => break,
+            }
+        }
+    }
+
+    /// Flying `strokes` (each primitive with its endpoints, in painting order) from `start`:
+    /// the `k`-th primitive starts at `times[2 * k + 1]` and ends at `times[2 * k + 2]`
+    pub(super) fn timeline(
+        &self,
+        start: nalgebra::Point2<f32>,
+        strokes: impl IntoIterator<Item = (crate::path::TourItem, [nalgebra::Point2<f32>; 2])>,
+    ) -> Timeline {
+        let mut timeline = Timeline::new(start);
+        for (item, [from, to]) in strokes {
+            timeline.push(from, self.transit_time(timeline.end(), from));
+            timeline.push(to, self.spray_time(item, from, to));
+        }
+        timeline
+    }
+}
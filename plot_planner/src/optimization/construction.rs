@@ -0,0 +1,151 @@
+use super::gryf_algo::mst;
+use super::{OptimizationSettings, PointsTour};
+
+/// How [`OptimizationSettings::construct_points`] builds a first tour
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TourConstruction {
+    /// See [`OptimizationSettings::optimize_points`]
+    #[default]
+    NearestNeighbour,
+    /// The depth-first walk of the euclidean minimum spanning tree.
+    /// At most twice as long as the shortest closed tour.
+    DoubleTree,
+    /// The walk of the minimum spanning tree and a greedy matching of its odd vertices
+    Christofides,
+}
+
+impl OptimizationSettings {
+    /// Builds a first tour of the points, e.g. to be improved by [`Self::improve_points`].
+    ///
+    /// The tree-based constructions only know about distances: they build a closed tour,
+    /// which is opened where it is the cheapest, and walked in its cheapest direction.
+    pub fn construct_points(
+        &self,
+        points: crate::generation::PointSlice<&[f32], &[f32]>,
+        construction: TourConstruction,
+    ) -> PointsTour {
+        let n = points.len();
+        let position = |i: usize| nalgebra::Point2::new(points.x[i], points.y[i]);
+        if n == 0 || construction == TourConstruction::NearestNeighbour {
+            return self.optimize_points(points);
+        }
+
+        let tree = mst::prim_euclidean(&points).expect("the points are not empty");
+        let root = if self.include_start {
+            (0..n)
+                .min_by(|a, b| {
+                    let a = (position(*a) - self.start).norm();
+                    let b = (position(*b) - self.start).norm();
+                    a.total_cmp(&b)
+                })
+                .unwrap()
+        } else {
+            0
+        };
+        let cycle = match construction {
+            TourConstruction::DoubleTree => mst::double_tree_tour(&tree, root),
+            _ => mst::christofides_tour(&points, &tree, root),
+        };
+
+        // Dropping the leg `cycle[i - 1] -> cycle[i]`, for the cheapest approach from the start
+        let distance = |a: usize, b: usize| (position(a) - position(b)).norm();
+        let saving = |i: usize| {
+            let dropped = distance(cycle[(i + n - 1) % n], cycle[i]);
+            if self.include_start {
+                (position(cycle[i]) - self.start).norm() - dropped
+            } else {
+                -dropped
+            }
+        };
+        let saving_reversed = |i: usize| {
+            let dropped = distance(cycle[(i + 1) % n], cycle[i]);
+            if self.include_start {
+                (position(cycle[i]) - self.start).norm() - dropped
+            } else {
+                -dropped
+            }
+        };
+        let cut = (0..n)
+            .min_by(|a, b| saving(*a).total_cmp(&saving(*b)))
+            .unwrap();
+        let cut_reversed = (0..n)
+            .min_by(|a, b| saving_reversed(*a).total_cmp(&saving_reversed(*b)))
+            .unwrap();
+
+        let mut forward = cycle.clone();
+        forward.rotate_left(cut);
+        let mut backward = cycle;
+        backward.rotate_left(cut_reversed + 1);
+        backward.reverse();
+
+        [forward, backward]
+            .into_iter()
+            .map(|order| {
+                let cost = self.points_cost(position, &order);
+                PointsTour { order, cost }
+            })
+            .min_by(|a, b| a.cost.total_cmp(&b.cost))
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimization::test_utils;
+
+    #[test]
+    fn tree_tours_are_bounded() {
+        let (x, y) = test_utils::random_points(13, 400, [60.0, 60.0]);
+        let points = crate::generation::PointSlice {
+            x: &x[..],
+            y: &y[..],
+        };
+        let distance = |a: usize, b: usize| ((x[a] - x[b]).powi(2) + (y[a] - y[b]).powi(2)).sqrt();
+
+        // Both algorithms agree on the weight of the tree
+        let tree = mst::prim_euclidean(&points).unwrap();
+        let dense = mst::prim(400, distance).unwrap();
+        let sparse = mst::kruskal(
+            400,
+            (0..400).flat_map(|a| (a + 1..400).map(move |b| ([a, b], distance(a, b)))),
+        )
+        .unwrap();
+        assert_eq!(tree.edges().len(), 399);
+        assert!(
+            (tree.weight() - dense.weight()).abs() < 1e-2,
+            "{} {}",
+            tree.weight(),
+            dense.weight()
+        );
+        assert!((tree.weight() - sparse.weight()).abs() < 1e-2);
+
+        // Plain lengths, so that the tree weight bounds the tours
+        let settings = OptimizationSettings {
+            include_start: false,
+            ..test_utils::settings([1.0, 1.0, 1.0], 0.0)
+        };
+        for construction in [TourConstruction::DoubleTree, TourConstruction::Christofides] {
+            let tour = settings.construct_points(points.clone(), construction);
+            let mut order = tour.order.clone();
+            order.sort_unstable();
+            assert_eq!(order, (0..400).collect::<Vec<_>>());
+            assert!(tour.cost >= tree.weight() - 1e-2);
+            assert!(
+                tour.cost <= 2.0 * tree.weight(),
+                "{construction:?}: {}",
+                tour.cost
+            );
+        }
+    }
+
+    #[test]
+    fn disconnected_graph_has_no_tree() {
+        let edges = [([0, 1], 1.0), ([2, 3], 1.0)];
+        assert_eq!(mst::kruskal(4, edges), Err(mst::Error::PreconditionFailed));
+        assert_eq!(
+            mst::prim(3, |a, b| if a + b == 1 { 1.0 } else { f32::INFINITY }),
+            Err(mst::Error::PreconditionFailed)
+        );
+    }
+}
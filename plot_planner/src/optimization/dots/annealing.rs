@@ -0,0 +1,330 @@
+use crate::optimization::gryf_algo::MetricSequenceCostFunction;
+use crate::optimization::local_search::{LocalSearch, Move, neighbour_lists};
+use crate::optimization::{OptimizationControlFlow, OptimizationSettings};
+use crate::path::TourItem;
+use rand::{Rng, SeedableRng};
+
+/// How the temperature of [`DotsAnnealingProcess`] goes down, from the initial one to the final one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CoolingSchedule {
+    /// By a constant factor at each iteration
+    #[default]
+    Geometric,
+    /// By a constant amount at each iteration
+    Linear,
+}
+
+/// See [`DotsAnnealingProcess`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnnealingSettings {
+    pub schedule: CoolingSchedule,
+    /// Estimated from random moves when `None`, so that half of the uphill ones are accepted
+    pub initial_temperature: Option<f32>,
+    /// The final temperature, relative to the initial one
+    pub final_ratio: f32,
+    /// The cooling spans that many iterations, the process converges after them
+    pub iterations: usize,
+    /// Number of nearest points a point may be moved next to
+    pub neighbours: usize,
+    /// Longest run of points moved at once
+    pub max_segment: usize,
+    pub seed: u64,
+}
+
+impl Default for AnnealingSettings {
+    fn default() -> Self {
+        Self {
+            schedule: CoolingSchedule::Geometric,
+            initial_temperature: None,
+            final_ratio: 1e-3,
+            iterations: 200_000,
+            neighbours: 8,
+            max_segment: 3,
+            seed: 0,
+        }
+    }
+}
+
+/// Ordering of dots by simulated annealing: random 2-opt & Or-opt moves towards nearby points,
+/// evaluated exactly under the sequence-dependent cost, and accepted uphill
+/// with a probability decreasing with the temperature.
+///
+/// It starts from the nearest neighbour tour, see [`OptimizationSettings::optimize_points`],
+/// and its tour is always the best one met.
+#[derive(Debug, Clone)]
+pub struct DotsAnnealingProcess<CF = OptimizationSettings> {
+    /// Over the dots, then the start
+    search: LocalSearch<CF>,
+    neighbours: Vec<Vec<usize>>,
+    settings: AnnealingSettings,
+    fixed: usize,
+    rng: rand_xoshiro::Xoshiro256PlusPlus,
+    initial_temperature: f32,
+
+    tour: Vec<TourItem>,
+    best_energy: f32,
+    total_iterations: usize,
+    converged: bool,
+}
+
+impl DotsAnnealingProcess {
+    /// `dots` are indices within `points`
+    pub fn new(
+        settings: OptimizationSettings,
+        points: Vec<nalgebra::Point2<f32>>,
+        dots: Vec<usize>,
+        annealing: AnnealingSettings,
+    ) -> Self {
+        Self::with_metric(settings.clone(), &settings, points, dots, annealing)
+    }
+}
+
+impl<CF> DotsAnnealingProcess<CF>
+where
+    CF: MetricSequenceCostFunction<Weight = f32>,
+{
+    /// Weighing the steps with `metric` (e.g. a [`crate::optimization::KinematicCost`]),
+    /// `settings` only giving the start & the initial tour
+    pub fn with_metric(
+        metric: CF,
+        settings: &OptimizationSettings,
+        points: Vec<nalgebra::Point2<f32>>,
+        dots: Vec<usize>,
+        annealing: AnnealingSettings,
+    ) -> Self {
+        const SAMPLES: usize = 100;
+
+        let x = dots.iter().map(|i| points[*i].x).collect::<Vec<_>>();
+        let y = dots.iter().map(|i| points[*i].y).collect::<Vec<_>>();
+        let initial = settings.optimize_points(crate::generation::PointSlice {
+            x: &x[..],
+            y: &y[..],
+        });
+
+        let positions = dots
+            .iter()
+            .map(|i| points[*i])
+            .chain([settings.start])
+            .collect::<Vec<_>>();
+        let neighbours = neighbour_lists(&positions, annealing.neighbours);
+        let fixed = usize::from(settings.include_start);
+        let mut sequence = Vec::with_capacity(dots.len() + 1);
+        if settings.include_start {
+            sequence.push(dots.len());
+        }
+        sequence.extend_from_slice(&initial.order);
+
+        let search = LocalSearch::new(metric, positions, sequence, fixed);
+        let mut process = Self {
+            best_energy: search.cost(),
+            search,
+            neighbours,
+            rng: rand_xoshiro::Xoshiro256PlusPlus::seed_from_u64(annealing.seed),
+            settings: annealing,
+            fixed,
+            initial_temperature: 0.0,
+            tour: Vec::new(),
+            total_iterations: 0,
+            // Nothing to order, and the search would have no point to start from
+            converged: dots.is_empty(),
+        };
+        process.initial_temperature = match process.settings.initial_temperature {
+            Some(temperature) => temperature,
+            None if process.converged => 0.0,
+            None => {
+                let mut uphill = Vec::with_capacity(SAMPLES);
+                for _ in 0..SAMPLES {
+                    if let Some(m) = process.propose() {
+                        let delta = process.search.delta(&m);
+                        if delta > 0.0 {
+                            uphill.push(delta);
+                        }
+                    }
+                }
+                let mean = uphill.iter().sum::<f32>() / uphill.len().max(1) as f32;
+                mean / core::f32::consts::LN_2
+            }
+        };
+        process.update_tour();
+        process
+    }
+
+    fn update_tour(&mut self) {
+        self.tour.clear();
+        self.tour.extend(
+            self.search.sequence()[self.fixed..]
+                .iter()
+                .map(|dot| TourItem::Dot(*dot)),
+        );
+    }
+
+    /// The temperature at the current iteration
+    fn temperature(&self) -> f32 {
+        let progress = self.total_iterations as f32 / self.settings.iterations.max(1) as f32;
+        let initial = self.initial_temperature;
+        let last = initial * self.settings.final_ratio;
+        match self.settings.schedule {
+            CoolingSchedule::Geometric => initial * self.settings.final_ratio.powf(progress),
+            CoolingSchedule::Linear => initial + (last - initial) * progress,
+        }
+    }
+
+    /// A random move, bringing a point next to one of its nearest ones
+    fn propose(&mut self) -> Option<Move> {
+        let len = self.search.sequence().len();
+        if len < self.fixed.max(1) + 3 {
+            return None;
+        }
+        let i = self.rng.random_range(self.fixed.max(1)..len);
+        let point = self.search.sequence()[i];
+        let candidates = &self.neighbours[point];
+        if candidates.is_empty() {
+            return None;
+        }
+        let j = self
+            .search
+            .position(candidates[self.rng.random_range(0..candidates.len())]);
+        if j == usize::MAX {
+            return None;
+        }
+
+        if self.rng.random_bool(0.5) {
+            // Reversing the run between them, so that they end up adjacent
+            if i < j {
+                self.search.two_opt(i + 1, j)
+            } else {
+                self.search.two_opt(j + 1, i)
+            }
+        } else {
+            let e = (i + self.rng.random_range(0..self.settings.max_segment.max(1))).min(len - 1);
+            let reversed = self.rng.random_bool(0.5);
+            self.search.or_opt(i, e, j, reversed)
+        }
+    }
+
+    /// Each iteration tries a random move
+    pub fn step(&mut self, iterations: usize) -> OptimizationControlFlow<()> {
+        if self.converged {
+            return OptimizationControlFlow::Converged;
+        }
+
+        let energy = self.energy();
+        let mut best = None;
+        for _ in 0..iterations {
+            if self.total_iterations >= self.settings.iterations {
+                self.converged = true;
+                break;
+            }
+            let temperature = self.temperature();
+            self.total_iterations += 1;
+
+            let Some(m) = self.propose() else {
+                continue;
+            };
+            let delta = self.search.delta(&m);
+            let accepted = delta <= 0.0
+                || (temperature > 0.0 && self.rng.random::<f32>() < (-delta / temperature).exp());
+            if accepted {
+                self.search.apply(&m);
+                if self.search.cost() < self.best_energy {
+                    self.best_energy = self.search.cost();
+                    best = Some(self.search.sequence().to_vec());
+                }
+            }
+        }
+
+        if let Some(best) = best {
+            self.tour.clear();
+            self.tour
+                .extend(best[self.fixed..].iter().map(|dot| TourItem::Dot(*dot)));
+        }
+        if self.converged {
+            OptimizationControlFlow::Converged
+        } else {
+            OptimizationControlFlow::Ongoing {
+                delta_energy: self.energy() - energy,
+                total_iterations: self.total_iterations,
+            }
+        }
+    }
+
+    pub fn current_tour(&self) -> &[TourItem] {
+        &self.tour
+    }
+
+    /// Energy of [`Self::current_tour`]
+    pub fn energy(&self) -> f32 {
+        self.best_energy
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimization::test_utils;
+
+    #[test]
+    fn anneals_below_nearest_neighbour() {
+        let points = test_utils::random_positions(21, 120, [30.0, 15.0]);
+        let settings = test_utils::settings([1.5, 0.5, 1.0], 2.0);
+
+        for schedule in [CoolingSchedule::Geometric, CoolingSchedule::Linear] {
+            let mut process = DotsAnnealingProcess::new(
+                settings.clone(),
+                points.clone(),
+                (0..120).collect(),
+                AnnealingSettings {
+                    schedule,
+                    iterations: 60_000,
+                    ..Default::default()
+                },
+            );
+            let initial = process.energy();
+            while let OptimizationControlFlow::Ongoing { delta_energy, .. } = process.step(2000) {
+                assert!(delta_energy <= 0.0);
+            }
+            assert!(
+                process.energy() < initial * 0.95,
+                "{schedule:?}: {initial} -> {}",
+                process.energy()
+            );
+
+            // The energy is the one of the tour
+            let vertices = core::iter::once(settings.start)
+                .chain(process.current_tour().iter().map(|item| match item {
+                    TourItem::Dot(index) => points[*index],
+                    _ => unreachable!(),
+                }))
+                .collect::<Vec<_>>();
+            let energy = (1..vertices.len())
+                .map(|k| {
+                    settings.compute_metric_sequence_weight_scalar(
+                        k.checked_sub(2).map(|p| vertices[p]),
+                        vertices[k - 1],
+                        vertices[k],
+                    )
+                })
+                .sum::<f32>();
+            assert!((energy - process.energy()).abs() < 1e-2 * energy);
+            assert_eq!(process.current_tour().len(), 120);
+        }
+    }
+
+    #[test]
+    fn no_dots_converge_at_once() {
+        for include_start in [true, false] {
+            let settings = OptimizationSettings {
+                include_start,
+                ..test_utils::settings([1.5, 0.5, 1.0], 2.0)
+            };
+            let mut process =
+                DotsAnnealingProcess::new(settings, Vec::new(), Vec::new(), Default::default());
+            assert!(matches!(
+                process.step(10),
+                OptimizationControlFlow::Converged
+            ));
+            assert!(process.current_tour().is_empty());
+            assert_eq!(process.energy(), 0.0);
+        }
+    }
+}
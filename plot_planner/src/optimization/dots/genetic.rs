@@ -0,0 +1,383 @@
+use crate::optimization::gryf_algo::MetricSequenceCostFunction;
+use crate::optimization::local_search::{LocalSearch, LocalSearchSettings, neighbour_lists};
+use crate::optimization::{OptimizationControlFlow, OptimizationSettings};
+use crate::path::TourItem;
+use rand::{Rng, SeedableRng};
+
+/// See [`DotsGeneticProcess`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneticSettings {
+    /// Number of tours kept
+    pub population: usize,
+    /// Each parent is the best of that many random tours
+    pub tournament: usize,
+    /// Probability for an offspring to get a random reversal
+    pub mutation_rate: f32,
+    /// Offspring polished by a local search, if any (a memetic algorithm)
+    pub polish: Option<LocalSearchSettings>,
+    /// The process converges after that many offspring
+    pub max_offspring: usize,
+    /// Or after that many offspring without a new best tour
+    pub stall: usize,
+    pub seed: u64,
+}
+
+impl Default for GeneticSettings {
+    fn default() -> Self {
+        Self {
+            population: 32,
+            tournament: 3,
+            mutation_rate: 0.3,
+            polish: None,
+            max_offspring: 20_000,
+            stall: 5_000,
+            seed: 0,
+        }
+    }
+}
+
+/// Ordering of dots by a steady-state genetic algorithm:
+/// each iteration breeds an offspring of two parents with the edge recombination crossover,
+/// which keeps the steps the parents share, and it replaces the worst tour if it does better.
+///
+/// The tours are evaluated whole, so that any sequence-dependent cost works.
+/// The population grows from perturbations of the nearest neighbour tour,
+/// see [`OptimizationSettings::optimize_points`].
+#[derive(Debug, Clone)]
+pub struct DotsGeneticProcess<CF = OptimizationSettings> {
+    metric: CF,
+    /// Of the dots, then the start
+    positions: Vec<nalgebra::Point2<f32>>,
+    /// The start, and whether the first leg counts
+    optimization: OptimizationSettings,
+    neighbours: Vec<Vec<usize>>,
+    settings: GeneticSettings,
+    rng: rand_xoshiro::Xoshiro256PlusPlus,
+
+    /// Orders of the dots, and their energy
+    population: Vec<(Vec<usize>, f32)>,
+    best: usize,
+    tour: Vec<TourItem>,
+    total_iterations: usize,
+    since_best: usize,
+    converged: bool,
+}
+
+impl DotsGeneticProcess {
+    /// `dots` are indices within `points`
+    pub fn new(
+        settings: OptimizationSettings,
+        points: Vec<nalgebra::Point2<f32>>,
+        dots: Vec<usize>,
+        genetic: GeneticSettings,
+    ) -> Self {
+        Self::with_metric(settings.clone(), &settings, points, dots, genetic)
+    }
+}
+
+impl<CF> DotsGeneticProcess<CF>
+where
+    CF: MetricSequenceCostFunction<Weight = f32> + Clone,
+{
+    /// Weighing the steps with `metric` (e.g. a [`crate::optimization::KinematicCost`]),
+    /// `settings` only giving the start & the initial tour
+    pub fn with_metric(
+        metric: CF,
+        settings: &OptimizationSettings,
+        points: Vec<nalgebra::Point2<f32>>,
+        dots: Vec<usize>,
+        genetic: GeneticSettings,
+    ) -> Self {
+        let x = dots.iter().map(|i| points[*i].x).collect::<Vec<_>>();
+        let y = dots.iter().map(|i| points[*i].y).collect::<Vec<_>>();
+        let initial = settings.optimize_points(crate::generation::PointSlice {
+            x: &x[..],
+            y: &y[..],
+        });
+        let positions = dots
+            .iter()
+            .map(|i| points[*i])
+            .chain([settings.start])
+            .collect::<Vec<_>>();
+        let neighbours = neighbour_lists(&positions, 8);
+
+        let mut process = Self {
+            metric,
+            positions,
+            optimization: settings.clone(),
+            neighbours,
+            rng: rand_xoshiro::Xoshiro256PlusPlus::seed_from_u64(genetic.seed),
+            settings: genetic,
+            population: Vec::new(),
+            best: 0,
+            tour: Vec::new(),
+            total_iterations: 0,
+            since_best: 0,
+            converged: false,
+        };
+
+        // The nearest neighbour tour, and perturbations of it
+        let n = initial.order.len();
+        let energy = process.energy_of(&initial.order);
+        process.population.push((initial.order.clone(), energy));
+        for _ in 1..process.settings.population.max(1) {
+            let mut order = initial.order.clone();
+            for _ in 0..(n / 10).max(1) {
+                process.reverse_randomly(&mut order);
+            }
+            let energy = process.energy_of(&order);
+            process.population.push((order, energy));
+        }
+        process.update_best();
+        process
+    }
+
+    /// Energy of visiting the dots in `order`
+    fn energy_of(&self, order: &[usize]) -> f32 {
+        self.optimization
+            .points_cost_with(&self.metric, |i| self.positions[i], order)
+    }
+
+    fn update_best(&mut self) {
+        let (best, _) = self
+            .population
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| a.1.total_cmp(&b.1))
+            .expect("the population is never empty");
+        self.best = best;
+        self.tour.clear();
+        self.tour.extend(
+            self.population[best]
+                .0
+                .iter()
+                .map(|dot| TourItem::Dot(*dot)),
+        );
+    }
+
+    /// Reverses a random run, ending next to one of the nearest points of its start
+    fn reverse_randomly(&mut self, order: &mut [usize]) {
+        let n = order.len();
+        if n < 3 {
+            return;
+        }
+        let a = self.rng.random_range(0..n);
+        let candidates = &self.neighbours[order[a]];
+        let b = match candidates
+            .get(self.rng.random_range(0..candidates.len().max(1)))
+            .and_then(|point| order.iter().position(|dot| dot == point))
+        {
+            Some(b) => b,
+            None => self.rng.random_range(0..n),
+        };
+        let (a, b) = (a.min(b), a.max(b));
+        order[a..=b].reverse();
+    }
+
+    /// The best of a few random tours
+    fn select(&mut self) -> usize {
+        (0..self.settings.tournament.max(1))
+            .map(|_| self.rng.random_range(0..self.population.len()))
+            .min_by(|a, b| self.population[*a].1.total_cmp(&self.population[*b].1))
+            .unwrap()
+    }
+
+    /// Edge recombination: the next dot is a neighbour of the current one in either parent,
+    /// preferably the one with the fewest neighbours left
+    fn crossover(&mut self, first: &[usize], second: &[usize]) -> Vec<usize> {
+        let n = first.len();
+        let mut adjacency = vec![Vec::with_capacity(4); n];
+        for parent in [first, second] {
+            for pair in parent.windows(2) {
+                for (a, b) in [(pair[0], pair[1]), (pair[1], pair[0])] {
+                    if !adjacency[a].contains(&b) {
+                        adjacency[a].push(b);
+                    }
+                }
+            }
+        }
+
+        let mut visited = vec![false; n];
+        let mut child = Vec::with_capacity(n);
+        // Dead ends restart from the first dot left, in the order of the first parent
+        let mut fallback = 0;
+        let mut current = first[0];
+        loop {
+            visited[current] = true;
+            child.push(current);
+            if child.len() == n {
+                break;
+            }
+            for neighbour in adjacency[current].clone() {
+                adjacency[neighbour].retain(|dot| *dot != current);
+            }
+
+            let fewest = adjacency[current]
+                .iter()
+                .map(|dot| adjacency[*dot].len())
+                .min();
+            current = match fewest {
+                Some(fewest) => {
+                    let ties = adjacency[current]
+                        .iter()
+                        .copied()
+                        .filter(|dot| adjacency[*dot].len() == fewest)
+                        .collect::<Vec<_>>();
+                    ties[self.rng.random_range(0..ties.len())]
+                }
+                None => {
+                    while visited[first[fallback]] {
+                        fallback += 1;
+                    }
+                    first[fallback]
+                }
+            };
+        }
+        child
+    }
+
+    /// Polishes an order with a local search
+    fn polish(&self, order: Vec<usize>, settings: &LocalSearchSettings) -> Vec<usize> {
+        let fixed = usize::from(self.optimization.include_start);
+        let sequence = self
+            .optimization
+            .include_start
+            .then_some(self.positions.len() - 1)
+            .into_iter()
+            .chain(order)
+            .collect();
+        let mut search =
+            LocalSearch::new(self.metric.clone(), self.positions.clone(), sequence, fixed);
+        search.run(&self.neighbours, settings);
+        let mut order = search.into_sequence();
+        order.drain(..fixed);
+        order
+    }
+
+    /// Each iteration breeds an offspring
+    pub fn step(&mut self, iterations: usize) -> OptimizationControlFlow<()> {
+        const EPSILON: f32 = 1e-4;
+
+        if self.converged {
+            return OptimizationControlFlow::Converged;
+        }
+
+        let energy = self.energy();
+        for _ in 0..iterations {
+            if self.total_iterations >= self.settings.max_offspring
+                || self.since_best >= self.settings.stall
+                || self.population[0].0.len() < 2
+            {
+                self.converged = true;
+                break;
+            }
+            self.total_iterations += 1;
+            self.since_best += 1;
+
+            let (a, b) = (self.select(), self.select());
+            let (first, second) = (self.population[a].0.clone(), self.population[b].0.clone());
+            let mut child = self.crossover(&first, &second);
+            if self.rng.random::<f32>() < self.settings.mutation_rate {
+                self.reverse_randomly(&mut child);
+            }
+            if let Some(polish) = self.settings.polish {
+                child = self.polish(child, &polish);
+            }
+            let child_energy = self.energy_of(&child);
+
+            // Replacing the worst, unless a twin is already there
+            let (worst, worst_energy) = self
+                .population
+                .iter()
+                .enumerate()
+                .map(|(k, (_, energy))| (k, *energy))
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .unwrap();
+            let twin = self
+                .population
+                .iter()
+                .any(|(_, energy)| (energy - child_energy).abs() < EPSILON);
+            if child_energy < worst_energy && !twin {
+                self.population[worst] = (child, child_energy);
+                if child_energy < self.energy() {
+                    self.since_best = 0;
+                    self.update_best();
+                }
+            }
+        }
+
+        if self.converged {
+            OptimizationControlFlow::Converged
+        } else {
+            OptimizationControlFlow::Ongoing {
+                delta_energy: self.energy() - energy,
+                total_iterations: self.total_iterations,
+            }
+        }
+    }
+
+    pub fn current_tour(&self) -> &[TourItem] {
+        &self.tour
+    }
+
+    /// Energy of [`Self::current_tour`]
+    pub fn energy(&self) -> f32 {
+        self.population[self.best].1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimization::test_utils;
+
+    #[test]
+    fn evolves_below_nearest_neighbour() {
+        let points = test_utils::random_positions(8, 80, [30.0, 15.0]);
+        let settings = test_utils::settings([1.5, 0.5, 1.0], 2.0);
+
+        // Polished offspring are better, and far fewer are needed
+        for (polish, max_offspring) in [
+            (None, 4000),
+            (
+                Some(LocalSearchSettings {
+                    max_passes: 1,
+                    or_3opt: false,
+                    ..Default::default()
+                }),
+                100,
+            ),
+        ] {
+            let mut process = DotsGeneticProcess::new(
+                settings.clone(),
+                points.clone(),
+                (0..80).collect(),
+                GeneticSettings {
+                    polish,
+                    max_offspring,
+                    ..Default::default()
+                },
+            );
+            let initial = process.energy();
+            while let OptimizationControlFlow::Ongoing { delta_energy, .. } = process.step(50) {
+                assert!(delta_energy <= 0.0);
+            }
+            assert!(
+                process.energy() < initial * 0.97,
+                "{polish:?}: {initial} -> {}",
+                process.energy()
+            );
+
+            let mut dots = process
+                .current_tour()
+                .iter()
+                .map(|item| match item {
+                    TourItem::Dot(index) => *index,
+                    _ => unreachable!(),
+                })
+                .collect::<Vec<_>>();
+            dots.sort_unstable();
+            assert_eq!(dots, (0..80).collect::<Vec<_>>());
+        }
+    }
+}
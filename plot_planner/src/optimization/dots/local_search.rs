@@ -0,0 +1,242 @@
+use crate::optimization::local_search::{LocalSearch, LocalSearchSettings, neighbour_lists};
+use crate::optimization::{OptimizationControlFlow, OptimizationSettings};
+use crate::path::TourItem;
+
+/// See [`DotsLocalSearchProcess`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DotsLocalSearchSettings {
+    pub search: LocalSearchSettings,
+    /// The process converges after this long, counted from its first step
+    pub time_budget: Option<std::time::Duration>,
+    /// The process converges once the tour is within this gap of the lower bound,
+    /// see [`crate::optimization::optimality_gap`]
+    pub target_gap: Option<f32>,
+}
+
+impl Default for DotsLocalSearchSettings {
+    fn default() -> Self {
+        Self {
+            search: LocalSearchSettings {
+                neighbours: 10,
+                ..Default::default()
+            },
+            time_budget: None,
+            target_gap: None,
+        }
+    }
+}
+
+/// Ordering of large sets of dots by local search: 2-opt, Or-opt and Or-3opt moves,
+/// restricted to candidate sets of nearest neighbours, and driven by don't-look bits.
+///
+/// The initial tour is the nearest neighbour one, see [`OptimizationSettings::optimize_points`].
+#[derive(Debug, Clone)]
+pub struct DotsLocalSearchProcess {
+    /// Over the dots, then the start
+    search: LocalSearch<OptimizationSettings>,
+    neighbours: Vec<Vec<usize>>,
+    settings: DotsLocalSearchSettings,
+    fixed: usize,
+
+    tour: Vec<TourItem>,
+    started: Option<std::time::Instant>,
+    total_iterations: usize,
+    converged: bool,
+
+    /// No tour is cheaper, see [`OptimizationSettings::lower_bound`], computed when first needed
+    lower_bound: std::sync::OnceLock<f32>,
+}
+
+impl DotsLocalSearchProcess {
+    /// `dots` are indices within `points`
+    pub fn new(
+        settings: OptimizationSettings,
+        points: Vec<nalgebra::Point2<f32>>,
+        dots: Vec<usize>,
+        search: DotsLocalSearchSettings,
+    ) -> Self {
+        let x = dots.iter().map(|i| points[*i].x).collect::<Vec<_>>();
+        let y = dots.iter().map(|i| points[*i].y).collect::<Vec<_>>();
+        let initial = settings.optimize_points(crate::generation::PointSlice {
+            x: &x[..],
+            y: &y[..],
+        });
+
+        let positions = dots
+            .iter()
+            .map(|i| points[*i])
+            .chain([settings.start])
+            .collect::<Vec<_>>();
+        let neighbours = neighbour_lists(&positions, search.search.neighbours);
+
+        let fixed = usize::from(settings.include_start);
+        let mut sequence = Vec::with_capacity(dots.len() + 1);
+        if settings.include_start {
+            sequence.push(dots.len());
+        }
+        sequence.extend_from_slice(&initial.order);
+
+        let mut process = Self {
+            search: LocalSearch::new(settings, positions, sequence, fixed),
+            neighbours,
+            settings: search,
+            fixed,
+            tour: Vec::new(),
+            started: None,
+            total_iterations: 0,
+            converged: false,
+            lower_bound: std::sync::OnceLock::new(),
+        };
+        process.update_tour();
+        process
+    }
+
+    fn update_tour(&mut self) {
+        self.tour.clear();
+        self.tour.extend(
+            self.search.sequence()[self.fixed..]
+                .iter()
+                .map(|dot| TourItem::Dot(*dot)),
+        );
+    }
+
+    /// Each iteration looks for an improving move around one dot
+    pub fn step(&mut self, iterations: usize) -> OptimizationControlFlow<()> {
+        if self.converged {
+            return OptimizationControlFlow::Converged;
+        }
+        let started = *self.started.get_or_insert_with(std::time::Instant::now);
+        let out_of_time = || {
+            self.settings
+                .time_budget
+                .is_some_and(|budget| started.elapsed() >= budget)
+        };
+
+        let energy = self.energy();
+        let mut improved = false;
+        for i in 0..iterations {
+            // Reading the clock is not free
+            if i % 256 == 0 && (out_of_time() || self.within_target()) {
+                self.converged = true;
+                break;
+            }
+
+            self.total_iterations += 1;
+            match self.search.step(&self.neighbours, &self.settings.search) {
+                Some(true) => improved = true,
+                Some(false) => {}
+                None => {
+                    self.converged = true;
+                    break;
+                }
+            }
+        }
+
+        if self.converged {
+            self.search.update();
+        }
+        if improved {
+            self.update_tour();
+        }
+
+        if self.converged {
+            OptimizationControlFlow::Converged
+        } else {
+            OptimizationControlFlow::Ongoing {
+                delta_energy: self.energy() - energy,
+                total_iterations: self.total_iterations,
+            }
+        }
+    }
+
+    pub fn current_tour(&self) -> &[TourItem] {
+        &self.tour
+    }
+
+    /// No tour of the dots is cheaper
+    pub fn lower_bound(&self) -> f32 {
+        *self.lower_bound.get_or_init(|| {
+            let dots = self.search.positions().len() - 1;
+            self.search.metric().lower_bound(
+                &self.search.positions()[..dots],
+                &(0..dots).collect::<Vec<_>>(),
+                &[],
+            )
+        })
+    }
+
+    pub fn set_target_gap(&mut self, target_gap: Option<f32>) {
+        self.settings.target_gap = target_gap;
+    }
+
+    fn within_target(&self) -> bool {
+        self.settings.target_gap.is_some_and(|target| {
+            crate::optimization::optimality_gap(self.energy(), self.lower_bound()) <= target
+        })
+    }
+
+    /// Energy of [`Self::current_tour`]
+    pub fn energy(&self) -> f32 {
+        self.search.cost()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimization::test_utils;
+
+    fn process(time_budget: Option<std::time::Duration>) -> DotsLocalSearchProcess {
+        let points = test_utils::random_positions(11, 3000, [80.0, 40.0]);
+        let settings = test_utils::settings([1.5, 0.1, 1.0], 2.0);
+        DotsLocalSearchProcess::new(
+            settings,
+            points,
+            (0..3000).rev().collect(),
+            DotsLocalSearchSettings {
+                time_budget,
+                ..Default::default()
+            },
+        )
+    }
+
+    #[test]
+    fn improves_until_local_minimum() {
+        let mut process = process(None);
+        let initial = process.energy();
+        while let OptimizationControlFlow::Ongoing { delta_energy, .. } = process.step(1000) {
+            assert!(delta_energy <= 1e-3, "{delta_energy}");
+        }
+        assert!(
+            process.energy() < initial * 0.95,
+            "{initial} -> {}",
+            process.energy()
+        );
+        // Without a target gap, the bound is left to whoever asks for it
+        assert!(process.lower_bound.get().is_none());
+        assert!(process.lower_bound() <= process.energy());
+
+        let mut dots = process
+            .current_tour()
+            .iter()
+            .map(|item| match item {
+                TourItem::Dot(index) => *index,
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        dots.sort_unstable();
+        assert_eq!(dots, (0..3000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn stops_on_time_budget() {
+        let mut process = process(Some(std::time::Duration::ZERO));
+        let initial = process.energy();
+        assert!(matches!(
+            process.step(1000),
+            OptimizationControlFlow::Converged
+        ));
+        assert_eq!(process.energy(), initial);
+        assert_eq!(process.current_tour().len(), 3000);
+    }
+}
@@ -1,2 +1,28 @@
-#[derive(Debug, Clone)]
-pub struct DotsOptimizationProcess {}
+use crate::optimization::OptimizationSettings;
+use crate::optimization::sequence::{SequenceOptimizationProcess, SequenceOptimizer};
+
+mod local_search;
+pub use local_search::{DotsLocalSearchProcess, DotsLocalSearchSettings};
+
+mod annealing;
+pub use annealing::{AnnealingSettings, CoolingSchedule, DotsAnnealingProcess};
+
+mod genetic;
+pub use genetic::{DotsGeneticProcess, GeneticSettings};
+
+/// Ordering of dots: an asymmetric, sequence-dependent Travelling Salesman Problem.
+pub type DotsOptimizationProcess = SequenceOptimizationProcess<()>;
+
+impl DotsOptimizationProcess {
+    /// `dots` are indices within `points`
+    pub fn new(
+        settings: OptimizationSettings,
+        points: Vec<nalgebra::Point2<f32>>,
+        dots: Vec<usize>,
+    ) -> Self {
+        Self::from_optimizer(
+            SequenceOptimizer::new(settings, points, dots, Vec::new()),
+            (),
+        )
+    }
+}
@@ -0,0 +1,631 @@
+use crate::optimization::common::Timeline;
+use crate::optimization::{
+    FlightSettings, OptimizationProcess, OptimizationSettings, RoutingError, TransitRouter,
+};
+use crate::path::{SectionBuffer, Stroke, TourItem};
+use nalgebra::Point2;
+
+/// How [`FleetSettings::plan`] shares the primitives among the drones
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Partitioning {
+    /// Compact clusters (a k-means, with a capacity per drone)
+    #[default]
+    Clusters,
+    /// Side by side strips, split along x
+    VerticalBands,
+    /// Stacked strips, split along y
+    HorizontalBands,
+}
+
+/// What the partitions of [`FleetSettings::plan`] are balanced on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Balance {
+    /// The time spent painting, see [`FleetSettings::spray_time`]
+    #[default]
+    SprayTime,
+    /// The number of primitives
+    Count,
+}
+
+/// Splitting a job across several drones (multiple TSP)
+#[derive(Debug, Clone)]
+pub struct FleetSettings {
+    /// Where each drone takes off, one per drone
+    pub starts: Vec<Point2<f32>>,
+    pub partitioning: Partitioning,
+    pub balance: Balance,
+    /// Two flying drones are never closer (horizontally) than this
+    pub min_separation: f32,
+    pub flight: FlightSettings,
+}
+
+/// The part of a job flown by one drone
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mission {
+    /// Where the drone takes off
+    pub start: Point2<f32>,
+    /// Primitives of the generation buffer, in painting order,
+    /// see [`OptimizationProcess::current_tour`]
+    pub tour: Vec<TourItem>,
+    /// Take-off time, relative to the first drone
+    pub delay: f32,
+    /// Time from take-off to the end of the last stroke
+    pub duration: f32,
+}
+
+impl Mission {
+    /// The strokes flown by the drone, `buffer` holding the primitives of the job,
+    /// with the waypoints of the detours around the obstacles of `router` in between.
+    /// A primitive without a way around yields an error, see [`SectionBuffer::iter_tour_routed`].
+    pub fn strokes<'a>(
+        &'a self,
+        buffer: &'a SectionBuffer,
+        router: &'a TransitRouter,
+    ) -> impl Iterator<Item = Result<Stroke, RoutingError>> + 'a {
+        buffer.iter_tour_routed(&self.tour, router, self.start)
+    }
+}
+
+/// A primitive of the job, see [`FleetSettings::plan`]
+#[derive(Debug, Clone, Copy)]
+struct Job {
+    item: TourItem,
+    centroid: Point2<f32>,
+    weight: f32,
+}
+
+impl FleetSettings {
+    /// Time to paint a primitive, `edges` being indices within `points`
+    pub fn spray_time(&self, item: TourItem, points: &[Point2<f32>], edges: &[[usize; 2]]) -> f32 {
+        let [from, to] = match item {
+            TourItem::Dot(_) => [Point2::origin(); 2],
+            TourItem::Edge { index, .. } => edges[index].map(|i| points[i]),
+        };
+        self.flight.spray_time(item, from, to)
+    }
+
+    /// Partitions the primitives of `buffer` (one partition per start),
+    /// optimizes a tour of each, and delays the take-offs so that
+    /// the drones keep [`Self::min_separation`].
+    ///
+    /// Each drone uses `settings`, from its own start. The drones are timed
+    /// flying around the obstacles of `router`, as along [`Mission::strokes`],
+    /// and a primitive without a way around fails the plan.
+    pub fn plan<A>(
+        &self,
+        buffer: &crate::generation::GenerationBufferVec<A>,
+        settings: &OptimizationSettings,
+        router: &TransitRouter,
+    ) -> Result<Vec<Mission>, RoutingError>
+    where
+        A: core::alloc::Allocator,
+    {
+        assert!(!self.starts.is_empty(), "at least one drone is expected");
+
+        let points = buffer
+            .points
+            .x
+            .iter()
+            .zip(buffer.points.y.iter())
+            .map(|(x, y)| Point2::new(*x, *y))
+            .collect::<Vec<_>>();
+        let dots = buffer.dots.index.to_vec();
+        let edges = buffer
+            .edges
+            .from
+            .iter()
+            .zip(buffer.edges.to.iter())
+            .map(|(from, to)| [*from, *to])
+            .collect::<Vec<_>>();
+
+        let jobs = (0..dots.len())
+            .map(TourItem::Dot)
+            .chain((0..edges.len()).map(|index| TourItem::Edge {
+                index,
+                reversed: false,
+            }))
+            .map(|item| Job {
+                item,
+                centroid: match item {
+                    TourItem::Dot(index) => points[dots[index]],
+                    TourItem::Edge { index, .. } => {
+                        let [from, to] = edges[index];
+                        nalgebra::center(&points[from], &points[to])
+                    }
+                },
+                weight: match self.balance {
+                    Balance::SprayTime => self.spray_time(item, &points, &edges),
+                    Balance::Count => 1.0,
+                },
+            })
+            .collect::<Vec<_>>();
+
+        let partitions = match self.partitioning {
+            Partitioning::Clusters => self.clusters(&jobs),
+            Partitioning::VerticalBands => self.bands(&jobs, 0),
+            Partitioning::HorizontalBands => self.bands(&jobs, 1),
+        };
+
+        let missions = partitions
+            .into_iter()
+            .zip(self.starts.iter())
+            .map(|(partition, start)| {
+                let mut dots_of = Vec::new();
+                let mut edges_of = Vec::new();
+                for job in partition.iter().map(|k| &jobs[*k]) {
+                    match job.item {
+                        TourItem::Dot(index) => dots_of.push(index),
+                        TourItem::Edge { index, .. } => edges_of.push(index),
+                    }
+                }
+                if dots_of.is_empty() && edges_of.is_empty() {
+                    let mission = Mission {
+                        start: *start,
+                        tour: Vec::new(),
+                        delay: 0.0,
+                        duration: 0.0,
+                    };
+                    return Ok((mission, Timeline::new(*start)));
+                }
+
+                let mut process = OptimizationProcess::from_primitives(
+                    OptimizationSettings {
+                        start: *start,
+                        ..settings.clone()
+                    },
+                    points.clone(),
+                    dots_of.iter().map(|i| dots[*i]).collect(),
+                    edges_of.iter().map(|i| edges[*i]).collect(),
+                    &vec![true; edges_of.len()],
+                );
+                self.flight.optimize(&mut process);
+
+                // Back to the indices of the buffer
+                let tour = process
+                    .current_tour()
+                    .iter()
+                    .map(|item| match *item {
+                        TourItem::Dot(index) => TourItem::Dot(dots_of[index]),
+                        TourItem::Edge { index, reversed } => TourItem::Edge {
+                            index: edges_of[index],
+                            reversed,
+                        },
+                    })
+                    .collect::<Vec<_>>();
+                let mut mission = Mission {
+                    start: *start,
+                    tour,
+                    delay: 0.0,
+                    duration: 0.0,
+                };
+                let timeline = self.timeline(&mission, &points, &dots, &edges, router)?;
+                mission.duration = timeline.duration();
+                Ok((mission, timeline))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let (mut missions, timelines): (Vec<_>, Vec<_>) = missions.into_iter().unzip();
+        self.separate(&mut missions, &timelines);
+        Ok(missions)
+    }
+
+    /// Contiguous strips along `axis`, of about the same weight,
+    /// given to the drones in the order of their starts along it
+    fn bands(&self, jobs: &[Job], axis: usize) -> Vec<Vec<usize>> {
+        let k = self.starts.len();
+        let mut order = (0..jobs.len()).collect::<Vec<_>>();
+        order.sort_unstable_by(|a, b| jobs[*a].centroid[axis].total_cmp(&jobs[*b].centroid[axis]));
+        let total = jobs
+            .iter()
+            .map(|job| job.weight)
+            .sum::<f32>()
+            .max(f32::MIN_POSITIVE);
+
+        let mut bands = vec![Vec::new(); k];
+        let mut cumulated = 0.0;
+        for index in order {
+            // Each job goes to the band its middle falls in
+            let band = ((cumulated + jobs[index].weight / 2.0) / total * k as f32) as usize;
+            bands[band.min(k - 1)].push(index);
+            cumulated += jobs[index].weight;
+        }
+
+        let mut drones = (0..k).collect::<Vec<_>>();
+        drones.sort_by(|a, b| self.starts[*a][axis].total_cmp(&self.starts[*b][axis]));
+        let mut partitions = vec![Vec::new(); k];
+        for (band, drone) in bands.into_iter().zip(drones) {
+            partitions[drone] = band;
+        }
+        partitions
+    }
+
+    /// Clusters of about the same weight, each given to the drone starting closest to it.
+    ///
+    /// Lloyd iterations, where each job is assigned to the closest center that still has capacity,
+    /// starting from bands along the longest side.
+    fn clusters(&self, jobs: &[Job]) -> Vec<Vec<usize>> {
+        const ITERATIONS: usize = 16;
+
+        let k = self.starts.len();
+        if jobs.is_empty() {
+            return vec![Vec::new(); k];
+        }
+        let (min, max) = jobs
+            .iter()
+            .fold((jobs[0].centroid, jobs[0].centroid), |(min, max), job| {
+                (min.inf(&job.centroid), max.sup(&job.centroid))
+            });
+        let extent = max - min;
+        let mut clusters = self.bands(jobs, if extent.x >= extent.y { 0 } else { 1 });
+
+        let total = jobs.iter().map(|job| job.weight).sum::<f32>();
+        let heaviest = jobs.iter().map(|job| job.weight).fold(0.0, f32::max);
+        let capacity = total / k as f32 + heaviest;
+        for _ in 0..ITERATIONS {
+            let centers = clusters
+                .iter()
+                .map(|cluster| weighted_center(jobs, cluster))
+                .collect::<Vec<_>>();
+
+            let mut pairs = Vec::with_capacity(jobs.len() * k);
+            for (index, job) in jobs.iter().enumerate() {
+                for (c, center) in centers.iter().enumerate() {
+                    if let Some(center) = center {
+                        pairs.push(((job.centroid - center).norm_squared(), index, c));
+                    }
+                }
+            }
+            pairs.sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+
+            let mut assigned = vec![false; jobs.len()];
+            let mut loads = vec![0.0; k];
+            let mut next = vec![Vec::new(); k];
+            for (_, index, c) in pairs {
+                if !assigned[index] && loads[c] + jobs[index].weight <= capacity {
+                    assigned[index] = true;
+                    loads[c] += jobs[index].weight;
+                    next[c].push(index);
+                }
+            }
+            // Cannot happen with that capacity, but no job is left out regardless
+            for index in (0..jobs.len()).filter(|index| !assigned[*index]) {
+                let lightest = (0..k)
+                    .min_by(|a, b| loads[*a].total_cmp(&loads[*b]))
+                    .unwrap();
+                loads[lightest] += jobs[index].weight;
+                next[lightest].push(index);
+            }
+
+            if next == clusters {
+                break;
+            }
+            clusters = next;
+        }
+
+        // Greedily, the closest pairs of cluster & start first
+        let mut pairs = Vec::with_capacity(k * k);
+        for (c, cluster) in clusters.iter().enumerate() {
+            let center = weighted_center(jobs, cluster).unwrap_or(self.starts[c]);
+            for (drone, start) in self.starts.iter().enumerate() {
+                pairs.push(((center - start).norm_squared(), c, drone));
+            }
+        }
+        pairs.sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+        let mut partitions = vec![None; k];
+        let mut taken = vec![false; k];
+        for (_, c, drone) in pairs {
+            if !taken[c] && partitions[drone].is_none() {
+                taken[c] = true;
+                partitions[drone] = Some(core::mem::take(&mut clusters[c]));
+            }
+        }
+        partitions
+            .into_iter()
+            .map(Option::unwrap_or_default)
+            .collect()
+    }
+
+    /// Where the drone flying `mission` is over time, from its take-off,
+    /// going around the obstacles of `router` between the strokes
+    fn timeline(
+        &self,
+        mission: &Mission,
+        points: &[Point2<f32>],
+        dots: &[usize],
+        edges: &[[usize; 2]],
+        router: &TransitRouter,
+    ) -> Result<Timeline, RoutingError> {
+        let mut timeline = Timeline::new(mission.start);
+        for item in mission.tour.iter() {
+            let [from, to] = match *item {
+                TourItem::Dot(index) => [points[dots[index]]; 2],
+                TourItem::Edge { index, reversed } => {
+                    let [from, to] = edges[index];
+                    let [from, to] = if reversed { [to, from] } else { [from, to] };
+                    [points[from], points[to]]
+                }
+            };
+            let waypoints = router
+                .detour(timeline.end(), from)
+                .ok_or(RoutingError::Unroutable(*item))?;
+            for waypoint in waypoints.into_iter().chain([from]) {
+                timeline.push(waypoint, self.flight.transit_time(timeline.end(), waypoint));
+            }
+            timeline.push(to, self.flight.spray_time(*item, from, to));
+        }
+        Ok(timeline)
+    }
+
+    /// Delays the missions, in order, until each keeps its distance from the previous ones,
+    /// `timelines` being where each drone is over time.
+    ///
+    /// A drone is only considered from its take-off to the end of its last stroke.
+    /// Past the end of all the previous missions, there is no conflict at all.
+    /// Without a (finite, positive) separation, the missions all take off at once.
+    fn separate(&self, missions: &mut [Mission], timelines: &[Timeline]) {
+        const CANDIDATES: usize = 64;
+
+        if !(self.min_separation > 0.0 && self.min_separation.is_finite()) {
+            return;
+        }
+
+        for k in 1..missions.len() {
+            let (before, after) = missions.split_at_mut(k);
+            let mission = &mut after[0];
+            if mission.tour.is_empty() {
+                continue;
+            }
+            let clear_from = before
+                .iter()
+                .map(|other| other.delay + other.duration)
+                .fold(0.0, f32::max);
+            let increment = clear_from / CANDIDATES as f32;
+
+            let conflicts = |delay: f32| {
+                before.iter().zip(timelines).any(|(other, timeline)| {
+                    let begin = delay.max(other.delay);
+                    let end = (delay + mission.duration).min(other.delay + other.duration);
+                    end >= begin
+                        && timelines[k].min_distance(delay, timeline, other.delay, [begin, end])
+                            < self.min_separation
+                })
+            };
+            let mut delay = 0.0;
+            while delay < clear_from && conflicts(delay) {
+                delay += increment;
+            }
+            mission.delay = delay.min(clear_from);
+        }
+    }
+}
+
+/// The center of the jobs, weighted, if any
+fn weighted_center(jobs: &[Job], indices: &[usize]) -> Option<Point2<f32>> {
+    let total = indices.iter().map(|i| jobs[*i].weight).sum::<f32>();
+    (total > 0.0).then(|| {
+        Point2::from(
+            indices
+                .iter()
+                .map(|i| jobs[*i].centroid.coords * jobs[*i].weight)
+                .sum::<nalgebra::Vector2<f32>>()
+                / total,
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimization::test_utils;
+    use rand::{Rng, SeedableRng};
+
+    fn job() -> crate::generation::GenerationBufferVec<std::alloc::Global> {
+        let mut rng = rand_xoshiro::Xoroshiro64Star::seed_from_u64(42);
+        let mut buffer = crate::generation::GenerationBufferVec::new();
+        for i in 0..240 {
+            buffer.points.x.push(rng.random_range(0.0..60.0));
+            buffer.points.y.push(rng.random_range(0.0..20.0));
+            if i % 3 == 0 {
+                buffer.dots.index.push(i);
+            }
+        }
+        for i in 0..80 {
+            buffer.edges.from.push(3 * i + 1);
+            buffer.edges.to.push(3 * i + 2);
+        }
+        buffer
+    }
+
+    fn fleet(partitioning: Partitioning) -> FleetSettings {
+        FleetSettings {
+            starts: vec![
+                Point2::new(0.0, -5.0),
+                Point2::new(30.0, -5.0),
+                Point2::new(60.0, -5.0),
+            ],
+            partitioning,
+            balance: Balance::SprayTime,
+            min_separation: 4.0,
+            flight: FlightSettings {
+                transit_speed: 5.0,
+                spray_speed: 1.0,
+                dot_time: 0.5,
+                iterations: 256,
+            },
+        }
+    }
+
+    fn settings() -> OptimizationSettings {
+        test_utils::settings([1.0, 1.0, 1.0], 0.1)
+    }
+
+    fn open_sky() -> TransitRouter {
+        TransitRouter::new(&[], 0.0)
+    }
+
+    #[test]
+    fn missions_cover_the_job_once() {
+        let buffer = job();
+        for partitioning in [
+            Partitioning::Clusters,
+            Partitioning::VerticalBands,
+            Partitioning::HorizontalBands,
+        ] {
+            let missions = fleet(partitioning)
+                .plan(&buffer, &settings(), &open_sky())
+                .unwrap();
+            assert_eq!(missions.len(), 3);
+
+            let mut items = missions
+                .iter()
+                .flat_map(|mission| mission.tour.iter())
+                .map(|item| match *item {
+                    TourItem::Edge { index, .. } => TourItem::Edge {
+                        index,
+                        reversed: false,
+                    },
+                    dot => dot,
+                })
+                .collect::<Vec<_>>();
+            items.sort_unstable_by_key(|item| match *item {
+                TourItem::Dot(index) => (0, index),
+                TourItem::Edge { index, .. } => (1, index),
+            });
+            let expected = (0..80)
+                .map(TourItem::Dot)
+                .chain((0..80).map(|index| TourItem::Edge {
+                    index,
+                    reversed: false,
+                }))
+                .collect::<Vec<_>>();
+            assert_eq!(items, expected, "{partitioning:?}");
+        }
+    }
+
+    #[test]
+    fn no_separation_takes_off_at_once() {
+        let buffer = job();
+        for min_separation in [0.0, -1.0, f32::NAN] {
+            let fleet = FleetSettings {
+                min_separation,
+                ..fleet(Partitioning::VerticalBands)
+            };
+            let missions = fleet.plan(&buffer, &settings(), &open_sky()).unwrap();
+            assert!(missions.iter().all(|mission| mission.delay == 0.0));
+        }
+    }
+
+    #[test]
+    fn drones_keep_their_distance() {
+        let buffer = job();
+        let fleet = fleet(Partitioning::VerticalBands);
+        let missions = fleet.plan(&buffer, &settings(), &open_sky()).unwrap();
+
+        let points = (0..buffer.points.x.len())
+            .map(|i| Point2::new(buffer.points.x[i], buffer.points.y[i]))
+            .collect::<Vec<_>>();
+        let edges = (0..buffer.edges.from.len())
+            .map(|i| [buffer.edges.from[i], buffer.edges.to[i]])
+            .collect::<Vec<_>>();
+        let timelines = missions
+            .iter()
+            .map(|mission| {
+                fleet
+                    .timeline(mission, &points, &buffer.dots.index, &edges, &open_sky())
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        // The work is balanced
+        let work = missions
+            .iter()
+            .map(|mission| {
+                mission
+                    .tour
+                    .iter()
+                    .map(|item| fleet.spray_time(*item, &points, &edges))
+                    .sum::<f32>()
+            })
+            .collect::<Vec<_>>();
+        let (least, most) = (
+            work.iter().copied().fold(f32::INFINITY, f32::min),
+            work.iter().copied().fold(0.0, f32::max),
+        );
+        assert!(most < 1.5 * least, "{work:?}");
+
+        // Some take-offs are delayed
+        assert!(missions.iter().any(|mission| mission.delay > 0.0));
+        // The separation is kept at any time, not only at the breakpoints of the timelines
+        let end = missions
+            .iter()
+            .map(|mission| mission.delay + mission.duration)
+            .fold(0.0, f32::max);
+        let mut t = 0.0;
+        while t < end {
+            let flying = missions
+                .iter()
+                .zip(&timelines)
+                .filter(|(mission, _)| {
+                    (mission.delay..=mission.delay + mission.duration).contains(&t)
+                })
+                .map(|(mission, timeline)| timeline.at(t - mission.delay))
+                .collect::<Vec<_>>();
+            for (i, a) in flying.iter().enumerate() {
+                for b in &flying[i + 1..] {
+                    assert!((a - b).norm() >= fleet.min_separation - 1e-3, "at {t}");
+                }
+            }
+            t += 0.05;
+        }
+    }
+
+    #[test]
+    fn drones_are_timed_around_obstacles() {
+        let buffer = job();
+        let fleet = fleet(Partitioning::VerticalBands);
+        // Between the starts & the job, but for a gap at each end
+        let wall = vec![Point2::new(5.0, -2.0), Point2::new(55.0, -2.0)];
+        let router = TransitRouter::new(&[wall], 0.5);
+        let straight = fleet.plan(&buffer, &settings(), &open_sky()).unwrap();
+        let missions = fleet.plan(&buffer, &settings(), &router).unwrap();
+
+        let points = (0..buffer.points.x.len())
+            .map(|i| Point2::new(buffer.points.x[i], buffer.points.y[i]))
+            .collect::<Vec<_>>();
+        let edges = (0..buffer.edges.from.len())
+            .map(|i| [buffer.edges.from[i], buffer.edges.to[i]])
+            .collect::<Vec<_>>();
+        for (mission, straight) in missions.iter().zip(&straight) {
+            let timeline = fleet
+                .timeline(mission, &points, &buffer.dots.index, &edges, &router)
+                .unwrap();
+            assert_eq!(timeline.duration(), mission.duration);
+            for leg in timeline.positions.windows(2) {
+                assert!(router.is_clear(leg[0], leg[1]));
+            }
+            // The middle drone flies to the end of the wall and back
+            if mission.start.x == 30.0 {
+                assert!(mission.duration > straight.duration + 4.0);
+            }
+        }
+
+        // A dot boxed in by the obstacles, away from the others, cannot be reached
+        let boxed = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]
+            .map(|(x, y)| Point2::new(30.0 + x, 40.0 + y))
+            .to_vec();
+        let walls = boxed
+            .iter()
+            .zip(boxed.iter().cycle().skip(1))
+            .map(|(a, b)| vec![*a, *b])
+            .collect::<Vec<_>>();
+        let mut buffer = buffer;
+        buffer.points.x.push(30.0);
+        buffer.points.y.push(40.0);
+        buffer.dots.index.push(240);
+        let router = TransitRouter::new(&walls, 0.5);
+        assert_eq!(
+            fleet.plan(&buffer, &settings(), &router),
+            Err(RoutingError::Unroutable(TourItem::Dot(80)))
+        );
+    }
+}
@@ -182,6 +182,23 @@ pub trait MetricSequenceCostFunction {
     )
 ;
 
+    /// The weight of a single step, without the SIMD ceremony.
+    /// By default, splats the points on the narrowest supported width.
+    #[inline(always)]
+    fn compute_metric_sequence_weight_scalar(
+        &self,
+        prev: Option<nalgebra::Point2<f32>>,
+        from: nalgebra::Point2<f32>,
+        to: nalgebra::Point2<f32>,
+    ) -> Self::Weight {
+        let splat = |p: nalgebra::Point2<f32>| Point::<2> {
+            x: simd::Simd::splat(p.x),
+            y: simd::Simd::splat(p.y),
+        };
+        let (weight, _) =
+            self.compute_metric_sequence_weight::<2>(prev.map(splat), splat(from), splat(to));
+        weight.to_array()[0]
+    }
 }
 
 pub struct MetricSequenceCostFunctionAdapter<'a, CF> {
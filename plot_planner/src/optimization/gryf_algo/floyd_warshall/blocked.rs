@@ -0,0 +1,148 @@
+use super::RoutingTable;
+
+/// Floyd–Warshall over tiles of `block_size` vertices, relaxing the shortest paths of the table
+/// (e.g. filled with [`RoutingTable::add_edge`]) in place.
+///
+/// Each block of pivots is first run through its own rows, which then relax all the other
+/// rows: those rows are independent, see [`par_floyd_warshall_blocked`].
+pub fn floyd_warshall_blocked<W>(table: &mut RoutingTable<W>, block_size: usize)
+where
+    W: Copy + PartialOrd + core::ops::Add<Output = W>,
+{
+    let n = table.vertex_count();
+    let rows = block_size.max(1) * n;
+    let (distances, hops) = slices(table);
+    for pivots in pivot_blocks(n, block_size) {
+        let (pivot_distances, pivot_hops) = relax_pivot_block(distances, hops, n, pivots.clone());
+        for (block, (distances, hops)) in distances
+            .chunks_mut(rows)
+            .zip(hops.chunks_mut(rows))
+            .enumerate()
+        {
+            if block * block_size.max(1) != pivots.start {
+                for k in pivots.clone() {
+                    let offset = (k - pivots.start) * n;
+                    relax(
+                        distances,
+                        hops,
+                        n,
+                        k,
+                        &pivot_distances[offset..offset + n],
+                        &pivot_hops[offset..offset + n],
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// [`floyd_warshall_blocked`], relaxing the blocks of rows in parallel
+#[cfg(feature = "rayon")]
+pub fn par_floyd_warshall_blocked<W>(table: &mut RoutingTable<W>, block_size: usize)
+where
+    W: Copy + PartialOrd + core::ops::Add<Output = W> + Send + Sync,
+{
+    use rayon::iter::{IndexedParallelIterator, ParallelIterator};
+    use rayon::slice::ParallelSliceMut;
+
+    let n = table.vertex_count();
+    let rows = block_size.max(1) * n;
+    let (distances, hops) = slices(table);
+    for pivots in pivot_blocks(n, block_size) {
+        let (pivot_distances, pivot_hops) = relax_pivot_block(distances, hops, n, pivots.clone());
+        distances
+            .par_chunks_mut(rows)
+            .zip(hops.par_chunks_mut(rows))
+            .enumerate()
+            .filter(|(block, _)| block * block_size.max(1) != pivots.start)
+            .for_each(|(_, (distances, hops))| {
+                for k in pivots.clone() {
+                    let offset = (k - pivots.start) * n;
+                    relax(
+                        distances,
+                        hops,
+                        n,
+                        k,
+                        &pivot_distances[offset..offset + n],
+                        &pivot_hops[offset..offset + n],
+                    );
+                }
+            });
+    }
+}
+
+fn slices<W>(table: &mut RoutingTable<W>) -> (&mut [W], &mut [Option<usize>]) {
+    (
+        table
+            .distances
+            .as_slice_mut()
+            .expect("the table is in standard layout"),
+        table
+            .next_hop
+            .as_slice_mut()
+            .expect("the table is in standard layout"),
+    )
+}
+
+fn pivot_blocks(n: usize, block_size: usize) -> impl Iterator<Item = core::ops::Range<usize>> {
+    let block_size = block_size.max(1);
+    (0..n)
+        .step_by(block_size)
+        .map(move |start| start..(start + block_size).min(n))
+}
+
+/// Runs the pivots through their own rows, and returns these rows
+fn relax_pivot_block<W>(
+    distances: &mut [W],
+    hops: &mut [Option<usize>],
+    n: usize,
+    pivots: core::ops::Range<usize>,
+) -> (Vec<W>, Vec<Option<usize>>)
+where
+    W: Copy + PartialOrd + core::ops::Add<Output = W>,
+{
+    let rows = pivots.start * n..pivots.end * n;
+    for k in pivots {
+        let pivot_distances = distances[k * n..(k + 1) * n].to_vec();
+        let pivot_hops = hops[k * n..(k + 1) * n].to_vec();
+        relax(
+            &mut distances[rows.clone()],
+            &mut hops[rows.clone()],
+            n,
+            k,
+            &pivot_distances,
+            &pivot_hops,
+        );
+    }
+    (distances[rows.clone()].to_vec(), hops[rows].to_vec())
+}
+
+/// Relaxes whole rows through the pivot `k`, whose row is given
+fn relax<W>(
+    distances: &mut [W],
+    hops: &mut [Option<usize>],
+    n: usize,
+    k: usize,
+    pivot_distances: &[W],
+    pivot_hops: &[Option<usize>],
+) where
+    W: Copy + PartialOrd + core::ops::Add<Output = W>,
+{
+    for (row_distances, row_hops) in distances.chunks_exact_mut(n).zip(hops.chunks_exact_mut(n)) {
+        // Nothing goes through k from this row
+        let Some(ik_hop) = row_hops[k] else {
+            continue;
+        };
+        let ik = row_distances[k];
+        for j in 0..n {
+            if pivot_hops[j].is_none() {
+                continue;
+            }
+            let sum = ik + pivot_distances[j];
+            if row_hops[j].is_none() || sum < row_distances[j] {
+                row_distances[j] = sum;
+                row_hops[j] = Some(ik_hop);
+            }
+        }
+    }
+}
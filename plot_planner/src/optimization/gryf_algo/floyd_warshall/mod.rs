@@ -4,15 +4,75 @@ use ndarray::Array2;
 use core::simd;
 use core::simd::Select;
 
+mod blocked;
+pub use blocked::floyd_warshall_blocked;
+#[cfg(feature = "rayon")]
+pub use blocked::par_floyd_warshall_blocked;
+
 /// Used for APSP (All Pairs Shortest Path)
-pub struct RoutingTable {
-    pub distances: Array2<f64>,
+#[derive(Debug, Clone)]
+pub struct RoutingTable<W = f64> {
+    pub distances: Array2<W>,
     // next_hop[i, j] stores the next vertex to visit to get from i to j
     pub next_hop: Array2<Option<usize>>,
 }
 
+impl<W> RoutingTable<W>
+where
+    W: Copy + PartialOrd + num_traits::Zero,
+{
+    /// `vertex_count` vertices, only connected to themselves
+    pub fn new(vertex_count: usize) -> Self {
+        let n = vertex_count;
+        Self {
+            distances: Array2::from_elem((n, n), W::zero()),
+            next_hop: Array2::from_shape_fn((n, n), |(i, j)| (i == j).then_some(j)),
+        }
+    }
+
+    /// Connects `from` to `to`, unless they are already connected by a lighter edge
+    pub fn add_edge(&mut self, from: usize, to: usize, weight: W) {
+        if self.next_hop[[from, to]].is_none() || weight < self.distances[[from, to]] {
+            self.distances[[from, to]] = weight;
+            self.next_hop[[from, to]] = Some(to);
+        }
+    }
+}
+
+impl<W: Copy> RoutingTable<W> {
+    pub fn vertex_count(&self) -> usize {
+        self.next_hop.nrows()
+    }
+
+    /// The weight of the shortest path, if `to` can be reached from `from`
+    pub fn distance(&self, from: usize, to: usize) -> Option<W> {
+        self.next_hop[[from, to]].map(|_| self.distances[[from, to]])
+    }
+
+    /// The vertices along the shortest path, both ends included,
+    /// if `to` can be reached from `from`.
+    pub fn path(&self, from: usize, to: usize) -> Option<Vec<usize>> {
+        let mut path = vec![from];
+        let mut current = from;
+        while current != to {
+            current = self.next_hop[[current, to]]?;
+            path.push(current);
+            // Only negative cycles make paths longer than the vertex count
+            if path.len() > self.vertex_count() {
+                return None;
+            }
+        }
+        Some(path)
+    }
+}
+
+/// Marks the missing next hops while relaxing, see [`RoutingTable::next_hop`]
+const NO_HOP: usize = usize::MAX;
+
 // https://moorejs.github.io/APSP-in-parallel/
-pub fn floyd_warshall_simd<W, const L: usize, DM, G>(matrix: &mut DM, graph: &G)
+/// Shortest paths between all the vertices of the graph, the matrix holding the distances.
+/// Pairs which are not connected are left as they are in the matrix.
+pub fn floyd_warshall_simd<W, const L: usize, DM, G>(matrix: &mut DM, graph: &G) -> RoutingTable<W>
 where
     W: core::simd::SimdElement
         + Copy
@@ -26,11 +86,23 @@ where
     core::simd::Simd<W, L>: core::ops::Add<Output = core::simd::Simd<W, L>>
         + core::simd::cmp::SimdPartialOrd<Mask = core::simd::Mask<W::Mask, L>>,
     core::simd::Simd<usize, L>: core::ops::Add<Output = core::simd::Simd<usize, L>>,
-    G: Graph<Weight = W>,
+    G: Graph<Weight = W, VertexIndex = usize>,
 {
-    // First we insert the weight of the edges that are directly connected
+    use core::simd::cmp::SimdPartialEq;
+
+    let n = graph.vertex_count();
+    let mut next_hop = vec![NO_HOP; n * n];
+
+    // First we insert the weight of the edges that are directly connected, the lightest if several
     for (edge, weight, mask) in graph.edges::<1>() {
-        matrix.set(edge, weight, mask);
+        if !mask.test(0) {
+            continue;
+        }
+        let hop = &mut next_hop[edge.from[0] * n + edge.to[0]];
+        if *hop == NO_HOP || weight[0] < matrix.get(edge).0[0] {
+            matrix.set(edge, weight, mask);
+            *hop = edge.to[0];
+        }
     }
 
     // Then, for all the vertices, we set the edges from themselves to themselves to 0
@@ -41,13 +113,22 @@ where
         };
         let weights = core::simd::Simd::splat(W::zero());
         matrix.set(edge, weights, mask);
+        if mask.test(0) {
+            next_hop[vertices[0] * n + vertices[0]] = vertices[0];
+        }
     }
 
     // The k & i iterations are scalar
     for (k, _) in graph.vertices::<1>() {
-        let k_vec = core::simd::Simd::splat(k[0]);;
+        let k_vec = core::simd::Simd::splat(k[0]);
 
         for (i, _) in graph.vertices::<1>() {
+            // Nothing goes through k from i
+            let ik_hop = next_hop[i[0] * n + k[0]];
+            if ik_hop == NO_HOP {
+                continue;
+            }
+
             let i_vec = core::simd::Simd::splat(i[0]);
             let (ik_weight, _) = matrix.get::<1>(Edge::<_, 1> { from: i, to: k });
             let ik_weights = core::simd::Simd::splat(ik_weight[0]);
@@ -65,16 +146,34 @@ where
                     from: k_vec,
                     to: j_vec,
                 });
+                let kj_offsets = core::simd::Simd::splat(k[0] * n) + j_vec;
+                let kj_hops = core::simd::Simd::gather_select(
+                    &next_hop,
+                    j_mask,
+                    kj_offsets,
+                    core::simd::Simd::splat(NO_HOP),
+                );
+                let ij_offsets = core::simd::Simd::splat(i[0] * n) + j_vec;
+                let ij_hops = core::simd::Simd::gather_select(
+                    &next_hop,
+                    j_mask,
+                    ij_offsets,
+                    core::simd::Simd::splat(NO_HOP),
+                );
 
                 // Relax: d[i][j] = min(d[i][j], d[i][k] + d[k][j])
                 let sum = ik_weights + kj_weights;
 
-                // Use the mask to ensure we only update valid vertices
+                // Use the mask to ensure we only update valid vertices,
+                // reached from k, and either unreached from i or by a heavier path
                 let combined_mask = ij_mask & kj_mask & j_mask;
                 let lt_mask = core::simd::cmp::SimdPartialOrd::simd_lt(sum, ij_weights);
                 // Convert mask to isize for select method
                 let lt_mask_isize: simd::Mask<isize, L> = lt_mask.cast();
-                let new_min = lt_mask_isize.select(sum, ij_weights);
+                let unreached = ij_hops.simd_eq(core::simd::Simd::splat(NO_HOP));
+                let reached_from_k = kj_hops.simd_ne(core::simd::Simd::splat(NO_HOP));
+                let improved = (lt_mask_isize | unreached) & reached_from_k & combined_mask;
+                let new_min = improved.select(sum, ij_weights);
 
                 // Update the matrix
                 matrix.set(
@@ -85,6 +184,170 @@ where
                     new_min,
                     combined_mask,
                 );
+                core::simd::Simd::splat(ik_hop).scatter_select(&mut next_hop, improved, ij_offsets);
+            }
+        }
+    }
+
+    RoutingTable {
+        distances: Array2::from_shape_fn((n, n), |(i, j)| {
+            let (weight, _) = matrix.get::<1>(Edge {
+                from: core::simd::Simd::splat(i),
+                to: core::simd::Simd::splat(j),
+            });
+            weight[0]
+        }),
+        next_hop: Array2::from_shape_fn((n, n), |(i, j)| {
+            Some(next_hop[i * n + j]).filter(|hop| *hop != NO_HOP)
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::AdjacencyMatrix;
+    use super::*;
+    use rand::{Rng, SeedableRng};
+
+    /// A directed graph, as a list of weighted edges
+    struct EdgeList {
+        vertex_count: usize,
+        edges: Vec<(usize, usize, f32)>,
+    }
+
+    impl Graph for EdgeList {
+        type VertexIndex = usize;
+        type Weight = f32;
+
+        fn edges<const L: usize>(
+            &self,
+        ) -> impl Iterator<
+            Item = (
+                Edge<usize, L>,
+                core::simd::Simd<f32, L>,
+                core::simd::Mask<isize, L>,
+            ),
+        > {
+            self.edges.chunks(L).map(|chunk| {
+                let (mut from, mut to, mut weights, mut mask) =
+                    ([0; L], [0; L], [0.0; L], [false; L]);
+                for (lane, (a, b, weight)) in chunk.iter().enumerate() {
+                    (from[lane], to[lane], weights[lane], mask[lane]) = (*a, *b, *weight, true);
+                }
+                (
+                    Edge {
+                        from: core::simd::Simd::from_array(from),
+                        to: core::simd::Simd::from_array(to),
+                    },
+                    core::simd::Simd::from_array(weights),
+                    core::simd::Mask::from_array(mask),
+                )
+            })
+        }
+
+        fn vertices<const L: usize>(
+            &self,
+        ) -> impl Iterator<Item = (core::simd::Simd<usize, L>, core::simd::Mask<isize, L>)>
+        {
+            use core::simd::cmp::SimdPartialOrd;
+            let n = self.vertex_count;
+            (0..n)
+                .step_by(L)
+                .map(|k| core::simd::Simd::splat(k) + aosoa::iota())
+                .map(move |offsets| (offsets, offsets.simd_lt(core::simd::Simd::splat(n))))
+        }
+
+        fn vertex_count(&self) -> usize {
+            self.vertex_count
+        }
+
+        fn edge_count(&self) -> usize {
+            self.edges.len()
+        }
+    }
+
+    #[test]
+    fn variants_agree_on_paths() {
+        let n = 45;
+        let mut rng = rand_xoshiro::Xoroshiro64Star::seed_from_u64(3);
+        let graph = EdgeList {
+            vertex_count: n,
+            edges: (0..n * 4)
+                .map(|_| {
+                    let a = rng.random_range(0..n);
+                    let b = rng.random_range(0..n);
+                    (a, b, rng.random_range(1.0..10.0f32))
+                })
+                .filter(|(a, b, _)| a != b)
+                .collect(),
+        };
+        let weight = |a: usize, b: usize| {
+            graph
+                .edges
+                .iter()
+                .filter(|(from, to, _)| (*from, *to) == (a, b))
+                .map(|(_, _, weight)| *weight)
+                .reduce(f32::min)
+        };
+
+        // The plain algorithm, for reference
+        let mut reference = vec![None; n * n];
+        for a in 0..n {
+            for b in 0..n {
+                reference[a * n + b] = if a == b { Some(0.0) } else { weight(a, b) };
+            }
+        }
+        for k in 0..n {
+            for i in 0..n {
+                for j in 0..n {
+                    if let (Some(ik), Some(kj)) = (reference[i * n + k], reference[k * n + j])
+                        && reference[i * n + j].is_none_or(|ij| ik + kj < ij)
+                    {
+                        reference[i * n + j] = Some(ik + kj);
+                    }
+                }
+            }
+        }
+
+        let mut blocked = RoutingTable::new(n);
+        for (a, b, weight) in graph.edges.iter() {
+            blocked.add_edge(*a, *b, *weight);
+        }
+        #[cfg(feature = "rayon")]
+        let mut parallel = blocked.clone();
+        floyd_warshall_blocked(&mut blocked, 8);
+        #[cfg(feature = "rayon")]
+        par_floyd_warshall_blocked(&mut parallel, 8);
+
+        let mut matrix = AdjacencyMatrix {
+            weights: vec![f32::INFINITY; n * n],
+            size: n,
+        };
+        let simd = floyd_warshall_simd::<f32, 4, _, _>(&mut matrix, &graph);
+
+        let mut tables = vec![&blocked, &simd];
+        #[cfg(feature = "rayon")]
+        tables.push(&parallel);
+        for table in tables {
+            for i in 0..n {
+                for j in 0..n {
+                    let expected = reference[i * n + j];
+                    let distance = table.distance(i, j);
+                    assert_eq!(distance.is_some(), expected.is_some(), "{i} -> {j}");
+                    let (Some(distance), Some(expected)) = (distance, expected) else {
+                        continue;
+                    };
+                    assert!((distance - expected).abs() < 1e-3);
+
+                    // The path follows edges, and weighs the distance
+                    let path = table.path(i, j).unwrap();
+                    assert_eq!((path[0], *path.last().unwrap()), (i, j));
+                    let length = path
+                        .windows(2)
+                        .map(|pair| weight(pair[0], pair[1]).unwrap())
+                        .sum::<f32>();
+                    assert!((length - expected).abs() < 1e-3, "{path:?}");
+                }
             }
         }
     }
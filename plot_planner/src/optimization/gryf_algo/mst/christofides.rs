@@ -0,0 +1,142 @@
+use super::MinimumSpanningTree;
+use crate::optimization::UniformGrid;
+
+/// The vertices in the order of a depth-first walk of the tree from `root`,
+/// i.e. the walk around the doubled tree, with the vertices already visited skipped.
+///
+/// With a metric weight, the closed tour is at most twice as heavy as the tree.
+pub fn double_tree_tour(tree: &MinimumSpanningTree, root: usize) -> Vec<usize> {
+    let adjacency = tree.adjacency();
+    let mut visited = vec![false; tree.vertex_count()];
+    let mut tour = Vec::with_capacity(tree.vertex_count());
+    let mut stack = vec![root];
+    while let Some(v) = stack.pop() {
+        if visited[v] {
+            continue;
+        }
+        visited[v] = true;
+        tour.push(v);
+        // Reversed, so that the neighbours are walked in order
+        stack.extend(adjacency[v].iter().rev().filter(|w| !visited[**w]));
+    }
+    tour
+}
+
+/// A Christofides-like tour of the points, from `root`:
+/// the odd vertices of the tree are matched greedily (rather than perfectly) by distance,
+/// and the Eulerian circuit of the tree and the matching is walked, skipping the vertices
+/// already visited.
+///
+/// `tree` is expected to span the points, weighted by the euclidean distance, e.g. from
+/// [`super::prim_euclidean`].
+pub fn christofides_tour<X, Y>(
+    points: &crate::generation::PointSlice<X, Y>,
+    tree: &MinimumSpanningTree,
+    root: usize,
+) -> Vec<usize>
+where
+    X: core::ops::Deref<Target = [f32]>,
+    Y: core::ops::Deref<Target = [f32]>,
+{
+    let n = tree.vertex_count();
+    let mut edges = tree.edges().to_vec();
+    edges.extend(greedy_matching(points, tree));
+
+    // Hierholzer's algorithm, on the multigraph of the tree and the matching
+    let mut incident = vec![Vec::new(); n];
+    for (e, [a, b]) in edges.iter().enumerate() {
+        incident[*a].push(e);
+        incident[*b].push(e);
+    }
+    let mut used = vec![false; edges.len()];
+    let mut next = vec![0; n];
+    let mut stack = vec![root];
+    let mut visited = vec![false; n];
+    let mut tour = Vec::with_capacity(n);
+    while let Some(v) = stack.last().copied() {
+        // The next unused edge of `v`
+        while next[v] < incident[v].len() && used[incident[v][next[v]]] {
+            next[v] += 1;
+        }
+        match incident[v].get(next[v]) {
+            Some(e) => {
+                used[*e] = true;
+                let [a, b] = edges[*e];
+                stack.push(if a == v { b } else { a });
+            }
+            None => {
+                stack.pop();
+                if !visited[v] {
+                    visited[v] = true;
+                    tour.push(v);
+                }
+            }
+        }
+    }
+
+    // The circuit is built backwards, from `root` back to it
+    tour.reverse();
+    let root_index = tour.iter().position(|v| *v == root).unwrap_or(0);
+    tour.rotate_left(root_index);
+    tour
+}
+
+/// Pairs the vertices of odd degree of the tree, the closest ones first
+fn greedy_matching<X, Y>(
+    points: &crate::generation::PointSlice<X, Y>,
+    tree: &MinimumSpanningTree,
+) -> Vec<[usize; 2]>
+where
+    X: core::ops::Deref<Target = [f32]>,
+    Y: core::ops::Deref<Target = [f32]>,
+{
+    const CANDIDATES: usize = 8;
+
+    let mut degree = vec![0usize; tree.vertex_count()];
+    for [a, b] in tree.edges().iter() {
+        degree[*a] += 1;
+        degree[*b] += 1;
+    }
+    let odd = (0..tree.vertex_count())
+        .filter(|v| degree[*v] % 2 == 1)
+        .collect::<Vec<_>>();
+    let x = odd.iter().map(|v| points.x[*v]).collect::<Vec<_>>();
+    let y = odd.iter().map(|v| points.y[*v]).collect::<Vec<_>>();
+    let mut grid = UniformGrid::new(&crate::generation::PointSlice {
+        x: &x[..],
+        y: &y[..],
+    });
+
+    // The closest pairs among a few candidates each
+    let mut pairs = Vec::with_capacity(odd.len() * CANDIDATES);
+    for i in 0..odd.len() {
+        for (j, distance) in grid.k_nearest(grid.position(i), CANDIDATES + 1) {
+            if i < j {
+                pairs.push((distance, i, j));
+            }
+        }
+    }
+    pairs.sort_by(|(a, ..), (b, ..)| a.total_cmp(b));
+
+    let mut matching = Vec::with_capacity(odd.len() / 2);
+    for (_, i, j) in pairs {
+        if grid.contains(i) && grid.contains(j) {
+            grid.remove(i);
+            grid.remove(j);
+            matching.push([odd[i], odd[j]]);
+        }
+    }
+
+    // The vertices whose candidates were all taken are matched to the nearest one left
+    for i in 0..odd.len() {
+        if !grid.contains(i) {
+            continue;
+        }
+        grid.remove(i);
+        if let Some((j, _)) = grid.k_nearest(grid.position(i), 1).first() {
+            grid.remove(*j);
+            matching.push([odd[i], odd[*j]]);
+        }
+    }
+    matching
+}
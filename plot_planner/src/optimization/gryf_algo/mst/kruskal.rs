@@ -1,13 +1,39 @@
-pub enum Error {
-    /// The graph is empty or not **connected**, preventing the formation of an MST
-    /// that spans all vertices.
-    //#[error("Graph precondition failed: graph must be non-empty and connected.")]
-    PreconditionFailed,
-}
+use super::{Error, MinimumSpanningTree};
+use crate::optimization::gryf_algo::utils::Dsu;
+
+/// Kruskal's algorithm over a sparse graph, e.g. the k nearest neighbours of each point.
+///
+/// Fails if the edges do not connect the `vertex_count` vertices.
+pub fn kruskal(
+    vertex_count: usize,
+    edges: impl IntoIterator<Item = ([usize; 2], f32)>,
+) -> Result<MinimumSpanningTree, Error> {
+    if vertex_count == 0 {
+        return Err(Error::PreconditionFailed);
+    }
+
+    let mut edges = edges.into_iter().collect::<Vec<_>>();
+    edges.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+
+    let mut dsu = Dsu::new(0..vertex_count);
+    let mut tree = MinimumSpanningTree {
+        vertex_count,
+        edges: Vec::with_capacity(vertex_count - 1),
+        weight: 0.0,
+    };
+    for ([a, b], weight) in edges {
+        if dsu.union(&a, &b) {
+            tree.edges.push([a, b]);
+            tree.weight += weight;
+            if tree.edges.len() + 1 == vertex_count {
+                break;
+            }
+        }
+    }
 
-pub struct MinimumSpanningTree<G>
-where
-    G: gryf::core::GraphBase,
-{
-    edges: std::collections::HashSet<G::EdgeId>,
+    if tree.edges.len() + 1 == vertex_count {
+        Ok(tree)
+    } else {
+        Err(Error::PreconditionFailed)
+    }
 }
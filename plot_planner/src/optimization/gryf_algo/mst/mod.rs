@@ -1 +1,52 @@
+// Minimum spanning trees over point sets, and the tours built from them.
+// The weights are symmetric, e.g. the euclidean distance: the asymmetric costs are bounded by it
+// (see `SpecificEnergyCost::min_spec_e`), so the MST weight gives a cheap lower bound of a tour.
+
+mod christofides;
 mod kruskal;
+mod prim;
+
+pub use christofides::{christofides_tour, double_tree_tour};
+pub use kruskal::kruskal;
+pub use prim::{prim, prim_euclidean};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The graph is empty or not **connected**, preventing the formation of an MST
+    /// that spans all vertices.
+    //#[error("Graph precondition failed: graph must be non-empty and connected.")]
+    PreconditionFailed,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MinimumSpanningTree {
+    vertex_count: usize,
+    edges: Vec<[usize; 2]>,
+    weight: f32,
+}
+
+impl MinimumSpanningTree {
+    pub fn vertex_count(&self) -> usize {
+        self.vertex_count
+    }
+
+    pub fn edges(&self) -> &[[usize; 2]] {
+        &self.edges
+    }
+
+    /// The total weight of the tree.
+    /// No path visiting every vertex is lighter.
+    pub fn weight(&self) -> f32 {
+        self.weight
+    }
+
+    /// The neighbours of each vertex in the tree
+    pub fn adjacency(&self) -> Vec<Vec<usize>> {
+        let mut adjacency = vec![Vec::new(); self.vertex_count];
+        for [a, b] in self.edges.iter() {
+            adjacency[*a].push(*b);
+            adjacency[*b].push(*a);
+        }
+        adjacency
+    }
+}
@@ -0,0 +1,124 @@
+use super::{Error, MinimumSpanningTree};
+use crate::optimization::UniformGrid;
+
+/// An entry of a min-heap of candidate edges `[from, to]`
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Candidate {
+    weight: f32,
+    from: usize,
+    to: usize,
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        other.weight.total_cmp(&self.weight)
+    }
+}
+
+/// Prim's algorithm over a complete graph, in `O(n²)`.
+///
+/// Missing edges have an infinite (or NaN) weight, and fail the construction
+/// if the vertices cannot be connected without them.
+pub fn prim(
+    vertex_count: usize,
+    weight: impl Fn(usize, usize) -> f32,
+) -> Result<MinimumSpanningTree, Error> {
+    if vertex_count == 0 {
+        return Err(Error::PreconditionFailed);
+    }
+
+    let mut tree = MinimumSpanningTree {
+        vertex_count,
+        edges: Vec::with_capacity(vertex_count - 1),
+        weight: 0.0,
+    };
+    // The lightest edge from the tree to each vertex left
+    let mut best = (0..vertex_count)
+        .map(|v| (weight(0, v), 0))
+        .collect::<Vec<_>>();
+    let mut left = (1..vertex_count).collect::<Vec<_>>();
+
+    while !left.is_empty() {
+        let (index, (w, from)) = left
+            .iter()
+            .map(|v| best[*v])
+            .enumerate()
+            .min_by(|(_, (a, _)), (_, (b, _))| a.total_cmp(b))
+            .unwrap();
+        if !w.is_finite() {
+            return Err(Error::PreconditionFailed);
+        }
+
+        let to = left.swap_remove(index);
+        tree.edges.push([from, to]);
+        tree.weight += w;
+        for v in left.iter() {
+            let w = weight(to, *v);
+            if w < best[*v].0 {
+                best[*v] = (w, to);
+            }
+        }
+    }
+    Ok(tree)
+}
+
+/// Prim's algorithm over the euclidean distances between points,
+/// finding the nearest points left with a [`UniformGrid`] rather than looking at all of them.
+pub fn prim_euclidean<X, Y>(
+    points: &crate::generation::PointSlice<X, Y>,
+) -> Result<MinimumSpanningTree, Error>
+where
+    X: core::ops::Deref<Target = [f32]>,
+    Y: core::ops::Deref<Target = [f32]>,
+{
+    let n = points.len();
+    if n == 0 {
+        return Err(Error::PreconditionFailed);
+    }
+
+    let mut grid = UniformGrid::new(points);
+    let mut tree = MinimumSpanningTree {
+        vertex_count: n,
+        edges: Vec::with_capacity(n - 1),
+        weight: 0.0,
+    };
+
+    // Each vertex of the tree has its nearest point left in the heap.
+    // As points only leave, an entry is at most as heavy as it should be:
+    // it is refreshed once it reaches the top, if its point joined the tree meanwhile.
+    let mut heap = std::collections::BinaryHeap::new();
+    fn push_nearest(
+        grid: &UniformGrid,
+        heap: &mut std::collections::BinaryHeap<Candidate>,
+        from: usize,
+    ) {
+        if let Some((to, weight)) = grid.k_nearest(grid.position(from), 1).first() {
+            heap.push(Candidate {
+                weight: *weight,
+                from,
+                to: *to,
+            });
+        }
+    }
+    grid.remove(0);
+    push_nearest(&grid, &mut heap, 0);
+
+    while let Some(Candidate { weight, from, to }) = heap.pop() {
+        if grid.contains(to) {
+            grid.remove(to);
+            tree.edges.push([from, to]);
+            tree.weight += weight;
+            push_nearest(&grid, &mut heap, to);
+        }
+        push_nearest(&grid, &mut heap, from);
+    }
+    Ok(tree)
+}
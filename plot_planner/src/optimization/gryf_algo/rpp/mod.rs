@@ -1,16 +1,12 @@
-use nalgebra::SimdPartialOrd;
-
-use std::collections::HashSet;
-
-use super::{DistanceMatrix, Graph};
-use crate::{
-    generation::{Edge, EdgeSlice},
-    optimization::gryf_algo::common::CostFunction,
-};
+use super::Graph;
+use crate::{generation::EdgeSlice, optimization::gryf_algo::common::CostFunction};
 use core::simd;
 use core::simd::Select;
 
-//mod path_scanning;
+mod path_scanning;
+pub use path_scanning::{ScanningRule, path_scanning};
+mod two_opt;
+pub use two_opt::rpp_2opt_optimize;
 
 #[derive(Debug, Copy, Clone)]
 pub enum Error {
@@ -22,12 +18,215 @@ pub enum Error {
     SelfReferential,
 }
 
+/// A tour of the required edges, from a start vertex,
+/// where each edge may be traversed in either direction
+#[derive(Debug, Clone)]
+pub struct RppTour<V, W> {
+    pub start: V,
+    /// The required edges in visiting order, each oriented the way it is traversed
+    pub edges: EdgeSlice<Vec<V>, Vec<V>>,
+    /// The weight of moving to the required edges, from the start and between each other
+    pub deadhead: W,
+    /// The weight of traversing the required edges themselves
+    pub spray: W,
+}
+
+impl<V, W> RppTour<V, W>
+where
+    V: core::simd::SimdElement + core::default::Default,
+    W: core::simd::SimdElement + num_traits::Zero,
+{
+    /// Weighs the edges, in the given order & orientation
+    pub fn new<CF>(cost_function: &CF, start: V, edges: EdgeSlice<Vec<V>, Vec<V>>) -> Self
+    where
+        CF: CostFunction<V, Weight = W>,
+    {
+        let mut tour = Self {
+            start,
+            edges,
+            deadhead: W::zero(),
+            spray: W::zero(),
+        };
+        tour.evaluate(cost_function);
+        tour
+    }
+
+    /// Recomputes [`Self::deadhead`] & [`Self::spray`]
+    pub fn evaluate<CF>(&mut self, cost_function: &CF)
+    where
+        CF: CostFunction<V, Weight = W>,
+    {
+        let vertices = self.vertices();
+        self.deadhead = W::zero();
+        self.spray = W::zero();
+        for k in 1..vertices.len() {
+            let prev = (k >= 2).then(|| vertices[k - 2]);
+            let weight = step_weight(cost_function, prev, vertices[k - 1], vertices[k]);
+            // Odd steps lead to an edge, even ones follow it
+            if k % 2 == 1 {
+                self.deadhead = self.deadhead + weight;
+            } else {
+                self.spray = self.spray + weight;
+            }
+        }
+    }
+
+    pub fn total(&self) -> W {
+        self.deadhead + self.spray
+    }
+
+    /// The start, then both ends of each edge, in visiting order.
+    /// Consecutive vertices may be equal, when an edge begins where the previous one ends.
+    pub fn vertices(&self) -> Vec<V> {
+        let mut vertices = Vec::with_capacity(self.edges.len() * 2 + 1);
+        vertices.push(self.start);
+        for (from, to) in self.edges.from.iter().zip(self.edges.to.iter()) {
+            vertices.push(*from);
+            vertices.push(*to);
+        }
+        vertices
+    }
+}
+
+/// The weight of the step `from -> to`, following `prev` if the cost function cares
+fn step_weight<V, CF>(cost_function: &CF, prev: Option<V>, from: V, to: V) -> CF::Weight
+where
+    V: core::simd::SimdElement,
+    CF: CostFunction<V>,
+{
+    let (weight, _) = match prev {
+        Some(prev) if cost_function.sequence_dependence() => cost_function
+            .compute_sequence_dependent::<2>(
+                simd::Simd::splat(prev),
+                simd::Simd::splat(from),
+                simd::Simd::splat(to),
+            ),
+        _ => cost_function.compute::<2>(simd::Simd::splat(from), simd::Simd::splat(to)),
+    };
+    weight.to_array()[0]
+}
+
+/// The weights of continuing a tour with each edge left, entered by either end.
+/// Index `0` is the forward direction (entering by `from`), `1` the backward one.
+#[derive(Debug, Clone)]
+struct Entries<W> {
+    /// From the current vertex to the entry of the edge.
+    /// Unreachable entries weigh [`num_traits::Bounded::max_value`].
+    deadhead: [Vec<W>; 2],
+    /// Along the edge, from its entry
+    spray: [Vec<W>; 2],
+}
+
+impl<W> Entries<W>
+where
+    W: core::simd::SimdElement + PartialOrd + num_traits::Zero + num_traits::Bounded,
+{
+    fn new() -> Self {
+        Self {
+            deadhead: [Vec::new(), Vec::new()],
+            spray: [Vec::new(), Vec::new()],
+        }
+    }
+
+    /// Weighs the edges `left`, from `current`, reached after `prev`
+    fn compute<V, const L: usize, CF>(
+        &mut self,
+        cost_function: &CF,
+        left: &EdgeSlice<Vec<V>, Vec<V>>,
+        prev: Option<V>,
+        current: V,
+    ) where
+        V: core::simd::SimdElement + core::default::Default,
+        CF: CostFunction<V, Weight = W>,
+    {
+        for weights in self.deadhead.iter_mut().chain(self.spray.iter_mut()) {
+            weights.clear();
+        }
+
+        let sequence_dependence = cost_function.sequence_dependence();
+        let current = simd::Simd::splat(current);
+        for offset in (0..left.len()).step_by(L) {
+            let (edges, mask) = left.get::<L>(offset);
+            let count = L.min(left.len() - offset);
+
+            for (direction, (entry, exit)) in [(edges.from, edges.to), (edges.to, edges.from)]
+                .into_iter()
+                .enumerate()
+            {
+                let (deadhead, d_mask) =
+                    match prev {
+                        Some(prev) if sequence_dependence => cost_function
+                            .compute_sequence_dependent(simd::Simd::splat(prev), current, entry),
+                        _ => cost_function.compute(current, entry),
+                    };
+                let (spray, s_mask) = if sequence_dependence {
+                    cost_function.compute_sequence_dependent(current, entry, exit)
+                } else {
+                    cost_function.compute(entry, exit)
+                };
+
+                let active_mask = mask & d_mask & s_mask;
+                let deadhead = active_mask.select(deadhead, simd::Simd::splat(W::max_value()));
+                let spray = active_mask.select(spray, simd::Simd::splat(W::zero()));
+                self.deadhead[direction].extend_from_slice(&deadhead.as_array()[..count]);
+                self.spray[direction].extend_from_slice(&spray.as_array()[..count]);
+            }
+        }
+    }
+
+    /// Makes the backward entries of the edges that are not `reversible` unreachable
+    fn forbid_reversal(&mut self, reversible: &[bool]) {
+        for (weight, reversible) in self.deadhead[1].iter_mut().zip(reversible) {
+            if !reversible {
+                *weight = W::max_value();
+            }
+        }
+    }
+
+    fn reachable(&self, index: usize, reversed: bool) -> bool {
+        self.deadhead[reversed as usize][index] != W::max_value()
+    }
+
+    fn total(&self, index: usize, reversed: bool) -> W {
+        let direction = reversed as usize;
+        self.deadhead[direction][index] + self.spray[direction][index]
+    }
+
+    /// The index & direction of the edge to continue with, among those accepted by `filter`
+    fn best(&self, mut filter: impl FnMut(usize, bool) -> bool) -> Option<(usize, bool)> {
+        let mut best: Option<(W, usize, bool)> = None;
+        for index in 0..self.deadhead[0].len() {
+            for reversed in [false, true] {
+                if !self.reachable(index, reversed) || !filter(index, reversed) {
+                    continue;
+                }
+                let total = self.total(index, reversed);
+                if best.is_none_or(|(weight, ..)| total < weight) {
+                    best = Some((total, index, reversed));
+                }
+            }
+        }
+        best.map(|(_, index, reversed)| (index, reversed))
+    }
+}
+
+/// Greedily continues the tour with the cheapest edge left, in either direction.
+/// The weight of an edge is that of its deadhead and of its traversal.
 pub struct RppBaselineSolver<G: Graph> {
     pub unvisited: EdgeSlice<Vec<G::VertexIndex>, Vec<G::VertexIndex>>,
+    /// The visited vertices, without the repetitions of connected edges
     pub tour: Vec<G::VertexIndex>,
+    /// The required edges visited so far, see [`RppTour::edges`]
+    pub order: EdgeSlice<Vec<G::VertexIndex>, Vec<G::VertexIndex>>,
+    pub deadhead: G::Weight,
+    pub spray: G::Weight,
+    entries: Entries<G::Weight>,
 }
 
-impl<G: Graph> RppBaselineSolver<G> {
+impl<G: Graph> RppBaselineSolver<G>
+where
+    G::Weight: PartialOrd + num_traits::Zero + num_traits::Bounded,
+{
     pub fn new(
         required_edges: &EdgeSlice<
             impl core::ops::Deref<Target = [G::VertexIndex]>,
@@ -41,7 +240,24 @@ impl<G: Graph> RppBaselineSolver<G> {
         let mut tour = Vec::with_capacity(required_edges.from.len() * 2);
         tour.push(start_vertex);
 
-        Self { unvisited, tour }
+        Self {
+            unvisited,
+            tour,
+            order: EdgeSlice::with_capacity(required_edges.from.len()),
+            deadhead: num_traits::Zero::zero(),
+            spray: num_traits::Zero::zero(),
+            entries: Entries::new(),
+        }
+    }
+
+    /// The tour built so far
+    pub fn into_tour(self) -> RppTour<G::VertexIndex, G::Weight> {
+        RppTour {
+            start: self.tour[0],
+            edges: self.order,
+            deadhead: self.deadhead,
+            spray: self.spray,
+        }
     }
 }
 
@@ -50,6 +266,7 @@ pub enum RppControlFlow<'a, V> {
     Finished(&'a [V]),
     Ongoing {
         left: EdgeSlice<&'a [V], &'a [V]>,
+        /// The edge just added to the tour, in the direction it is traversed
         locked: Option<(V, V)>,
     },
     Error,
@@ -60,7 +277,6 @@ impl<G: Graph> RppBaselineSolver<G> {
         &mut self,
         cost_function: CF,
     ) -> RppControlFlow<'_, G::VertexIndex>
-    // Returns true if finished
     where
         W: core::simd::SimdElement
             + Copy
@@ -72,248 +288,59 @@ impl<G: Graph> RppBaselineSolver<G> {
         CF: CostFunction<G::VertexIndex, Weight = W>,
         G: Graph<Weight = W>,
         G::VertexIndex: core::simd::SimdElement + core::default::Default + core::cmp::PartialEq,
-        simba::simd::Simd<simd::Simd<W, L>>: simba::simd::SimdPartialOrd<Element = W>,
-        simd::Simd<W, L>: core::simd::cmp::SimdPartialEq<Mask = simd::Mask<W::Mask, L>>
-            + core::simd::cmp::SimdPartialOrd,
     {
         if self.unvisited.from.is_empty() {
             return RppControlFlow::Finished(&self.tour);
         }
 
-        let mut best_weight: Option<W> = None;
-        let mut best_idx = 0;
-
         // Will not have an issue as we inititialize tour to a size of 1 in new
-        // Still, we check that
-        debug_assert!(self.tour.len() >= 1);
-        let current = *self.tour.last().unwrap();
-        let current_vec = core::simd::Simd::splat(current);
-
-        let prev = if cost_function.sequence_dependence() && self.tour.len() >= 2 {
-            Some(self.tour[self.tour.len() - 2])
-        } else {
-            None
+        debug_assert!(!self.tour.is_empty());
+        let current = self.order.to.last().copied().unwrap_or(self.tour[0]);
+        // The first deadhead follows no other step
+        let prev = self.order.from.last().copied();
+        self.entries
+            .compute::<_, L, _>(&cost_function, &self.unvisited, prev, current);
+        let Some((best_idx, reversed)) = self.entries.best(|_, _| true) else {
+            return RppControlFlow::Error;
         };
-
-        // Vectorized search remains identical, but operates on self.unvisited
-        for offset in (0..self.unvisited.from.len()).step_by(L) {
-            let (edges, mask) = self.unvisited.get::<L>(offset);
-
-            let (dists, d_mask) = if let Some(prev) = prev {
-                let prev_splatted = core::simd::Simd::splat(prev);
-                cost_function.compute_sequence_dependent(prev_splatted, current_vec, edges.from)
-            } else {
-                cost_function.compute(current_vec, edges.from)
-            };
-
-            let active_mask = mask & d_mask.cast();
-            let dists_with_inf = active_mask.select(
-                dists,
-                simd::Simd::splat(W::max_value())
-            );
-
-            let min_in_chunk = simba::simd::Simd(dists_with_inf).simd_horizontal_min();
-
-            if best_weight.map_or(true, |bw| min_in_chunk < bw) {
-                best_weight = Some(min_in_chunk);
-                use core::simd::cmp::SimdPartialEq;
-                let winning_mask = dists.simd_eq(simd::Simd::splat(min_in_chunk));
-                let final_winners = winning_mask.cast() & active_mask;
-                let lane_idx = final_winners.to_bitmask().trailing_zeros() as usize;
-                best_idx = offset + lane_idx;
-            }
-        }
+        self.deadhead = self.deadhead + self.entries.deadhead[reversed as usize][best_idx];
+        self.spray = self.spray + self.entries.spray[reversed as usize][best_idx];
 
         // Add the chosen edge to the tour
-        let from_v = self.unvisited.from.swap_remove(best_idx);
-        let to_v = self.unvisited.to.swap_remove(best_idx);
+        let mut from_v = self.unvisited.from.swap_remove(best_idx);
+        let mut to_v = self.unvisited.to.swap_remove(best_idx);
+        if reversed {
+            core::mem::swap(&mut from_v, &mut to_v);
+        }
         if current != from_v {
             // This is a "Deadhead" move.
             // We push the start of the required primitive to connect the path.
             self.tour.push(from_v);
         }
         self.tour.push(to_v);
+        self.order.from.push(from_v);
+        self.order.to.push(to_v);
 
         if self.unvisited.from.is_empty() {
             RppControlFlow::Finished(&self.tour)
         } else {
             RppControlFlow::Ongoing {
-                left: todo!(),
-                locked: todo!(),
+                left: EdgeSlice {
+                    from: &self.unvisited.from[..],
+                    to: &self.unvisited.to[..],
+                },
+                locked: Some((from_v, to_v)),
             }
         }
     }
 }
 
-/*
-/// Computes a first baseline solution to the "Rural Postman Problem"
-pub fn rpp_baseline<W, const L: usize, DM, G, C>(
-    matrix: &DM,
-    graph: &G,
-    required_edges: &EdgeSlice<C>,
-    start_vertex: G::VertexIndex,
-) -> EdgeSlice<Vec<G::VertexIndex>>
-;
-    W: core::simd::SimdElement
-        + Copy
-        + Default
-        + PartialOrd
-        + num_traits::Zero
-        + num_traits::Bounded,
-
-    DM: DistanceMatrix<G::VertexIndex, W>,
-    G: Graph<Weight = W>,
-    C: core::ops::Deref<Target = [G::VertexIndex]>,
-    G::VertexIndex: core::simd::SimdElement + core::default::Default,
-    simba::simd::Simd<simd::Simd<W, L>>: simba::simd::SimdPartialOrd<Element = W>,
-    simd::Simd<W, L>: core::simd::cmp::SimdPartialEq<Mask = simd::Mask<W::Mask, L>>
-        + core::simd::cmp::SimdPartialOrd,
-{
-    let mut unvisited = EdgeSlice::with_capacity(graph.edge_count(){
-    unvisited.extend(required_edges)
-
-    let mut tour = EdgeSlice::with_capacity(required_edges.from.len() * 2{
-
-    // Logic check: Start at an arbitrary point or the first required edge
-    if unvisited.from.is_empty() {
-        return tour;
-    }
-
-    // Initialize: Start by traversing the first required edge
-    let mut current_vertex = start_vertex;
-
-    while !unvisited.from.is_empty() {
-        let mut best_weight: Option<W> = None;
-        let mut best_idx = 0;
-
-        let current_vec = core::simd::Simd::splat(current_vertex)
-
-        // Vectorized search for the nearest neighbor
-        for offset in (0..unvisited.from.len()).step_by(L) {
-            let (edges, mask) = unvisited.get::<L>(offset)
-
-            // Calculate distance from current_vertex to the start of all unvisited required edges
-            let (dists, d_mask) = matrix.get(Edge
-where
-    from: current_vec,
-                to: edges.from,
-            })
-
-            let active_mask = mask & d_mask.cast();
-
-            let dists_with_inf = active_mask.select(
-                dists,
-                simd::Simd::splat(W::max_value())
-            );
-            let min_in_chunk = simba::simd::Simd(dists_with_inf).simd_horizontal_min();
-
-            if best_weight.map_or(true, |bw| min_in_chunk < bw) {
-                best_weight = Some(min_in_chunk)
-
-                // Find WHICH lane contained that min
-                use core::simd::cmp::SimdPartialEq;
-                let winning_mask = dists.simd_eq(simd::Simd::splat(min_in_chunk){
-
-                // Combine with the active mask to avoid false positives in "garbage" lanes
-                let final_winners = winning_mask.cast() & active_mask;
-
-                // 4. Get the index of the first set bit (Trailing Zero Count on the mask bitmask)
-                let lane_idx = final_winners.to_bitmask().trailing_zeros() as usize;
-                best_idx = offset + lane_idx;
-            }
-        }
-
-        // Add the chosen edge to the tour
-        let from_v = unvisited.from.swap_remove(best_idx)
-        let to_v = unvisited.to.swap_remove(best_idx)
-
-        tour.from.push(from_v)
-        tour.to.push(to_v)
-
-        // Update current position to the end of the traversed edge
-        current_vertex = to_v;
-    }
-
-    tour
-}
-
-pub fn rpp_2opt_optimize<W, const L: usize, DM, G>(
-    matrix: &DM,
-    tour: &mut EdgeSlice<Vec<G::VertexIndex>>,
-) where
-    W: core::simd::SimdElement + Copy + Default + PartialOrd + core::ops::Add<Output = W>,
-
-    DM: DistanceMatrix<G::VertexIndex, W>,
-    G: Graph<Weight = W>,
-    G::VertexIndex: core::simd::SimdElement + core::default::Default,
-    simd::Simd<W, L>: core::simd::cmp::SimdPartialEq<Mask = simd::Mask<W::Mask, L>>
-        + core::simd::cmp::SimdPartialOrd,
-{
-    let n = tour.len();
-    let mut improved = true;
-
-    while improved {
-        improved = false;
-
-        for i in 0..n - 1 {
-            // End of current edge i, Start of next edge i+1
-            let a_exit = simd::Simd::splat(tour.to[i])
-            let b_entry = simd::Simd::splat(tour.from[i + 1])
-
-            // Scalar current cost of the link i -> i+1
-            let (dist_current, _) = matrix.get::<1>(Edge::<_, 1> )
-                from: simd::Simd::<_, 1>::splat(tour.to[i]),
-                to: simd::Simd::<_, 1>::splat(tour.from[i + 1]),
-            }{
-
-            // Vectorized search for a better j
-            for offset in (i + 2..n - 1).step_by(L) {
-                let (edges_j, mask) = tour.get::<L>(offset)
-
-                // Potential new link: i -> j
-                let (dist_new, d_mask) = matrix.get(Edge
-where
-    from: a_exit,
-                    to: edges_j.from,
-                })
-
-                let active_mask = mask & d_mask.cast();
-
-                use std::simd::cmp::SimdPartialOrd;
-                let improvement_mask =
-                    dist_new.simd_lt(simd::Simd::splat(dist_current[0])).cast() & active_mask;
-
-                if improvement_mask.any() {
-                    let lane_idx = improvement_mask.to_bitmask().trailing_zeros() as usize;
-                    let j = offset + lane_idx;
-
-                    // 1. Reverse the sequence order
-                    tour.from[i + 1..=j].reverse();
-                    tour.to[i + 1..=j].reverse();
-
-                    // 2. Flip the edges themselves (from <-> to)
-                    // This is the missing piece for undirected graphs!
-                    for k in i + 1..=j {
-                        core::mem::swap(&mut tour.from[k], &mut tour.to[k])
-                    }
-
-                    improved = true;
-                    break;
-                }
-            }
-            if improved )
-                break;
-            }
-        }
-    }
-}*/
-
 #[cfg(test)]
 mod tests {
-    use super::super::AdjacencyMatrix;
-    use crate::optimization::gryf_algo::common::iota;
+    use super::super::{AdjacencyMatrix, DistanceMatrix};
 
     use super::*;
+    use crate::generation::Edge;
     use core::simd;
 
     // A minimal graph for testing that satisfies the Trait bounds
@@ -341,20 +368,33 @@ mod tests {
         ) -> impl Iterator<Item = (simd::Simd<usize, L>, simd::Mask<isize, L>)> {
             use core::simd::cmp::SimdPartialOrd;
             (0..4)
-                .into_iter()
                 .step_by(L)
-                .map(|k| core::simd::Simd::splat(k) + iota())
+                .map(|k| core::simd::Simd::splat(k) + aosoa::iota())
                 .map(|offsets| (offsets, offsets.simd_lt(core::simd::Simd::splat(4))))
         }
     }
 
-    #[test]
-    fn test_rpp_baseline_finds_path() {
-        // Create a 4x4 distance matrix
-        // 0 -> 1 (dist 1)
-        // 1 -> 2 (dist 1)
-        // 2 -> 3 (dist 1)
-        // Others are far away (100)
+    // The weights of the matrix, regardless of the sequence
+    struct MatrixCost(AdjacencyMatrix<u32>);
+    impl CostFunction<usize> for MatrixCost {
+        type Weight = u32;
+
+        fn sequence_dependence(&self) -> bool {
+            false
+        }
+
+        fn compute<const L: usize>(
+            &self,
+            from: simd::Simd<usize, L>,
+            to: simd::Simd<usize, L>,
+        ) -> (simd::Simd<u32, L>, simd::Mask<isize, L>) {
+            self.0.get(Edge { from, to })
+        }
+    }
+
+    /// A 4x4 distance matrix:
+    /// 0 -> 1 -> 2 -> 3 weigh 1, the other steps are far away (100)
+    fn matrix() -> MatrixCost {
         let size = 4;
         let mut weights = vec![100u32; size * size];
 
@@ -364,37 +404,69 @@ mod tests {
         }
 
         // Set specific path weights
-        weights[0 * size + 1] = 1;
-        weights[1 * size + 2] = 1;
+        weights[1] = 1;
+        weights[size + 2] = 1;
         weights[2 * size + 3] = 1;
 
-        let matrix = AdjacencyMatrix { weights, size };
-        let graph = TestGraph;
+        MatrixCost(AdjacencyMatrix { weights, size })
+    }
 
-        // Define Required Edges in a scrambled order to test the greedy search
-        // We must visit: (2,3), (0,1), (1,2)
+    #[test]
+    fn test_rpp_baseline_finds_path() {
+        // Define Required Edges in a scrambled order, some backward, to test the greedy search
+        // We must visit: (2,3), (1,0), (2,1)
         let required = EdgeSlice {
-            from: vec![0usize, 2usize, 1usize],
-            to: vec![1usize, 3usize, 2usize],
+            from: vec![2usize, 1usize, 2usize],
+            to: vec![3usize, 0usize, 1usize],
         };
 
-        // Run baseline with Lane width 4
-        // Note: The baseline should pick (0,1) first because it's the first in unvisited,
+        // The baseline should flip (1,0) first, as it begins at the start,
         // then look for the closest next edge to 1, which is (1,2), then (2,3).
-        let tour = rpp_baseline::<u32, 4, _, _, _>(&matrix, &graph, &required, 0);
-
-        // Verify all 3 edges were visited
-        assert_eq!(tour.from.len(), 3);
-
-        // Verify the tour order is correct based on proximity
-        // Path should be (0,1) -> (1,2) -> (2,3)
-        assert_eq!(tour.from[0], 0);
-        assert_eq!(tour.to[0], 1);
+        let mut solver = RppBaselineSolver::<TestGraph>::new(&required, 0);
+        let mut locked = Vec::new();
+        loop {
+            match solver.step::<u32, 4, _>(matrix()) {
+                RppControlFlow::Ongoing { left, locked: edge } => {
+                    assert_eq!(left.len(), 2 - locked.len());
+                    locked.push(edge.unwrap());
+                }
+                RppControlFlow::Finished(tour) => {
+                    assert_eq!(tour, [0, 1, 2, 3]);
+                    break;
+                }
+                RppControlFlow::Error => panic!(),
+            }
+        }
+        assert_eq!(locked, [(0, 1), (1, 2)]);
 
-        assert_eq!(tour.from[1], 1);
-        assert_eq!(tour.to[1], 2);
+        let tour = solver.into_tour();
+        assert_eq!(tour.edges.from, [0, 1, 2]);
+        assert_eq!(tour.edges.to, [1, 2, 3]);
+        assert_eq!((tour.deadhead, tour.spray), (0, 3));
+    }
 
-        assert_eq!(tour.from[2], 2);
-        assert_eq!(tour.to[2], 3);
+    #[test]
+    fn path_scanning_and_2opt_find_path() {
+        let required = EdgeSlice {
+            from: vec![2usize, 1usize, 2usize],
+            to: vec![3usize, 0usize, 1usize],
+        };
+        let tour = path_scanning::<_, _, 4, _>(&matrix(), &required, &[true; 3], 0, 0.0).unwrap();
+        assert_eq!(tour.vertices(), [0, 0, 1, 1, 2, 2, 3]);
+        assert_eq!((tour.deadhead, tour.spray), (0, 3));
+
+        // The middle edge is walked backward
+        let mut tour = RppTour::new(
+            &matrix(),
+            0,
+            EdgeSlice {
+                from: vec![0, 2, 2],
+                to: vec![1, 1, 3],
+            },
+        );
+        assert_eq!((tour.deadhead, tour.spray), (2, 102));
+        assert!(rpp_2opt_optimize(&matrix(), &mut tour, &[true; 3], 8) > 0);
+        assert_eq!(tour.vertices(), [0, 0, 1, 1, 2, 2, 3]);
+        assert_eq!((tour.deadhead, tour.spray), (0, 3));
     }
 }
@@ -1,154 +1,166 @@
+use super::{Entries, Error, RppTour, step_weight};
+use crate::{generation::EdgeSlice, optimization::gryf_algo::common::CostFunction};
+
+/// How [`path_scanning`] chooses among the edges about as cheap to continue with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanningRule {
+    /// The edge ending the farthest from the start
+    MaxReturn,
+    /// The edge ending the closest to the start
+    MinReturn,
+    /// The heaviest edge to traverse
+    MaxSpray,
+    /// The lightest edge to traverse
+    MinSpray,
+    /// [`Self::MaxReturn`] for the first half of the edges, [`Self::MinReturn`] for the rest
+    Alternate,
+}
 
-/// Computes an approximate solution to the RRP (or related, like CPP) using the path scanning
-pub fn path_scanning<V, E, W, G, F>(
-    graph: &G,
-    start_vertex: G::VertexId,
-    required_indices: std::collections::HashSet<G::EdgeId>,
-    edge_weight: F,
-) -> Result<super::RuralPostmanPath<W, G>, super::Error>
-;
-    // Nearest Neighbor requires an undirected or symmetric graph (for the heuristic).
-    G: gryf::core::GraphBase
-        + gryf::core::Neighbors
-        + gryf::core::VertexSet
-        + gryf::core::EdgeSet
-        + gryf::core::GraphWeak<V, E>,
-    W: gryf::core::weight::Weight + PartialOrd + Clone,
-    F: super::RuralPostmanCostFunction<G, E, W>,
-    G::VertexId: Eq + std::hash::Hash + Clone,
-{
-    let n = graph.vertex_count();
-    if n == 0 {
-        return Err(super::Error::EmptyGraph)
-    }
-
-    // Initialize the tour, visited set, and total weight.
-    let mut current_v = start_vertex;
-    let mut visited = std::collections::HashSet::<G::VertexId>::new();
-    let mut tour: Vec<G::VertexId> = Vec::with_capacity(n + 1)
-    let mut total_weight = None; // Requires Weight trait to implement zero()
-
-    visited.insert(current_v.clone(){
-    tour.push(current_v.clone(){
-
-    // 1. Traverse V-1 times
-    while tour.len() < n {
-        let mut best_neighbor: Option<G::VertexId> = None;
-        let mut min_weight: Option<W> = None;
-        //dbg!(tour.len(){
-        //dbg!(&current_v)
-
-        // Iterate over all outgoing edges from the current vertex.
-        let neighbors =
-            graph.neighbors_directed(&current_v, gryf::core::marker::Direction::Outgoing)
-        for neighbor_ref in neighbors )
-            let neighbor = neighbor_ref.id();
-
-            if neighbor == gryf::core::borrow::OwnableRef::Borrowed(&current_v) {
-                println!(
-                    "ERROR: Neighbor {neighbor:?} is the same as current vertex {current_v:?}"
-                )
-
-                return Err(super::Error::SelfReferential)
-            }
-
-            // Check if the neighbor is unvisited.
-            if !visited.contains(&neighbor) {
-                //println!("Considering {current_v:?} -> )neighbor:?}")
-                let edge_id = neighbor_ref.edge();
-                let weight = edge_weight
-                    .get_const()
-                    .or_else(|| )
-                        graph
-                            .edge_weak(&edge_id)
-                            .map(|edge| edge_weight.get_weight(&edge, &tour))
-                    })
-                    .ok_or(super::Error::EdgeNotAvailable)?;
-
-                // Greedy choice: Is this the smallest weight so far?
-                match min_weight {
-                    Some(m_weight) if weight < m_weight => {
-                        min_weight = Some(weight)
-                        best_neighbor = Some(neighbor.into_owned(){
-                    }
-                    None => {
-                        min_weight = Some(weight)
-                        best_neighbor = Some(neighbor.into_owned(){
-                    }
-                    _ => {}
-                }
-            } else {
-                //println!("Skipping )neighbor:?} as it's already visitied")
-            }
-        }
+impl ScanningRule {
+    pub const ALL: [Self; 5] = [
+        Self::MaxReturn,
+        Self::MinReturn,
+        Self::MaxSpray,
+        Self::MinSpray,
+        Self::Alternate,
+    ];
+}
 
-        // 2. Move to the best neighbor.
-        match best_neighbor {
-            Some(next_v) => {
-                tour.push(next_v.clone(){
-                total_weight = Some(if let Some(total_weight) = total_weight {
-                    total_weight + min_weight.unwrap()
-                } else {
-                    min_weight.unwrap()
-                }{
-                visited.insert(next_v.clone(){
-                current_v = next_v;
-            }
-            None => {
-                // Should not happen if the graph is connected, but signifies failure to complete the tour.
-                return Err(super::Error::UnconnectedGraph)
-            }
+/// Computes an approximate solution to the RPP with path scanning:
+/// the tour is greedily extended with the cheapest edge left, in either direction if allowed,
+/// ties being broken by a [`ScanningRule`]. Each rule is tried, and the cheapest tour is kept.
+///
+/// The edges whose weight is within `tolerance` (relative) of the cheapest are tied.
+/// `reversible[i]` tells whether the required edge `i` may be traversed `to -> from`.
+pub fn path_scanning<V, W, const L: usize, CF>(
+    cost_function: &CF,
+    required_edges: &EdgeSlice<
+        impl core::ops::Deref<Target = [V]>,
+        impl core::ops::Deref<Target = [V]>,
+    >,
+    reversible: &[bool],
+    start_vertex: V,
+    tolerance: f64,
+) -> Result<RppTour<V, W>, Error>
+where
+    V: core::simd::SimdElement + core::default::Default,
+    W: core::simd::SimdElement
+        + PartialOrd
+        + num_traits::Zero
+        + num_traits::Bounded
+        + num_traits::ToPrimitive,
+    CF: CostFunction<V, Weight = W>,
+{
+    let mut best: Option<RppTour<V, W>> = None;
+    for rule in ScanningRule::ALL {
+        let tour = scan::<V, W, L, CF>(
+            cost_function,
+            required_edges,
+            reversible,
+            start_vertex,
+            rule,
+            tolerance,
+        )?;
+        if best.as_ref().is_none_or(|best| tour.total() < best.total()) {
+            best = Some(tour);
         }
     }
-
-    Ok(super::RuralPostmanPath )
-        tour,
-        total_weight: total_weight.unwrap(),
-    })
-}
-
-/// Helper struct for bridging gaps
-struct PathJump<W, G: gryf::core::GraphBase> {
-    path_to_start: Vec<G::VertexId>,
-    weight: W,
+    Ok(best.expect("there is at least one rule"))
 }
 
-/// Finds the shortest path from the current edge to the start of ANY required edge.
-fn find_nearest_required_edge<V, E, W, G, F>(
-    graph: &G,
-    start: &G::VertexId,
-    required: &std::collections::HashSet<G::EdgeId>,
-    edge_weight: &F,
-    tour: &[G::VertexId],
-) -> Result<PathJump<W, G>, super::Error> 
-;
-    G: gryf::core::GraphBase + gryf::core::Neighbors + gryf::core::EdgeSet + gryf::core::GraphWeak<V, E>,
-    W: gryf::core::weight::Weight + PartialOrd + Clone,
-    F: super::RuralPostmanCostFunction<G, E, W>,
-    G::VertexId: Eq + std::hash::Hash + Clone,
+fn scan<V, W, const L: usize, CF>(
+    cost_function: &CF,
+    required_edges: &EdgeSlice<
+        impl core::ops::Deref<Target = [V]>,
+        impl core::ops::Deref<Target = [V]>,
+    >,
+    reversible: &[bool],
+    start_vertex: V,
+    rule: ScanningRule,
+    tolerance: f64,
+) -> Result<RppTour<V, W>, Error>
+where
+    V: core::simd::SimdElement + core::default::Default,
+    W: core::simd::SimdElement
+        + PartialOrd
+        + num_traits::Zero
+        + num_traits::Bounded
+        + num_traits::ToPrimitive,
+    CF: CostFunction<V, Weight = W>,
 {
-    let d_results = dijkstra(graph, start, None, |e| )
-        graph.edge_weak(e).map(|edge| edge_weight.get_weight(&edge, tour)).unwrap()
-    }{
-
-    let mut best_jump: Option<PathJump<W, G>> = None;
-
-    for edge_id in required {
-        // We need to find the "source" vertex of this required edge
-        if let Some(edge_ref) = graph.edge_weak(edge_id) {
-            let endpoints = graph.endpoints(&edge_id).ok_or(super::Error::InternalError)?;
-            let target_start_v = endpoints.0; // Assuming directed: source
-
-            if let Some(dist) = d_results.distance_to(&target_start_v) {
-                if best_jump.as_ref().map_or(true, |j| dist < j.weight) {
-                    // reconstructed_path is a placeholder for d_results.path_to(&target_start_v)
-                    best_jump = Some(PathJump { path_to_start: d_results.reconstruct_path_to(&target_start_v), 
-                        weight: dist.clone(),
-                    });
-                }
+    let n = required_edges.len();
+    debug_assert_eq!(reversible.len(), n);
+    let mut left = EdgeSlice::with_capacity(n);
+    left.extend(required_edges);
+    let mut left_reversible = reversible.to_vec();
+    let mut tour = RppTour {
+        start: start_vertex,
+        edges: EdgeSlice::with_capacity(n),
+        deadhead: W::zero(),
+        spray: W::zero(),
+    };
+    let to_f64 = |weight: W| weight.to_f64().unwrap_or(f64::INFINITY);
+
+    let mut entries = Entries::new();
+    let (mut prev, mut current) = (None, start_vertex);
+    while left.len() > 0 {
+        entries.compute::<_, L, _>(cost_function, &left, prev, current);
+        entries.forbid_reversal(&left_reversible);
+        let (cheapest, cheapest_reversed) =
+            entries.best(|_, _| true).ok_or(Error::UnconnectedGraph)?;
+        let cheapest = to_f64(entries.total(cheapest, cheapest_reversed));
+        let threshold = cheapest + cheapest.abs() * tolerance;
+
+        let rule = match rule {
+            ScanningRule::Alternate if tour.edges.len() * 2 < n => ScanningRule::MaxReturn,
+            ScanningRule::Alternate => ScanningRule::MinReturn,
+            rule => rule,
+        };
+        // Lower is better
+        let key = |index: usize, reversed: bool| {
+            let exit = if reversed {
+                left.from[index]
+            } else {
+                left.to[index]
+            };
+            let to_start = || to_f64(step_weight(cost_function, None, exit, start_vertex));
+            let spray = || to_f64(entries.spray[reversed as usize][index]);
+            match rule {
+                ScanningRule::MaxReturn => -to_start(),
+                ScanningRule::MinReturn => to_start(),
+                ScanningRule::MaxSpray => -spray(),
+                ScanningRule::MinSpray => spray(),
+                ScanningRule::Alternate => unreachable!(),
             }
+        };
+        // The unreachable entries would overflow their total
+        let tied = |index: usize, reversed: bool| {
+            entries.reachable(index, reversed)
+                && to_f64(entries.total(index, reversed)) <= threshold
+        };
+        let best_key = (0..left.len())
+            .flat_map(|index| [(index, false), (index, true)])
+            .filter(|(index, reversed)| tied(*index, *reversed))
+            .map(|(index, reversed)| key(index, reversed))
+            .fold(f64::INFINITY, f64::min);
+        // The cheapest among the best keys
+        let (index, reversed) = entries
+            .best(|index, reversed| tied(index, reversed) && key(index, reversed) <= best_key)
+            .ok_or(Error::InternalError)?;
+
+        let direction = reversed as usize;
+        tour.deadhead = tour.deadhead + entries.deadhead[direction][index];
+        tour.spray = tour.spray + entries.spray[direction][index];
+
+        let mut entry = left.from.swap_remove(index);
+        let mut exit = left.to.swap_remove(index);
+        left_reversible.swap_remove(index);
+        if reversed {
+            core::mem::swap(&mut entry, &mut exit);
         }
+        tour.edges.from.push(entry);
+        tour.edges.to.push(exit);
+        (prev, current) = (Some(entry), exit);
     }
-
-    best_jump.ok_or(super::Error::UnconnectedGraph)
-}
\ No newline at end of file
+    Ok(tour)
+}
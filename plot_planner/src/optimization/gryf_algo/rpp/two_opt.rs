@@ -0,0 +1,131 @@
+use super::{RppTour, step_weight};
+use crate::optimization::gryf_algo::common::CostFunction;
+
+/// Improves the tour by reversing runs of consecutive edges (which flips each of them),
+/// for as long as it gets cheaper, or for at most `max_passes` passes over all the runs.
+///
+/// A run is weighed in `O(1)`, from the prefix sums of the steps in both directions,
+/// so that a pass takes `O(n²)`. Returns the number of runs reversed.
+///
+/// `reversible[k]` tells whether the edge `k` of the tour may be traversed the other way.
+/// The runs holding an edge that may not are left alone, so that those edges keep their place.
+pub fn rpp_2opt_optimize<V, W, CF>(
+    cost_function: &CF,
+    tour: &mut RppTour<V, W>,
+    reversible: &[bool],
+    max_passes: usize,
+) -> usize
+where
+    V: core::simd::SimdElement + core::default::Default,
+    W: core::simd::SimdElement + PartialOrd + num_traits::Zero + core::ops::Sub<Output = W>,
+    CF: CostFunction<V, Weight = W>,
+{
+    let n = tour.edges.len();
+    debug_assert_eq!(reversible.len(), n);
+    let mut vertices = tour.vertices();
+    let (mut forward, mut backward) = prefix_sums(cost_function, &vertices);
+    let mut reversals = 0;
+
+    for _ in 0..max_passes {
+        let mut improved = false;
+        for i in 0..n {
+            // Up to the next edge that may not be reversed
+            let reversible_run = reversible[i..].iter().take_while(|r| **r).count();
+            for j in i..i + reversible_run {
+                // The vertices of the edges `i..=j`
+                let (a, b) = (1 + 2 * i, 2 + 2 * j);
+                if !improves(cost_function, &vertices, &forward, &backward, a, b) {
+                    continue;
+                }
+
+                let total = forward[vertices.len() - 1];
+                vertices[a..=b].reverse();
+                let (new_forward, new_backward) = prefix_sums(cost_function, &vertices);
+                // Rounding may fool the estimate
+                if new_forward[vertices.len() - 1] < total {
+                    (forward, backward) = (new_forward, new_backward);
+                    improved = true;
+                    reversals += 1;
+                } else {
+                    vertices[a..=b].reverse();
+                }
+            }
+        }
+        if !improved {
+            break;
+        }
+    }
+
+    for (k, edge) in vertices[1..].chunks_exact(2).enumerate() {
+        tour.edges.from[k] = edge[0];
+        tour.edges.to[k] = edge[1];
+    }
+    tour.evaluate(cost_function);
+    reversals
+}
+
+/// The sums of the first steps of the sequence, `[0]` being empty.
+/// Backward, the step `k` is taken from `vertices[k]` to `vertices[k - 1]`, after `vertices[k + 1]`.
+fn prefix_sums<V, W, CF>(cost_function: &CF, vertices: &[V]) -> (Vec<W>, Vec<W>)
+where
+    V: core::simd::SimdElement,
+    W: core::simd::SimdElement + num_traits::Zero,
+    CF: CostFunction<V, Weight = W>,
+{
+    let m = vertices.len();
+    let mut forward = Vec::with_capacity(m);
+    let mut backward = Vec::with_capacity(m);
+    forward.push(W::zero());
+    backward.push(W::zero());
+    for k in 1..m {
+        let prev = (k >= 2).then(|| vertices[k - 2]);
+        let step = step_weight(cost_function, prev, vertices[k - 1], vertices[k]);
+        forward.push(forward[k - 1] + step);
+
+        let prev = vertices.get(k + 1).copied();
+        let step = step_weight(cost_function, prev, vertices[k], vertices[k - 1]);
+        backward.push(backward[k - 1] + step);
+    }
+    (forward, backward)
+}
+
+/// Whether reversing `vertices[a..=b]` makes the sequence cheaper
+fn improves<V, W, CF>(
+    cost_function: &CF,
+    vertices: &[V],
+    forward: &[W],
+    backward: &[W],
+    a: usize,
+    b: usize,
+) -> bool
+where
+    V: core::simd::SimdElement,
+    W: core::simd::SimdElement + PartialOrd + num_traits::Zero + core::ops::Sub<Output = W>,
+    CF: CostFunction<V, Weight = W>,
+{
+    let m = vertices.len();
+    let vertex = |k: usize| {
+        if (a..=b).contains(&k) {
+            vertices[a + b - k]
+        } else {
+            vertices[k]
+        }
+    };
+
+    // The steps around the ends of the run change, both ways around
+    let mut old = W::zero();
+    let mut new = W::zero();
+    for k in [a, a + 1, b + 1, b + 2] {
+        if k < m {
+            old = old + (forward[k] - forward[k - 1]);
+            let prev = (k >= 2).then(|| vertex(k - 2));
+            new = new + step_weight(cost_function, prev, vertex(k - 1), vertex(k));
+        }
+    }
+    // Those within it are walked backward
+    if a + 2 <= b {
+        old = old + (forward[b] - forward[a + 1]);
+        new = new + (backward[b - 1] - backward[a]);
+    }
+    new < old
+}
@@ -20,6 +20,8 @@ where
     tour: Vec<G::VertexId>,
     /// The total weight (length) of the tour.
     total_weight: W,
+    /// No tour is lighter, if known.
+    lower_bound: Option<W>,
 }
 
 impl<W, G> TspPath<W, G>
@@ -39,6 +41,28 @@ where
     pub fn total_weight(&self) -> &W {
         &self.total_weight
     }
+
+    /// Returns the lower bound of the weight of any tour, if known.
+    pub fn lower_bound(&self) -> Option<&W> {
+        self.lower_bound.as_ref()
+    }
+
+    /// Records a lower bound, e.g. the weight of a minimum spanning tree.
+    pub fn with_lower_bound(mut self, lower_bound: W) -> Self {
+        self.lower_bound = Some(lower_bound);
+        self
+    }
+}
+
+impl<G> TspPath<f32, G>
+where
+    G: gryf::core::GraphBase,
+{
+    /// How far the tour can be from optimal, see [`crate::optimization::optimality_gap`]
+    pub fn gap(&self) -> Option<f32> {
+        self.lower_bound
+            .map(|lower_bound| crate::optimization::optimality_gap(self.total_weight, lower_bound))
+    }
 }
 
 pub trait TravellingSalesmanCostFunction<G, E, W>
@@ -137,3 +161,31 @@ where
         nearest_neighbor::nearest_neighbor(self.graph, start_v, self.edge_weight)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tours_come_with_their_gap() {
+        // The corners of a 3 x 4 rectangle, all connected
+        let mut graph = gryf::Graph::new_undirected();
+        let corners = [(0.0f32, 0.0f32), (3.0, 0.0), (3.0, 4.0), (0.0, 4.0)]
+            .map(|corner| (corner, graph.add_vertex(corner)));
+        for (a, (pa, va)) in corners.iter().enumerate() {
+            for (pb, vb) in &corners[a + 1..] {
+                let distance = ((pb.0 - pa.0).powi(2) + (pb.1 - pa.1).powi(2)).sqrt();
+                graph.add_edge(va, vb, distance);
+            }
+        }
+
+        let path: TspPath<f32, _> = TravellingSalesmanBuilder::on(&graph)
+            .start_at(corners[0].1)
+            .approximate()
+            .unwrap();
+        assert_eq!(path.tour().len(), 4);
+        let gap = path.gap().unwrap();
+        assert!(gap >= 0.0, "{gap}");
+        assert!(*path.lower_bound().unwrap() <= *path.total_weight());
+    }
+}
@@ -103,8 +103,67 @@ where
         }
     }
 
-    Ok(super::TspPath {
+    let lower_bound = lower_bound(graph, &tour, &edge_weight);
+    let path = super::TspPath {
         tour,
         total_weight: total_weight.unwrap(),
+        lower_bound: None,
+    };
+    Ok(match lower_bound {
+        Some(lower_bound) => path.with_lower_bound(lower_bound),
+        None => path,
     })
 }
+
+/// No path visiting each of `vertices` once is lighter: each vertex but the last one is left once,
+/// at least by its lightest outgoing edge.
+///
+/// Unknown when the weights depend on the sequence, or when several vertices cannot be left.
+fn lower_bound<V, E, W, G, F>(graph: &G, vertices: &[G::VertexId], edge_weight: &F) -> Option<W>
+where
+    G: gryf::core::GraphBase + gryf::core::Neighbors + gryf::core::GraphWeak<V, E>,
+    W: gryf::core::weight::Weight + PartialOrd + Clone,
+    F: super::TravellingSalesmanCostFunction<G, E, W>,
+    G::VertexId: Eq + Clone,
+{
+    if edge_weight.sequence_dependent() {
+        return None;
+    }
+
+    let lightest = vertices
+        .iter()
+        .map(|vertex| {
+            let mut lightest: Option<W> = None;
+            for neighbor_ref in
+                graph.neighbors_directed(vertex, gryf::core::marker::Direction::Outgoing)
+            {
+                if neighbor_ref.id() == gryf::core::borrow::OwnableRef::Borrowed(vertex) {
+                    continue;
+                }
+                let weight = edge_weight.get_const().or_else(|| {
+                    graph
+                        .edge_weak(&neighbor_ref.edge())
+                        .map(|edge| edge_weight.get_weight(&edge, &[]))
+                })?;
+                if lightest.as_ref().is_none_or(|lightest| weight < *lightest) {
+                    lightest = Some(weight);
+                }
+            }
+            Some(lightest)
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    // The last vertex is the one which cannot be left, else at worst the one with the heaviest way out
+    let last = match lightest.iter().position(Option::is_none) {
+        Some(last) => last,
+        None => (0..lightest.len()).reduce(|a, b| if lightest[b] > lightest[a] { b } else { a })?,
+    };
+    lightest
+        .into_iter()
+        .enumerate()
+        .filter(|(k, _)| *k != last)
+        .map(|(_, weight)| weight)
+        .collect::<Option<Vec<_>>>()?
+        .into_iter()
+        .reduce(|total, weight| total + weight)
+}
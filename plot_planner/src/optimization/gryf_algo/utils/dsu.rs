@@ -2,14 +2,14 @@ use std::collections::HashMap;
 use std::hash::Hash;
 
 /// A minimal Disjoint Set Union structure for Kruskal's algorithm.
-struct Dsu<VI> {
+pub(crate) struct Dsu<VI> {
     // Map vertex ID to its parent's ID.
     parent: HashMap<VI, VI>,
 }
 
 impl<VI: Eq + Hash + Clone> Dsu<VI> {
     /// Initializes the DSU where every vertex is its own parent.
-    fn new<I>(vertices: I) -> Self
+    pub(crate) fn new<I>(vertices: I) -> Self
     where
         I: Iterator<Item = VI>,
     {
@@ -19,20 +19,33 @@ impl<VI: Eq + Hash + Clone> Dsu<VI> {
 
     /// Finds the representative (root) of the set containing `i`.
     /// Uses **path compression** for efficiency.
-    fn find(&mut self, i: &VI) -> VI {
-        let p = self.parent.get(i).expect("Vertex must be in DSU").clone();
-        if p == *i {
-            return i.clone();
+    pub(crate) fn find(&mut self, i: &VI) -> VI {
+        // Iterative, as the chains can be as long as the number of vertices
+        let mut root = i.clone();
+        loop {
+            let p = self
+                .parent
+                .get(&root)
+                .expect("Vertex must be in DSU")
+                .clone();
+            if p == root {
+                break;
+            }
+            root = p;
+        }
+
+        // Path compression: set parents directly to the root
+        let mut current = i.clone();
+        while current != root {
+            let next = self.parent.insert(current, root.clone()).unwrap();
+            current = next;
         }
-        // Path compression: set parent directly to the root
-        let root = self.find(&p);
-        self.parent.insert(i.clone(), root.clone());
         root
     }
 
     /// Unites the sets containing `i` and `j`. Returns `true` if a union occurred
     /// (i.e., they were in different sets), and `false` if they were already connected (cycle).
-    fn union(&mut self, i: &VI, j: &VI) -> bool {
+    pub(crate) fn union(&mut self, i: &VI, j: &VI) -> bool {
         let root_i = self.find(i);
         let root_j = self.find(j);
 
@@ -1,2 +1,3 @@
 mod dsu;
+pub(crate) use dsu::Dsu;
 mod sort;
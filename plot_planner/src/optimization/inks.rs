@@ -0,0 +1,522 @@
+use crate::optimization::{FlightSettings, OptimizationProcess, OptimizationSettings, UniformGrid};
+use crate::palette::Palette;
+use crate::path::TourItem;
+use nalgebra::Point2;
+
+/// How [`InkSettings::schedule`] orders the colours
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InkOrder {
+    /// By decreasing lightness (L*), so that dark inks cover the light ones
+    #[default]
+    LightToDark,
+    /// So that consecutive colours overlap the least, i.e. the fewest passes land on wet paint.
+    /// Ties are broken from light to dark.
+    MinimalOverlap,
+}
+
+/// Sequencing the layers of a multi-ink job, one can at a time
+#[derive(Debug, Clone)]
+pub struct InkSettings {
+    pub order: InkOrder,
+    /// Landing, swapping the can, and taking off again
+    pub change_time: f32,
+    /// Paint of another colour is only sprayed over once dry
+    pub drying_delay: f32,
+    /// Passes closer than this overlap, see [`Self::new`]
+    overlap_radius: f32,
+    /// The budget of iterations is for each colour
+    pub flight: FlightSettings,
+}
+
+/// A step of a [`Schedule`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScheduleStep {
+    /// Back to the start to load this can of the palette, and wait for that long once done
+    Change { can: usize, wait: f32 },
+    /// A primitive of the `buffer`-th tagged buffer
+    Paint { buffer: usize, item: TourItem },
+}
+
+/// All the colours of a job, in painting order
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    pub steps: Vec<ScheduleStep>,
+    /// Until the last landing
+    pub duration: f32,
+}
+
+/// The primitives of all the buffers tagged with the same can
+#[derive(Debug, Default)]
+struct Layer {
+    points: Vec<Point2<f32>>,
+    dots: Vec<usize>,
+    edges: Vec<[usize; 2]>,
+    /// The buffer & index of each dot, then of each edge
+    origins: Vec<(usize, usize)>,
+}
+
+impl Layer {
+    fn endpoints(&self, item: TourItem) -> [Point2<f32>; 2] {
+        match item {
+            TourItem::Dot(index) => [self.points[self.dots[index]]; 2],
+            TourItem::Edge { index, reversed } => {
+                let [from, to] = self.edges[index];
+                let [from, to] = if reversed { [to, from] } else { [from, to] };
+                [self.points[from], self.points[to]]
+            }
+        }
+    }
+
+    /// Positions covering every primitive, at most `spacing` apart along the lines, with their item.
+    ///
+    /// Only the endpoints without a positive spacing.
+    fn samples(&self, spacing: f32) -> Vec<(Point2<f32>, TourItem)> {
+        let dots = (0..self.dots.len()).map(TourItem::Dot);
+        let edges = (0..self.edges.len()).map(|index| TourItem::Edge {
+            index,
+            reversed: false,
+        });
+        dots.chain(edges)
+            .flat_map(|item| {
+                let [from, to] = self.endpoints(item);
+                let steps = if spacing > 0.0 {
+                    ((to - from).norm() / spacing).ceil().max(1.0) as usize
+                } else {
+                    1
+                };
+                (0..=steps).map(move |k| (from + (to - from) * (k as f32 / steps as f32), item))
+            })
+            .collect()
+    }
+}
+
+/// Positions, queried within a radius
+struct Samples {
+    grid: UniformGrid,
+}
+
+impl Samples {
+    fn new(positions: &[Point2<f32>]) -> Self {
+        let (x, y): (Vec<f32>, Vec<f32>) = positions.iter().map(|p| (p.x, p.y)).unzip();
+        Self {
+            grid: UniformGrid::new(&crate::generation::PointSlice {
+                x: &x[..],
+                y: &y[..],
+            }),
+        }
+    }
+
+    /// Indices of the positions within `radius` of `query`
+    fn within(&self, query: Point2<f32>, radius: f32, out: &mut Vec<usize>) {
+        out.clear();
+        let mut candidates = Vec::new();
+        for ring in 0..=self.grid.ring_count() {
+            self.grid.ring(query, ring, &mut candidates);
+            if self.grid.ring_distance(ring) >= radius {
+                break;
+            }
+        }
+        out.extend(
+            candidates
+                .into_iter()
+                .filter(|i| (self.grid.position(*i) - query).norm() <= radius),
+        );
+    }
+}
+
+impl InkSettings {
+    /// Panics unless `overlap_radius` is finite and not negative
+    pub fn new(
+        order: InkOrder,
+        change_time: f32,
+        drying_delay: f32,
+        overlap_radius: f32,
+        flight: FlightSettings,
+    ) -> Self {
+        assert!(
+            overlap_radius.is_finite() && overlap_radius >= 0.0,
+            "the overlap radius should be finite and not negative"
+        );
+        Self {
+            order,
+            change_time,
+            drying_delay,
+            overlap_radius,
+            flight,
+        }
+    }
+
+    pub fn overlap_radius(&self) -> f32 {
+        self.overlap_radius
+    }
+
+    /// Orders the colours, optimizes a tour of each from `settings.start` (where the cans are swapped),
+    /// and waits at the changes so that overlapping passes of different colours dry in between.
+    ///
+    /// `buffers` are tagged with the index of their can within `palette`,
+    /// several buffers of the same can are painted together.
+    pub fn schedule<A>(
+        &self,
+        palette: &Palette,
+        buffers: &[(usize, &crate::generation::GenerationBufferVec<A>)],
+        settings: &OptimizationSettings,
+    ) -> Schedule
+    where
+        A: core::alloc::Allocator,
+    {
+        let mut layers = (0..palette.len())
+            .map(|_| Layer::default())
+            .collect::<Vec<_>>();
+        for (can, buffer) in buffers {
+            let layer = &mut layers[*can];
+            let offset = layer.points.len();
+            layer.points.extend(
+                buffer
+                    .points
+                    .x
+                    .iter()
+                    .zip(buffer.points.y.iter())
+                    .map(|(x, y)| Point2::new(*x, *y)),
+            );
+            layer
+                .dots
+                .extend(buffer.dots.index.iter().map(|i| i + offset));
+        }
+        // The edges after all the dots, as in the tours
+        for (b, (can, buffer)) in buffers.iter().enumerate() {
+            layers[*can]
+                .origins
+                .extend((0..buffer.dots.len()).map(|i| (b, i)));
+        }
+        let mut offsets = vec![0; palette.len()];
+        for (b, (can, buffer)) in buffers.iter().enumerate() {
+            let layer = &mut layers[*can];
+            let offset = offsets[*can];
+            layer.edges.extend(
+                buffer
+                    .edges
+                    .from
+                    .iter()
+                    .zip(buffer.edges.to.iter())
+                    .map(|(from, to)| [from + offset, to + offset]),
+            );
+            layer
+                .origins
+                .extend((0..buffer.edges.from.len()).map(|i| (b, i)));
+            offsets[*can] += buffer.points.len();
+        }
+
+        let samples = layers
+            .iter()
+            .map(|layer| layer.samples(self.overlap_radius))
+            .collect::<Vec<_>>();
+        let order = self.order(palette, &layers, &samples);
+
+        let mut steps = Vec::new();
+        let mut time = 0.0;
+        // The samples painted so far, and when they were
+        let mut painted: Vec<(Point2<f32>, f32)> = Vec::new();
+        let mut neighbours = Vec::new();
+        for can in order {
+            let layer = &layers[can];
+            let mut process = OptimizationProcess::from_primitives(
+                settings.clone(),
+                layer.points.clone(),
+                layer.dots.clone(),
+                layer.edges.clone(),
+                &vec![true; layer.edges.len()],
+            );
+            self.flight.optimize(&mut process);
+            let tour = process.current_tour();
+
+            let timeline = self.flight.timeline(
+                settings.start,
+                tour.iter().map(|item| (*item, layer.endpoints(*item))),
+            );
+            let mut started = vec![0.0; layer.origins.len()];
+            for (k, item) in tour.iter().enumerate() {
+                started[flat_index(layer, *item)] = timeline.times[2 * k + 1];
+            }
+
+            // The earliest take-off, so that every pass lands on dry paint
+            let ready = time + self.change_time;
+            let mut take_off: f32 = ready;
+            if !painted.is_empty() {
+                let index = Samples::new(&painted.iter().map(|(p, _)| *p).collect::<Vec<_>>());
+                for (position, item) in &samples[can] {
+                    index.within(*position, self.overlap_radius, &mut neighbours);
+                    for neighbour in &neighbours {
+                        take_off = take_off.max(
+                            painted[*neighbour].1 + self.drying_delay
+                                - started[flat_index(layer, *item)],
+                        );
+                    }
+                }
+            }
+
+            steps.push(ScheduleStep::Change {
+                can,
+                wait: take_off - ready,
+            });
+            let mut ended = vec![0.0; layer.origins.len()];
+            for (k, item) in tour.iter().enumerate() {
+                let (buffer, index) = layer.origins[flat_index(layer, *item)];
+                steps.push(ScheduleStep::Paint {
+                    buffer,
+                    item: match *item {
+                        TourItem::Dot(_) => TourItem::Dot(index),
+                        TourItem::Edge { reversed, .. } => TourItem::Edge { index, reversed },
+                    },
+                });
+                ended[flat_index(layer, *item)] = take_off + timeline.times[2 * k + 2];
+            }
+            painted.extend(
+                samples[can]
+                    .iter()
+                    .map(|(position, item)| (*position, ended[flat_index(layer, *item)])),
+            );
+
+            let landing = self.flight.transit_time(timeline.end(), settings.start);
+            time = take_off + timeline.duration() + landing;
+        }
+
+        Schedule {
+            steps,
+            duration: time,
+        }
+    }
+
+    /// The cans to paint, in order, see [`InkOrder`]
+    fn order(
+        &self,
+        palette: &Palette,
+        layers: &[Layer],
+        samples: &[Vec<(Point2<f32>, TourItem)>],
+    ) -> Vec<usize> {
+        const EXHAUSTIVE: usize = 8;
+
+        let mut order = (0..layers.len())
+            .filter(|can| !layers[*can].origins.is_empty())
+            .collect::<Vec<_>>();
+        order.sort_by(|a, b| {
+            palette.cans()[*b]
+                .colour
+                .l
+                .total_cmp(&palette.cans()[*a].colour.l)
+        });
+        if self.order == InkOrder::LightToDark || order.len() <= 1 {
+            return order;
+        }
+
+        // How many samples of each layer are covered by each other
+        let k = order.len();
+        let mut overlaps = vec![vec![0usize; k]; k];
+        let mut neighbours = Vec::new();
+        for (i, a) in order.iter().enumerate() {
+            let index = Samples::new(&samples[*a].iter().map(|(p, _)| *p).collect::<Vec<_>>());
+            for (j, b) in order.iter().enumerate().filter(|(j, _)| *j != i) {
+                for (position, _) in &samples[*b] {
+                    index.within(*position, self.overlap_radius, &mut neighbours);
+                    if !neighbours.is_empty() {
+                        overlaps[i][j] += 1;
+                        overlaps[j][i] += 1;
+                    }
+                }
+            }
+        }
+
+        let sequence = if k <= EXHAUSTIVE {
+            let mut best = ((0..k).collect::<Vec<_>>(), usize::MAX);
+            let mut current = Vec::with_capacity(k);
+            permutations(&overlaps, &mut current, &mut vec![false; k], 0, &mut best);
+            best.0
+        } else {
+            // Greedily, the lightest first
+            let mut sequence = vec![0];
+            let mut left = (1..k).collect::<Vec<_>>();
+            while !left.is_empty() {
+                let last = *sequence.last().unwrap();
+                let (position, _) = left
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, next)| overlaps[last][**next])
+                    .unwrap();
+                sequence.push(left.remove(position));
+            }
+            sequence
+        };
+        sequence.into_iter().map(|i| order[i]).collect()
+    }
+}
+
+/// Index of an item within [`Layer::origins`]
+fn flat_index(layer: &Layer, item: TourItem) -> usize {
+    match item {
+        TourItem::Dot(index) => index,
+        TourItem::Edge { index, .. } => layer.dots.len() + index,
+    }
+}
+
+/// Enumerates the sequences in lexicographic order, keeping the first of the least overlapping
+fn permutations(
+    overlaps: &[Vec<usize>],
+    current: &mut Vec<usize>,
+    used: &mut [bool],
+    cost: usize,
+    best: &mut (Vec<usize>, usize),
+) {
+    if cost >= best.1 {
+        return;
+    }
+    if current.len() == used.len() {
+        *best = (current.clone(), cost);
+        return;
+    }
+    for next in 0..used.len() {
+        if used[next] {
+            continue;
+        }
+        let step = current.last().map_or(0, |last| overlaps[*last][next]);
+        used[next] = true;
+        current.push(next);
+        permutations(overlaps, current, used, cost + step, best);
+        current.pop();
+        used[next] = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimization::test_utils;
+    use crate::palette::PaintCan;
+
+    fn settings() -> OptimizationSettings {
+        OptimizationSettings {
+            start: Point2::new(0.0, -2.0),
+            ..test_utils::settings([1.0, 1.0, 1.0], 0.1)
+        }
+    }
+
+    fn grid_of_dots(x0: f32) -> crate::generation::GenerationBufferVec<std::alloc::Global> {
+        let mut buffer = crate::generation::GenerationBufferVec::new();
+        for i in 0..16 {
+            buffer.points.x.push(x0 + (i % 4) as f32);
+            buffer.points.y.push((i / 4) as f32);
+            buffer.dots.index.push(i);
+        }
+        buffer
+    }
+
+    #[test]
+    fn colours_are_ordered_and_dried() {
+        let palette = Palette::new(vec![
+            PaintCan::from_srgb8("black", [0, 0, 0]),
+            PaintCan::from_srgb8("white", [255, 255, 255]),
+            PaintCan::from_srgb8("red", [200, 30, 30]),
+        ]);
+        // White under both, the red (lighter than black) beside them
+        let (white, black, red) = (grid_of_dots(0.0), grid_of_dots(0.5), grid_of_dots(20.0));
+        let buffers = [(0, &black), (1, &white), (2, &red)];
+        let inks = InkSettings::new(
+            InkOrder::LightToDark,
+            30.0,
+            600.0,
+            1.0,
+            FlightSettings {
+                transit_speed: 2.0,
+                spray_speed: 0.5,
+                dot_time: 1.0,
+                iterations: 256,
+            },
+        );
+
+        let schedule = inks.schedule(&palette, &buffers, &settings());
+        let changes = schedule
+            .steps
+            .iter()
+            .filter_map(|step| match step {
+                ScheduleStep::Change { can, wait } => Some((*can, *wait)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes.iter().map(|(can, _)| *can).collect::<Vec<_>>(),
+            [1, 2, 0]
+        );
+        // Only the black lands on other paint, the white being dry by then
+        assert_eq!(changes[0].1, 0.0);
+        assert_eq!(changes[1].1, 0.0);
+        assert!(changes[2].1 > 0.0);
+        let painted = schedule
+            .steps
+            .iter()
+            .filter(|step| matches!(step, ScheduleStep::Paint { .. }))
+            .count();
+        assert_eq!(painted, 48);
+
+        // Putting the red in between lets the white dry meanwhile
+        let schedule = InkSettings {
+            order: InkOrder::MinimalOverlap,
+            ..inks
+        }
+        .schedule(&palette, &buffers, &settings());
+        let changes = schedule
+            .steps
+            .iter()
+            .filter_map(|step| match step {
+                ScheduleStep::Change { can, .. } => Some(*can),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(changes, [1, 2, 0]);
+    }
+
+    #[test]
+    fn zero_radius_only_overlaps_in_place() {
+        let palette = Palette::new(vec![
+            PaintCan::from_srgb8("white", [255, 255, 255]),
+            PaintCan::from_srgb8("black", [0, 0, 0]),
+        ]);
+        let (white, black, apart) = (grid_of_dots(0.0), grid_of_dots(0.0), grid_of_dots(0.5));
+        let flight = FlightSettings {
+            transit_speed: 2.0,
+            spray_speed: 0.5,
+            dot_time: 1.0,
+            iterations: 64,
+        };
+        let inks = InkSettings::new(InkOrder::LightToDark, 30.0, 600.0, 0.0, flight);
+
+        let wait = |black: &crate::generation::GenerationBufferVec<std::alloc::Global>| {
+            let schedule = inks.schedule(&palette, &[(0, &white), (1, black)], &settings());
+            schedule
+                .steps
+                .iter()
+                .rev()
+                .find_map(|step| match step {
+                    ScheduleStep::Change { wait, .. } => Some(*wait),
+                    _ => None,
+                })
+                .unwrap()
+        };
+        assert!(wait(&black) > 0.0);
+        assert_eq!(wait(&apart), 0.0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_radius_is_rejected() {
+        InkSettings::new(
+            InkOrder::LightToDark,
+            0.0,
+            0.0,
+            -1.0,
+            FlightSettings {
+                transit_speed: 1.0,
+                spray_speed: 1.0,
+                dot_time: 1.0,
+                iterations: 0,
+            },
+        );
+    }
+}
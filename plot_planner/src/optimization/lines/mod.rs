@@ -1,2 +1,461 @@
-#[derive(Debug, Clone)]
-pub struct LinesOptimizationProcess {}
+use crate::optimization::gryf_algo::{CostFunction, MetricSequenceCostFunctionAdapter};
+use crate::optimization::polylines::{Polyline, PolylineSettings, merge_polylines};
+use crate::optimization::sequence::{SequenceOptimizationProcess, SequenceOptimizer};
+use crate::optimization::{OptimizationControlFlow, OptimizationSettings, rpp};
+use crate::path::TourItem;
+
+/// Ordering & orientation of line segments (and possibly dots),
+/// where only the deadheading between them is free to choose.
+///
+/// The segments are first joined into polylines (see [`merge_polylines`]),
+/// each being ordered as a single edge.
+pub type LinesOptimizationProcess = SequenceOptimizationProcess<Vec<Polyline>>;
+
+impl LinesOptimizationProcess {
+    /// `dots` & `edges` are indices within `points`,
+    /// `reversible[i]` tells whether the edge `i` may be painted `to -> from`
+    pub fn new(
+        settings: OptimizationSettings,
+        points: Vec<nalgebra::Point2<f32>>,
+        dots: Vec<usize>,
+        edges: Vec<[usize; 2]>,
+        reversible: &[bool],
+        polyline_settings: &PolylineSettings,
+    ) -> Self {
+        let (x, y): (Vec<f32>, Vec<f32>) = points.iter().map(|p| (p.x, p.y)).unzip();
+        let (from, to): (Vec<usize>, Vec<usize>) = edges.iter().map(|[a, b]| (*a, *b)).unzip();
+        let polylines = merge_polylines(
+            crate::generation::PointSlice {
+                x: &x[..],
+                y: &y[..],
+            },
+            crate::generation::EdgeSlice {
+                from: &from[..],
+                to: &to[..],
+            },
+            reversible,
+            polyline_settings,
+        );
+
+        let vertices = polylines
+            .iter()
+            .map(|polyline| polyline.vertices(&edges))
+            .collect();
+        let reversible = polylines
+            .iter()
+            .map(|polyline| polyline.reversible)
+            .collect();
+        Self::from_optimizer(
+            SequenceOptimizer::with_polylines(settings, points, dots, vertices, reversible),
+            polylines,
+        )
+    }
+}
+
+impl OptimizationSettings {
+    /// Orders & orients the line segments `edges` (indices within `points`):
+    /// [`rpp::path_scanning`], improved by [`rpp::rpp_2opt_optimize`].
+    ///
+    /// The segments are first joined into polylines (see [`merge_polylines`]),
+    /// each routed as a single required edge between its ends, and painted in one pass.
+    /// The tour lists the segments, weighed along the polylines.
+    ///
+    /// `reversible[i]` tells whether the segment `i` may be painted `to -> from`.
+    ///
+    /// The start is appended to the points, so that the tour leaves from index `points.len()`.
+    /// Its first leg is only accounted for if `include_start` is set.
+    pub fn optimize_lines(
+        &self,
+        points: crate::generation::PointSlice<&[f32], &[f32]>,
+        edges: crate::generation::EdgeSlice<&[usize], &[usize]>,
+        reversible: &[bool],
+    ) -> Result<rpp::RppTour<usize, f32>, rpp::Error> {
+        const L: usize = 8;
+        const TOLERANCE: f64 = 0.05;
+        const MAX_PASSES: usize = 8;
+
+        let n = points.len();
+        let x = points.x[..n]
+            .iter()
+            .copied()
+            .chain([self.start.x])
+            .collect::<Vec<_>>();
+        let y = points.y[..n]
+            .iter()
+            .copied()
+            .chain([self.start.y])
+            .collect::<Vec<_>>();
+        let adapter = FromStart {
+            inner: MetricSequenceCostFunctionAdapter {
+                metric: self.clone(),
+                points: crate::generation::PointSlice {
+                    x: &x[..],
+                    y: &y[..],
+                },
+            },
+            start: n,
+            include_start: self.include_start,
+        };
+
+        let pairs = edges
+            .from
+            .iter()
+            .zip(edges.to.iter())
+            .map(|(from, to)| [*from, *to])
+            .collect::<Vec<_>>();
+        let polylines = merge_polylines(
+            crate::generation::PointSlice {
+                x: &x[..n],
+                y: &y[..n],
+            },
+            edges,
+            reversible,
+            &PolylineSettings::default(),
+        );
+        let (from, to): (Vec<usize>, Vec<usize>) = polylines
+            .iter()
+            .map(|polyline| {
+                let vertices = polyline.vertices(&pairs);
+                (vertices[0], vertices[vertices.len() - 1])
+            })
+            .unzip();
+        let ends = crate::generation::EdgeSlice {
+            from: &from[..],
+            to: &to[..],
+        };
+        let polylines_reversible = polylines
+            .iter()
+            .map(|polyline| polyline.reversible)
+            .collect::<Vec<_>>();
+
+        let mut tour =
+            rpp::path_scanning::<_, _, L, _>(&adapter, &ends, &polylines_reversible, n, TOLERANCE)?;
+        let tour_reversible = traversed_polylines(&ends, &polylines_reversible, &tour)?
+            .into_iter()
+            .map(|(index, _)| polylines_reversible[index])
+            .collect::<Vec<_>>();
+        rpp::rpp_2opt_optimize(&adapter, &mut tour, &tour_reversible, MAX_PASSES);
+
+        // Back to the segments
+        let mut segments = crate::generation::EdgeSlice::with_capacity(pairs.len());
+        for (index, reversed) in traversed_polylines(&ends, &polylines_reversible, &tour)? {
+            let polyline = &polylines[index];
+            let items = if reversed {
+                polyline.reversed_edges().collect()
+            } else {
+                polyline.edges.clone()
+            };
+            for item in items {
+                let TourItem::Edge { index, reversed } = item else {
+                    unreachable!("polylines are made of edges")
+                };
+                let [from, to] = pairs[index];
+                let [from, to] = if reversed { [to, from] } else { [from, to] };
+                segments.from.push(from);
+                segments.to.push(to);
+            }
+        }
+        Ok(rpp::RppTour::new(&adapter, n, segments))
+    }
+}
+
+/// The polyline traversed by each edge of the tour, found by its `ends`, and whether it is reversed.
+/// The irreversible polylines are matched first, as they may only be found forward.
+fn traversed_polylines(
+    ends: &crate::generation::EdgeSlice<&[usize], &[usize]>,
+    reversible: &[bool],
+    tour: &rpp::RppTour<usize, f32>,
+) -> Result<Vec<(usize, bool)>, rpp::Error> {
+    let mut by_ends = std::collections::HashMap::<([usize; 2], bool), Vec<usize>>::new();
+    for (index, (from, to)) in ends.from.iter().zip(ends.to.iter()).enumerate() {
+        by_ends
+            .entry(([*from, *to], reversible[index]))
+            .or_default()
+            .push(index);
+    }
+    let mut find = |key| by_ends.get_mut(&key).and_then(Vec::pop);
+    tour.edges
+        .from
+        .iter()
+        .zip(tour.edges.to.iter())
+        .map(|(entry, exit)| {
+            find(([*entry, *exit], false))
+                .or_else(|| find(([*entry, *exit], true)))
+                .map(|index| (index, false))
+                .or_else(|| find(([*exit, *entry], true)).map(|index| (index, true)))
+                .ok_or(rpp::Error::InternalError)
+        })
+        .collect()
+}
+
+/// Without `include_start`, leaving `start` is free,
+/// and the vehicle is considered at rest where it arrives, as in `optimize_points`
+struct FromStart<CF> {
+    inner: CF,
+    start: usize,
+    include_start: bool,
+}
+
+impl<CF> CostFunction<usize> for FromStart<CF>
+where
+    CF: CostFunction<usize, Weight = f32>,
+{
+    type Weight = f32;
+
+    fn sequence_dependence(&self) -> bool {
+        self.inner.sequence_dependence()
+    }
+
+    fn compute<const L: usize>(
+        &self,
+        from: core::simd::Simd<usize, L>,
+        to: core::simd::Simd<usize, L>,
+    ) -> (core::simd::Simd<f32, L>, core::simd::Mask<isize, L>) {
+        use core::simd::{Select, cmp::SimdPartialEq};
+
+        let (weights, mask) = self.inner.compute(from, to);
+        if self.include_start {
+            return (weights, mask);
+        }
+        let leaving = from.simd_eq(core::simd::Simd::splat(self.start));
+        (leaving.select(core::simd::Simd::splat(0.0), weights), mask)
+    }
+
+    fn compute_sequence_dependent<const L: usize>(
+        &self,
+        prev: core::simd::Simd<usize, L>,
+        from: core::simd::Simd<usize, L>,
+        to: core::simd::Simd<usize, L>,
+    ) -> (core::simd::Simd<f32, L>, core::simd::Mask<isize, L>) {
+        use core::simd::{Select, cmp::SimdPartialEq};
+
+        if self.include_start {
+            return self.inner.compute_sequence_dependent(prev, from, to);
+        }
+        let (weights, mask) = self.inner.compute_sequence_dependent(prev, from, to);
+        let (at_rest, _) = self.compute(from, to);
+        let arrived = prev.simd_eq(core::simd::Simd::splat(self.start));
+        (arrived.select(at_rest, weights), mask)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimization::test_utils;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn lines_are_all_traversed() {
+        let (x, y) = test_utils::random_points(5, 200, [40.0, 40.0]);
+        let from = (0..100).map(|i| 2 * i).collect::<Vec<_>>();
+        let to = (0..100).map(|i| 2 * i + 1).collect::<Vec<_>>();
+        let settings = test_utils::settings([1.0, 1.0, 1.0], 0.1);
+
+        let tour = settings
+            .optimize_lines(
+                crate::generation::PointSlice {
+                    x: &x[..],
+                    y: &y[..],
+                },
+                crate::generation::EdgeSlice {
+                    from: &from[..],
+                    to: &to[..],
+                },
+                &[true; 100],
+            )
+            .unwrap();
+        let mut visited = tour
+            .edges
+            .from
+            .iter()
+            .zip(tour.edges.to.iter())
+            .map(|(a, b)| (*a.min(b), *a.max(b)))
+            .collect::<Vec<_>>();
+        visited.sort_unstable();
+        assert_eq!(
+            visited,
+            from.iter()
+                .copied()
+                .zip(to.iter().copied())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(tour.start, 200);
+
+        // The weights add up, and the deadheads are shorter than visiting the lines in order
+        let evaluated = rpp::RppTour::new(
+            &MetricSequenceCostFunctionAdapter {
+                metric: settings.clone(),
+                points: crate::generation::PointSlice {
+                    x: &[x.clone(), vec![0.0]].concat()[..],
+                    y: &[y.clone(), vec![0.0]].concat()[..],
+                },
+            },
+            200,
+            tour.edges.clone(),
+        );
+        assert!((evaluated.total() - tour.total()).abs() < 1e-2);
+        let in_order = (1..100)
+            .map(|i| ((x[2 * i] - x[2 * i - 1]).powi(2) + (y[2 * i] - y[2 * i - 1]).powi(2)).sqrt())
+            .sum::<f32>();
+        assert!(tour.deadhead < in_order, "{} {in_order}", tour.deadhead);
+    }
+
+    #[test]
+    fn leaving_the_start_may_be_free() {
+        use crate::optimization::MetricSequenceCostFunction;
+
+        let positions = test_utils::random_positions(6, 60, [20.0, 20.0]);
+        let (x, y): (Vec<f32>, Vec<f32>) = positions.iter().map(|p| (p.x, p.y)).unzip();
+        let from = (0..30).map(|i| 2 * i).collect::<Vec<_>>();
+        let to = (0..30).map(|i| 2 * i + 1).collect::<Vec<_>>();
+        // Far away, its leg would outweigh all the others
+        let settings = OptimizationSettings {
+            start: nalgebra::Point2::new(-500.0, -500.0),
+            include_start: false,
+            ..test_utils::settings([1.0, 1.0, 1.0], 0.1)
+        };
+
+        let tour = settings
+            .optimize_lines(
+                crate::generation::PointSlice {
+                    x: &x[..],
+                    y: &y[..],
+                },
+                crate::generation::EdgeSlice {
+                    from: &from[..],
+                    to: &to[..],
+                },
+                &[true; 30],
+            )
+            .unwrap();
+        assert_eq!(tour.edges.len(), 30);
+        assert!(tour.deadhead < 500.0, "{}", tour.deadhead);
+
+        // At rest on the first point, as with the dots
+        let vertices = tour.vertices()[1..]
+            .iter()
+            .map(|i| positions[*i])
+            .collect::<Vec<_>>();
+        let expected = (1..vertices.len())
+            .map(|k| {
+                settings.compute_metric_sequence_weight_scalar(
+                    k.checked_sub(2).map(|p| vertices[p]),
+                    vertices[k - 1],
+                    vertices[k],
+                )
+            })
+            .sum::<f32>();
+        assert!(
+            (tour.total() - expected).abs() < 1e-2,
+            "{} {expected}",
+            tour.total()
+        );
+    }
+
+    #[test]
+    fn polylines_are_painted_in_one_pass() {
+        let mut rng = rand_xoshiro::Xoroshiro64Star::seed_from_u64(7);
+        // Straight chains of 3 segments, each listed apart & some reversed
+        let (mut x, mut y, mut from, mut to) = (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+        for chain in 0..12 {
+            let (x0, y0) = (rng.random_range(0.0..60.0), rng.random_range(0.0..60.0));
+            for k in 0..4 {
+                x.push(x0 + k as f32);
+                y.push(y0);
+            }
+            for k in 0..3 {
+                let (a, b) = (4 * chain + k, 4 * chain + k + 1);
+                let (a, b) = if k == 1 { (b, a) } else { (a, b) };
+                from.push(a);
+                to.push(b);
+            }
+        }
+        let settings = test_utils::settings([1.0, 1.0, 1.0], 0.1);
+
+        let tour = settings
+            .optimize_lines(
+                crate::generation::PointSlice {
+                    x: &x[..],
+                    y: &y[..],
+                },
+                crate::generation::EdgeSlice {
+                    from: &from[..],
+                    to: &to[..],
+                },
+                &[true; 36],
+            )
+            .unwrap();
+        assert_eq!(tour.edges.len(), 36);
+        for chain in tour.edges.from.chunks(3).zip(tour.edges.to.chunks(3)) {
+            // Within a chain, each segment starts where the previous one ends
+            assert!(chain.0.iter().all(|v| v / 4 == chain.0[0] / 4));
+            assert_eq!(chain.0[1..], chain.1[..2]);
+        }
+    }
+
+    #[test]
+    fn irreversible_edges_stay_forward() {
+        let points = [(0.0, 0.0), (10.0, 0.0), (10.0, 5.0), (0.0, 5.0)]
+            .map(|(x, y)| nalgebra::Point2::new(x, y))
+            .to_vec();
+        let settings = OptimizationSettings {
+            start: nalgebra::Point2::new(10.0, 0.0),
+            ..test_utils::settings([1.0, 1.0, 1.0], 0.1)
+        };
+        // From the start, painting the first edge backward would be free
+        let mut process = LinesOptimizationProcess::new(
+            settings,
+            points,
+            Vec::new(),
+            vec![[0, 1], [2, 3]],
+            &[false, true],
+            &Default::default(),
+        );
+        while let OptimizationControlFlow::Ongoing { .. } = process.step(64) {}
+        assert!(process.current_tour().contains(&TourItem::Edge {
+            index: 0,
+            reversed: false
+        }));
+    }
+
+    #[test]
+    fn irreversible_segments_are_painted_forward() {
+        let (x, y) = test_utils::random_points(8, 80, [40.0, 40.0]);
+        let from = (0..40).map(|i| 2 * i).collect::<Vec<_>>();
+        let to = (0..40).map(|i| 2 * i + 1).collect::<Vec<_>>();
+        let reversible = (0..40).map(|i| i % 3 != 0).collect::<Vec<_>>();
+        let settings = test_utils::settings([1.0, 1.0, 1.0], 0.1);
+        let optimize = |reversible: &[bool]| {
+            settings
+                .optimize_lines(
+                    crate::generation::PointSlice {
+                        x: &x[..],
+                        y: &y[..],
+                    },
+                    crate::generation::EdgeSlice {
+                        from: &from[..],
+                        to: &to[..],
+                    },
+                    reversible,
+                )
+                .unwrap()
+        };
+
+        let tour = optimize(&reversible);
+        assert_eq!(tour.edges.len(), 40);
+        // Each segment begins at an even point, so that odd entries are reversed segments
+        let mut reversals = 0;
+        for entry in tour.edges.from.iter() {
+            if entry % 2 == 1 {
+                assert!(reversible[entry / 2], "segment {} reversed", entry / 2);
+                reversals += 1;
+            }
+        }
+        assert!(reversals > 0);
+
+        // Left free, some of them would be reversed
+        let free = optimize(&[true; 40]);
+        assert!(free.edges.from.iter().any(|entry| entry % 6 == 1));
+    }
+}
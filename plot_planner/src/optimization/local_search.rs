@@ -0,0 +1,709 @@
+// 2-opt, Or-opt and Or-3opt improvement of point tours, under the asymmetric,
+// sequence-dependent cost of `OptimizationSettings`.
+//
+// The tour is seen as a vertex sequence `V`, whose cost is the sum of the steps
+// `c(V[k-2], V[k-1], V[k])`. A move rearranges `V` as a concatenation of blocks of it,
+// some of them reversed. Only the steps spanning the start of a block change,
+// apart from the ones within reversed blocks, which are walked backwards: their cost is read
+// from prefix sums of the sequence walked backwards, so that any move is evaluated in
+// constant time, whatever the length of the reversed run.
+
+use super::gryf_algo::MetricSequenceCostFunction;
+use super::{OptimizationSettings, PointsTour, UniformGrid};
+
+/// Energies differing by less than this are considered equal,
+/// so that float noise does not make the search cycle.
+const EPSILON: f32 = 1e-4;
+
+/// See [`OptimizationSettings::improve_points`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LocalSearchSettings {
+    /// Number of nearest points considered as new neighbours of each point in the tour
+    pub neighbours: usize,
+    pub two_opt: bool,
+    pub or_opt: bool,
+    /// Longest run of points moved by Or-opt
+    pub max_segment: usize,
+    /// Insertion of runs of any length between neighbours, possibly reversed
+    pub or_3opt: bool,
+    /// Sweeps over the tour, at most
+    pub max_passes: usize,
+}
+
+impl Default for LocalSearchSettings {
+    fn default() -> Self {
+        Self {
+            neighbours: 8,
+            two_opt: true,
+            or_opt: true,
+            max_segment: 3,
+            or_3opt: true,
+            max_passes: 50,
+        }
+    }
+}
+
+/// The positions `start..=end` of the current sequence
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Block {
+    pub(super) start: usize,
+    pub(super) end: usize,
+    pub(super) reversed: bool,
+}
+
+impl Block {
+    const fn forward(start: usize, end: usize) -> Self {
+        Self {
+            start,
+            end,
+            reversed: false,
+        }
+    }
+
+    const fn len(&self) -> usize {
+        self.end + 1 - self.start
+    }
+}
+
+/// A rearrangement of the sequence, as the concatenation of (non empty) blocks
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Move {
+    blocks: [Block; 4],
+    count: usize,
+}
+
+impl Move {
+    fn new(blocks: impl IntoIterator<Item = Option<Block>>) -> Self {
+        let mut m = Self {
+            blocks: [Block::forward(0, 0); 4],
+            count: 0,
+        };
+        for block in blocks.into_iter().flatten() {
+            if block.start <= block.end {
+                m.blocks[m.count] = block;
+                m.count += 1;
+            }
+        }
+        m
+    }
+
+    pub(super) fn blocks(&self) -> &[Block] {
+        &self.blocks[..self.count]
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct LocalSearch<CF> {
+    metric: CF,
+    positions: Vec<nalgebra::Point2<f32>>,
+    /// Point indices, in visiting order
+    sequence: Vec<usize>,
+    /// Number of leading points which never move, e.g. the start
+    fixed: usize,
+    /// Position of each point within the sequence, `usize::MAX` if it is not part of it
+    position_of: Vec<usize>,
+    /// `forward[k]` is the cost of the sequence up to `k`,
+    /// `backward[k]` the sum of the steps `c(V[t], V[t-1], V[t-2])` for `t <= k`
+    forward: Vec<f32>,
+    backward: Vec<f32>,
+    /// The points whose don't-look bit is cleared, in the order they are looked at
+    queue: std::collections::VecDeque<usize>,
+    active: Vec<bool>,
+}
+
+impl<CF> LocalSearch<CF>
+where
+    CF: MetricSequenceCostFunction<Weight = f32>,
+{
+    pub(super) fn new(
+        metric: CF,
+        positions: Vec<nalgebra::Point2<f32>>,
+        sequence: Vec<usize>,
+        fixed: usize,
+    ) -> Self {
+        let mut search = Self {
+            metric,
+            position_of: vec![usize::MAX; positions.len()],
+            active: vec![false; positions.len()],
+            positions,
+            sequence,
+            fixed,
+            forward: Vec::new(),
+            backward: Vec::new(),
+            queue: std::collections::VecDeque::new(),
+        };
+        search.update();
+        for k in fixed..search.sequence.len() {
+            search.activate(search.sequence[k]);
+        }
+        search
+    }
+
+    pub(super) fn metric(&self) -> &CF {
+        &self.metric
+    }
+
+    pub(super) fn positions(&self) -> &[nalgebra::Point2<f32>] {
+        &self.positions
+    }
+
+    pub(super) fn sequence(&self) -> &[usize] {
+        &self.sequence
+    }
+
+    /// Position of a point within the sequence, `usize::MAX` if it is not part of it
+    pub(super) fn position(&self, point: usize) -> usize {
+        self.position_of[point]
+    }
+
+    pub(super) fn into_sequence(self) -> Vec<usize> {
+        self.sequence
+    }
+
+    pub(super) fn cost(&self) -> f32 {
+        self.forward.last().copied().unwrap_or(0.0)
+    }
+
+    #[inline]
+    fn weight(&self, prev: Option<usize>, from: usize, to: usize) -> f32 {
+        self.metric.compute_metric_sequence_weight_scalar(
+            prev.map(|p| self.positions[p]),
+            self.positions[from],
+            self.positions[to],
+        )
+    }
+
+    /// Recomputes the prefix sums and positions from scratch
+    pub(super) fn update(&mut self) {
+        let len = self.sequence.len();
+        self.forward = vec![0.0; len];
+        self.backward = vec![0.0; len];
+        if len == 0 {
+            return;
+        }
+        self.update_range(0, len - 1);
+    }
+
+    /// Recomputes the prefix sums and positions once the positions `lo..=hi` changed.
+    /// Only the steps up to `hi + 2` change, the following sums are shifted.
+    fn update_range(&mut self, lo: usize, hi: usize) {
+        let len = self.sequence.len();
+        let last = (hi + 2).min(len.saturating_sub(1));
+        let (forward, backward) = (self.forward[last], self.backward[last]);
+        for k in lo.max(1)..=last {
+            let v = &self.sequence;
+            let prev = (k >= 2).then(|| v[k - 2]);
+            let step = self.weight(prev, v[k - 1], v[k]);
+            let backward_step = if k >= 2 {
+                self.weight(Some(v[k]), v[k - 1], v[k - 2])
+            } else {
+                0.0
+            };
+            self.forward[k] = self.forward[k - 1] + step;
+            self.backward[k] = self.backward[k - 1] + backward_step;
+        }
+
+        let (forward, backward) = (self.forward[last] - forward, self.backward[last] - backward);
+        for k in last + 1..len {
+            self.forward[k] += forward;
+            self.backward[k] += backward;
+        }
+        for k in lo..=hi.min(len.saturating_sub(1)) {
+            self.position_of[self.sequence[k]] = k;
+        }
+    }
+
+    /// The cost change of a move
+    pub(super) fn delta(&self, m: &Move) -> f32 {
+        let blocks = m.blocks();
+        let len = self.sequence.len();
+
+        // The point at a position of the rearranged sequence
+        let vertex = |k: usize| {
+            let mut offset = 0;
+            for block in blocks {
+                if k < offset + block.len() {
+                    let i = k - offset;
+                    let position = if block.reversed {
+                        block.end - i
+                    } else {
+                        block.start + i
+                    };
+                    return self.sequence[position];
+                }
+                offset += block.len();
+            }
+            unreachable!("position {k} is out of the sequence")
+        };
+
+        // The two steps after the start of each block, in both sequences
+        // (the first block has no step before its start)
+        let mut old_steps = [usize::MAX; 8];
+        let mut new_steps = [usize::MAX; 8];
+        let mut count = 0;
+        let mut delta = 0.0;
+        let mut offset = 0;
+        for block in blocks {
+            for d in usize::from(offset == 0)..2 {
+                old_steps[count] = block.start + d;
+                new_steps[count] = offset + d;
+                count += 1;
+            }
+
+            // The steps within a reversed block are walked backwards
+            if block.reversed && block.len() > 2 {
+                delta += (self.backward[block.end] - self.backward[block.start + 1])
+                    - (self.forward[block.end] - self.forward[block.start + 1]);
+            }
+            offset += block.len();
+        }
+
+        // Blocks of a single point share steps with the next one
+        let distinct = |steps: &mut [usize]| {
+            steps.sort_unstable();
+            let mut previous = usize::MAX;
+            steps.iter_mut().for_each(|k| {
+                if *k == previous || *k == 0 || *k >= len {
+                    *k = usize::MAX;
+                } else {
+                    previous = *k;
+                }
+            });
+        };
+        distinct(&mut old_steps[..count]);
+        distinct(&mut new_steps[..count]);
+
+        for k in old_steps[..count].iter().filter(|k| **k != usize::MAX) {
+            delta -= self.forward[*k] - self.forward[*k - 1];
+        }
+        for k in new_steps[..count].iter().filter(|k| **k != usize::MAX) {
+            let prev = (*k >= 2).then(|| vertex(*k - 2));
+            delta += self.weight(prev, vertex(*k - 1), vertex(*k));
+        }
+        delta
+    }
+
+    pub(super) fn apply(&mut self, m: &Move) {
+        // The leading and trailing blocks in place are left untouched
+        let blocks = m.blocks();
+        let mut offset = 0;
+        let mut lo = usize::MAX;
+        let mut hi = 0;
+        for block in blocks {
+            if block.start != offset || block.reversed {
+                lo = lo.min(offset);
+                hi = hi.max(offset + block.len() - 1);
+            }
+            offset += block.len();
+        }
+        if lo > hi {
+            return;
+        }
+
+        let mut run = Vec::with_capacity(hi + 1 - lo);
+        let mut offset = 0;
+        for block in blocks {
+            if offset + block.len() > lo && offset <= hi {
+                let points = &self.sequence[block.start..=block.end];
+                if block.reversed {
+                    run.extend(points.iter().rev());
+                } else {
+                    run.extend_from_slice(points);
+                }
+            }
+            offset += block.len();
+        }
+        self.sequence[lo..=hi].copy_from_slice(&run);
+        self.update_range(lo, hi);
+    }
+
+    /// Reversing the positions `a..=b`
+    pub(super) fn two_opt(&self, a: usize, b: usize) -> Option<Move> {
+        let len = self.sequence.len();
+        if a < self.fixed || b <= a || b >= len {
+            return None;
+        }
+        Some(Move::new([
+            (a > 0).then(|| Block::forward(0, a - 1)),
+            Some(Block {
+                start: a,
+                end: b,
+                reversed: true,
+            }),
+            Some(Block::forward(b + 1, len - 1)),
+        ]))
+    }
+
+    /// Moving the positions `s..=e` right after the position `p`, possibly reversed
+    pub(super) fn or_opt(&self, s: usize, e: usize, p: usize, reversed: bool) -> Option<Move> {
+        let len = self.sequence.len();
+        let in_place = p + 1 == s && !reversed;
+        if s < self.fixed.max(1)
+            || p + 1 < self.fixed
+            || e < s
+            || e >= len
+            || p >= len
+            || (s <= p && p <= e)
+            || in_place
+        {
+            return None;
+        }
+        let segment = Block {
+            start: s,
+            end: e,
+            reversed,
+        };
+        Some(if p < s {
+            Move::new([
+                Some(Block::forward(0, p)),
+                Some(segment),
+                Some(Block::forward(p + 1, s - 1)),
+                Some(Block::forward(e + 1, len - 1)),
+            ])
+        } else {
+            Move::new([
+                Some(Block::forward(0, s - 1)),
+                Some(Block::forward(e + 1, p)),
+                Some(segment),
+                Some(Block::forward(p + 1, len - 1)),
+            ])
+        })
+    }
+
+    /// Exchanging the runs `i+1..c` and `c..=d` (Or-3opt, a segment insertion of any length),
+    /// the second one possibly reversed
+    pub(super) fn or_3opt(&self, i: usize, c: usize, d: usize, reversed: bool) -> Option<Move> {
+        let len = self.sequence.len();
+        if i + 1 < self.fixed.max(1) || c <= i + 1 || d < c || d >= len {
+            return None;
+        }
+        Some(Move::new([
+            Some(Block::forward(0, i)),
+            Some(Block {
+                start: c,
+                end: d,
+                reversed,
+            }),
+            Some(Block::forward(i + 1, c - 1)),
+            Some(Block::forward(d + 1, len - 1)),
+        ]))
+    }
+
+    /// Clears the don't-look bit of a point: it will be looked at again
+    fn activate(&mut self, point: usize) {
+        if !self.active[point] {
+            self.active[point] = true;
+            self.queue.push_back(point);
+        }
+    }
+
+    /// Only `points` will be looked at, and the ones around the moves they lead to
+    pub(super) fn focus(&mut self, points: impl IntoIterator<Item = usize>) {
+        for point in self.queue.drain(..) {
+            self.active[point] = false;
+        }
+        for point in points {
+            self.activate(point);
+        }
+    }
+
+    /// Looks for an improving move around the next active point, and applies it.
+    /// Returns `None` once every point has its don't-look bit set, i.e. at a local minimum.
+    ///
+    /// Only the moves making a point adjacent to one of its `neighbours` are tried.
+    pub(super) fn step(
+        &mut self,
+        neighbours: &[Vec<usize>],
+        settings: &LocalSearchSettings,
+    ) -> Option<bool> {
+        let point = self.queue.pop_front()?;
+        self.active[point] = false;
+        let i = self.position_of[point];
+        if i == usize::MAX || i < self.fixed {
+            return Some(false);
+        }
+
+        let Some(m) = self.improving_move(i, neighbours, settings) else {
+            return Some(false);
+        };
+        self.apply(&m);
+
+        // The points around the changed steps are worth another look
+        self.activate(point);
+        let mut offset = 0usize;
+        for block in m.blocks() {
+            for k in [offset.wrapping_sub(1), offset, offset + block.len() - 1] {
+                if let Some(point) = self.sequence.get(k) {
+                    self.activate(*point);
+                }
+            }
+            offset += block.len();
+        }
+        Some(true)
+    }
+
+    /// Applies improving moves until none is left, or out of passes.
+    ///
+    /// A pass looks at each point worth a look when it begins, the first one at every point.
+    pub(super) fn run(&mut self, neighbours: &[Vec<usize>], settings: &LocalSearchSettings) {
+        for _ in 0..settings.max_passes {
+            if self.queue.is_empty() {
+                break;
+            }
+            for _ in 0..self.queue.len() {
+                self.step(neighbours, settings);
+            }
+        }
+
+        // Gets rid of the rounding errors accumulated by the updates
+        self.update();
+    }
+
+    /// The first improving move around the position `i`, if any
+    fn improving_move(
+        &self,
+        i: usize,
+        neighbours: &[Vec<usize>],
+        settings: &LocalSearchSettings,
+    ) -> Option<Move> {
+        let len = self.sequence.len();
+        let point = self.sequence[i];
+        let improving = |m: &Move| self.delta(m) < -EPSILON;
+
+        for neighbour in neighbours[point].iter() {
+            let j = self.position_of[*neighbour];
+            if j == usize::MAX || j == i {
+                continue;
+            }
+
+            if settings.two_opt {
+                // The neighbour becomes the successor of the point, or its predecessor
+                let m = if j > i {
+                    self.two_opt(i + 1, j)
+                } else {
+                    self.two_opt(j + 1, i)
+                };
+                if let Some(m) = m.filter(improving) {
+                    return Some(m);
+                }
+            }
+
+            if settings.or_opt {
+                // A run starting with the point goes right after the neighbour,
+                // or, reversed, right before it
+                for end in i..(i + settings.max_segment).min(len) {
+                    let forward = self.or_opt(i, end, j, false);
+                    let reversed = j.checked_sub(1).and_then(|p| self.or_opt(i, end, p, true));
+                    if let Some(m) = forward.filter(improving).or(reversed.filter(improving)) {
+                        return Some(m);
+                    }
+                }
+            }
+
+            if settings.or_3opt && j > i + 1 && i + 1 < len {
+                // The neighbour becomes the successor of the point, and the run it starts
+                // (or ends, reversed) is inserted before the former successor,
+                // right after one of the neighbours of the latter
+                for other in neighbours[self.sequence[i + 1]].iter() {
+                    let k = self.position_of[*other];
+                    if k == usize::MAX || k <= i + 1 {
+                        continue;
+                    }
+                    let m = if j <= k {
+                        self.or_3opt(i, j, k, false)
+                    } else {
+                        self.or_3opt(i, k, j, true)
+                    };
+                    if let Some(m) = m.filter(improving) {
+                        return Some(m);
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+/// The `k` nearest points of each point
+pub(super) fn neighbour_lists(positions: &[nalgebra::Point2<f32>], k: usize) -> Vec<Vec<usize>> {
+    let x = positions.iter().map(|p| p.x).collect::<Vec<_>>();
+    let y = positions.iter().map(|p| p.y).collect::<Vec<_>>();
+    let grid = UniformGrid::new(&crate::generation::PointSlice {
+        x: &x[..],
+        y: &y[..],
+    });
+    positions
+        .iter()
+        .enumerate()
+        .map(|(i, position)| {
+            grid.k_nearest(*position, k + 1)
+                .into_iter()
+                .map(|(j, _)| j)
+                .filter(|j| *j != i)
+                .take(k)
+                .collect()
+        })
+        .collect()
+}
+
+impl OptimizationSettings {
+    /// Improves a tour (e.g. from [`Self::optimize_points`]) with 2-opt and Or-opt moves,
+    /// until it is a local minimum or out of passes.
+    ///
+    /// The moves are evaluated exactly, asymmetry and direction changes included,
+    /// and only the ones making a point adjacent to one of its nearest points are tried.
+    pub fn improve_points(
+        &self,
+        points: crate::generation::PointSlice<&[f32], &[f32]>,
+        tour: &mut PointsTour,
+        settings: &LocalSearchSettings,
+    ) {
+        self.improve_points_with(self.clone(), points, tour, settings);
+    }
+
+    /// [`Self::improve_points`], weighing the steps with another `metric`
+    /// (e.g. a [`crate::optimization::KinematicCost`] to minimise the time),
+    /// from the start of these settings
+    pub fn improve_points_with<CF>(
+        &self,
+        metric: CF,
+        points: crate::generation::PointSlice<&[f32], &[f32]>,
+        tour: &mut PointsTour,
+        settings: &LocalSearchSettings,
+    ) where
+        CF: MetricSequenceCostFunction<Weight = f32>,
+    {
+        let n = points.len();
+        if n < 2 {
+            return;
+        }
+
+        // The start is appended as an extra point, as in `optimize_points`
+        let positions = points.x[..n]
+            .iter()
+            .zip(points.y[..n].iter())
+            .map(|(x, y)| nalgebra::Point2::new(*x, *y))
+            .chain([self.start])
+            .collect::<Vec<_>>();
+        let start = n;
+
+        let neighbours = neighbour_lists(&positions, settings.neighbours);
+
+        let mut sequence = Vec::with_capacity(n + 1);
+        if self.include_start {
+            sequence.push(start);
+        }
+        sequence.extend_from_slice(&tour.order);
+        let fixed = usize::from(self.include_start);
+
+        let mut search = LocalSearch::new(metric, positions, sequence, fixed);
+        search.run(&neighbours, settings);
+
+        tour.cost = search.cost();
+        let mut order = search.into_sequence();
+        order.drain(..fixed);
+        tour.order = order;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimization::test_utils;
+    use rand::{Rng, SeedableRng};
+
+    fn settings(include_start: bool) -> OptimizationSettings {
+        OptimizationSettings {
+            include_start,
+            ..test_utils::settings([1.5, 0.1, 1.0], 2.0)
+        }
+    }
+
+    #[test]
+    fn delta_matches_recomputation() {
+        let settings = settings(true);
+        let (x, y) = test_utils::random_points(3, 40, [50.0, 50.0]);
+        let positions = x
+            .iter()
+            .zip(y.iter())
+            .map(|(x, y)| nalgebra::Point2::new(*x, *y))
+            .collect::<Vec<_>>();
+        let search = LocalSearch::new(settings.clone(), positions.clone(), (0..40).collect(), 1);
+
+        let mut rng = rand_xoshiro::Xoroshiro64Star::seed_from_u64(5);
+        let mut checked = 0;
+        while checked < 500 {
+            let (s, e, p) = (
+                rng.random_range(0..40),
+                rng.random_range(0..40),
+                rng.random_range(0..40),
+            );
+            let m = match rng.random_range(0..5) {
+                0 => search.two_opt(s, e),
+                1 => search.or_opt(s, (s + e % 3).min(39), p, false),
+                2 => search.or_opt(s, (s + e % 3).min(39), p, true),
+                3 => search.or_3opt(s, e, p, false),
+                _ => search.or_3opt(s, e, p, true),
+            };
+            let Some(m) = m else { continue };
+
+            let mut moved = LocalSearch::new(
+                settings.clone(),
+                positions.clone(),
+                search.sequence.clone(),
+                1,
+            );
+            moved.apply(&m);
+            let incremental = moved.cost();
+            moved.update();
+            assert!((incremental - moved.cost()).abs() < 1e-2, "{m:?}");
+            let expected = moved.cost() - search.cost();
+            let delta = search.delta(&m);
+            assert!(
+                (delta - expected).abs() < 1e-2,
+                "{m:?}: {delta} != {expected}"
+            );
+            checked += 1;
+        }
+    }
+
+    #[test]
+    fn improves_nearest_neighbour() {
+        let (x, y) = test_utils::random_points(3, 500, [50.0, 50.0]);
+        let points = crate::generation::PointSlice {
+            x: &x[..],
+            y: &y[..],
+        };
+
+        for include_start in [true, false] {
+            let settings = settings(include_start);
+            let mut tour = settings.optimize_points(points.clone());
+            let initial = tour.cost;
+            settings.improve_points(points.clone(), &mut tour, &LocalSearchSettings::default());
+            assert!(tour.cost < initial * 0.95, "{} -> {}", initial, tour.cost);
+
+            let mut order = tour.order.clone();
+            order.sort_unstable();
+            assert_eq!(order, (0..500).collect::<Vec<_>>());
+
+            // The cost reported is the one of the tour
+            let mut check = settings.optimize_points(points.clone());
+            check.order = tour.order.clone();
+            let reported = tour.cost;
+            settings.improve_points(
+                points.clone(),
+                &mut check,
+                &LocalSearchSettings {
+                    max_passes: 0,
+                    ..Default::default()
+                },
+            );
+            assert!(
+                (check.cost - reported).abs() < 1e-2 * reported,
+                "{} {}",
+                check.cost,
+                reported
+            );
+        }
+    }
+}
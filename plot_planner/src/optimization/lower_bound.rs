@@ -0,0 +1,390 @@
+// Lower bounds of the energy of tours, to tell how far from optimal a tour can be.
+//
+// - The entry bound: every item of the tour is entered once, at the cost of the cheapest way in
+//   (a relaxation of the assignment problem, keeping the in-degree constraints only).
+// - The assignment bound: every dot is entered and left once, for dots up to
+//   `ASSIGNMENT_LIMIT`, with the Hungarian algorithm.
+// - The Held–Karp bound: the heaviest 1-tree over subgradient ascent, on distances scaled by
+//   `SpecificEnergyCost::min_spec_e`, for dots up to `HELD_KARP_LIMIT`, the plain MST above.
+//
+// Direction changes are only accounted for by their lowest possible weight, i.e. none
+// unless the penalty is negative.
+
+use super::gryf_algo::mst;
+use super::{OptimizationSettings, PointsTour, UniformGrid};
+
+/// The assignment bound is `O(n³)`
+const ASSIGNMENT_LIMIT: usize = 300;
+/// Each subgradient iteration of the Held–Karp bound is `O(n²)`
+const HELD_KARP_LIMIT: usize = 1000;
+const HELD_KARP_ITERATIONS: usize = 30;
+
+/// How far a cost can be from optimal, relative to a lower bound: `0.05` is within 5%
+pub fn optimality_gap(cost: f32, lower_bound: f32) -> f32 {
+    if cost <= lower_bound {
+        0.0
+    } else if lower_bound <= 0.0 {
+        f32::INFINITY
+    } else {
+        (cost - lower_bound) / lower_bound
+    }
+}
+
+impl PointsTour {
+    /// See [`optimality_gap`] and [`OptimizationSettings::points_lower_bound`]
+    pub fn gap(&self, lower_bound: f32) -> f32 {
+        optimality_gap(self.cost, lower_bound)
+    }
+}
+
+impl OptimizationSettings {
+    /// A lower bound of the cost of any tour of the points
+    pub fn points_lower_bound(&self, points: crate::generation::PointSlice<&[f32], &[f32]>) -> f32 {
+        let positions = points.x[..points.len()]
+            .iter()
+            .zip(points.y[..points.len()].iter())
+            .map(|(x, y)| nalgebra::Point2::new(*x, *y))
+            .collect::<Vec<_>>();
+        let dots = (0..positions.len()).collect::<Vec<_>>();
+        self.lower_bound(&positions, &dots, &[])
+    }
+
+    /// A lower bound of the energy of any tour painting the dots & edges, which are indices
+    /// within `points`
+    pub(super) fn lower_bound(
+        &self,
+        points: &[nalgebra::Point2<f32>],
+        dots: &[usize],
+        edges: &[[usize; 2]],
+    ) -> f32 {
+        let items = dots.len() + edges.len();
+        if items == 0 {
+            return 0.0;
+        }
+
+        // Direction changes, at every vertex but the first and last ones
+        let vertices = items + edges.len() + usize::from(self.include_start);
+        let penalties = self.penalty.0.min(0.0) * (vertices.saturating_sub(2) as f32);
+
+        let mut bound = self.entry_bound(points, dots, edges);
+        if edges.is_empty() {
+            let positions = dots.iter().map(|i| points[*i]).collect::<Vec<_>>();
+            if dots.len() <= ASSIGNMENT_LIMIT {
+                bound = bound.max(self.assignment_bound(&positions));
+            }
+            bound = bound.max(self.held_karp_bound(&positions));
+        }
+        bound + penalties
+    }
+
+    fn arc(&self, from: nalgebra::Point2<f32>, to: nalgebra::Point2<f32>) -> f32 {
+        self.specific_energy.weight_scalar(to - from)
+    }
+
+    /// The painted edges, each in its cheapest direction,
+    /// and the cheapest way into every item but the first one (unless it is entered from the start)
+    fn entry_bound(
+        &self,
+        points: &[nalgebra::Point2<f32>],
+        dots: &[usize],
+        edges: &[[usize; 2]],
+    ) -> f32 {
+        let painted: f32 = edges
+            .iter()
+            .map(|[a, b]| {
+                let (a, b) = (points[*a], points[*b]);
+                self.arc(a, b).min(self.arc(b, a))
+            })
+            .sum();
+
+        // Where each item can be entered, and left, then the start
+        let mut ends = Vec::with_capacity(dots.len() + 2 * edges.len() + 1);
+        let mut item_of = Vec::with_capacity(ends.capacity());
+        for (item, dot) in dots.iter().enumerate() {
+            ends.push(points[*dot]);
+            item_of.push(item);
+        }
+        for (item, [a, b]) in edges.iter().enumerate() {
+            ends.extend([points[*a], points[*b]]);
+            item_of.extend([dots.len() + item; 2]);
+        }
+        let items = dots.len() + edges.len();
+        let starts = ends.len();
+        if self.include_start {
+            ends.push(self.start);
+            item_of.push(items);
+        }
+
+        let min_spec_e = self.specific_energy.min_spec_e();
+        if min_spec_e <= 0.0 {
+            // A move may then be free (or even pay back), and the search of the grid
+            // could not stop early: every entry is bounded by the farthest move instead
+            let [min, max] = ends.iter().fold(
+                [
+                    nalgebra::Point2::new(f32::MAX, f32::MAX),
+                    nalgebra::Point2::new(f32::MIN, f32::MIN),
+                ],
+                |[min, max], p| [min.inf(p), max.sup(p)],
+            );
+            let entered = min_spec_e * (max - min).norm();
+            let entered_items = items - usize::from(!self.include_start);
+            return painted + entered * (entered_items as f32);
+        }
+
+        let x = ends.iter().map(|p| p.x).collect::<Vec<_>>();
+        let y = ends.iter().map(|p| p.y).collect::<Vec<_>>();
+        let grid = UniformGrid::new(&crate::generation::PointSlice {
+            x: &x[..],
+            y: &y[..],
+        });
+
+        let mut entries = vec![f32::INFINITY; items];
+        for end in 0..starts {
+            let item = item_of[end];
+            let entry = grid.nearest_by(
+                ends[end],
+                |distance| distance * min_spec_e,
+                |from| {
+                    if item_of[from] == item {
+                        f32::INFINITY
+                    } else {
+                        self.arc(ends[from], ends[end])
+                    }
+                },
+            );
+            if let Some((_, cost)) = entry {
+                entries[item] = entries[item].min(cost);
+            }
+        }
+
+        // Without the start, the first item is not entered
+        let entries = entries.iter().map(|e| if e.is_finite() { *e } else { 0.0 });
+        let entered = entries.clone().sum::<f32>()
+            - if self.include_start {
+                0.0
+            } else {
+                entries.fold(0.0, f32::max)
+            };
+        painted + entered
+    }
+
+    /// The assignment problem over the dots and a depot, standing for the start (or nowhere)
+    /// before the first dot, and for nowhere after the last one
+    fn assignment_bound(&self, positions: &[nalgebra::Point2<f32>]) -> f32 {
+        let n = positions.len();
+        let depot = n;
+        let cost = |from: usize, to: usize| -> f64 {
+            if from == to {
+                f64::INFINITY
+            } else if to == depot {
+                0.0
+            } else if from == depot {
+                if self.include_start {
+                    self.arc(self.start, positions[to]) as f64
+                } else {
+                    0.0
+                }
+            } else {
+                self.arc(positions[from], positions[to]) as f64
+            }
+        };
+        hungarian(n + 1, cost) as f32
+    }
+
+    /// The Held–Karp bound over the euclidean distances (the start included),
+    /// times the lowest specific energy
+    fn held_karp_bound(&self, positions: &[nalgebra::Point2<f32>]) -> f32 {
+        let mut positions = positions.to_vec();
+        if self.include_start {
+            positions.push(self.start);
+        }
+        let n = positions.len();
+        if n < 2 {
+            return 0.0;
+        }
+        let distance = |a: usize, b: usize| (positions[a] - positions[b]).norm();
+        // Scaled by a negative energy, the heaviest tree would bound nothing
+        let min_spec_e = self.specific_energy.min_spec_e().max(0.0);
+
+        if n > HELD_KARP_LIMIT {
+            let x = positions.iter().map(|p| p.x).collect::<Vec<_>>();
+            let y = positions.iter().map(|p| p.y).collect::<Vec<_>>();
+            let tree = mst::prim_euclidean(&crate::generation::PointSlice {
+                x: &x[..],
+                y: &y[..],
+            });
+            return tree.map_or(0.0, |tree| tree.weight() * min_spec_e);
+        }
+
+        // A path is a cycle through a depot at no distance from anything.
+        // The 1-trees are rooted at the depot: a spanning tree of the vertices,
+        // and the two lightest links to the depot, whose weights are the penalties.
+        let mut penalties = vec![0.0f32; n];
+        let mut best = 0.0f32;
+        let mut upper = None;
+        let mut scale = 2.0f32;
+        let mut stale = 0;
+        for _ in 0..HELD_KARP_ITERATIONS {
+            let Ok(tree) = mst::prim(n, |a, b| distance(a, b) + penalties[a] + penalties[b]) else {
+                break;
+            };
+            let upper = *upper.get_or_insert_with(|| {
+                let tour = mst::double_tree_tour(&tree, 0);
+                tour.windows(2).map(|w| distance(w[0], w[1])).sum::<f32>()
+            });
+
+            let mut degrees = vec![0i32; n];
+            for [a, b] in tree.edges() {
+                degrees[*a] += 1;
+                degrees[*b] += 1;
+            }
+            let mut lightest = (0..n).collect::<Vec<_>>();
+            lightest.select_nth_unstable_by(1, |a, b| penalties[*a].total_cmp(&penalties[*b]));
+            for v in &lightest[..2] {
+                degrees[*v] += 1;
+            }
+
+            let bound = tree.weight() + penalties[lightest[0]] + penalties[lightest[1]]
+                - 2.0 * penalties.iter().sum::<f32>();
+            if bound > best {
+                best = bound;
+                stale = 0;
+            } else {
+                stale += 1;
+                if stale >= 3 {
+                    scale /= 2.0;
+                    stale = 0;
+                }
+            }
+
+            // Every vertex of degree 2: the 1-tree is a tour, the bound is tight
+            let norm = degrees
+                .iter()
+                .map(|d| ((d - 2) * (d - 2)) as f32)
+                .sum::<f32>();
+            if norm == 0.0 {
+                break;
+            }
+            let step = scale * (upper - bound).max(0.0) / norm;
+            for (penalty, degree) in penalties.iter_mut().zip(degrees.iter()) {
+                *penalty += step * ((degree - 2) as f32);
+            }
+        }
+        best * min_spec_e
+    }
+}
+
+/// The minimal total cost of assigning each row to a distinct column of the square matrix,
+/// in `O(n³)` (Hungarian algorithm, with potentials)
+fn hungarian(n: usize, cost: impl Fn(usize, usize) -> f64) -> f64 {
+    // 1-based, the row 0 and column 0 being sentinels
+    let mut u = vec![0.0f64; n + 1];
+    let mut v = vec![0.0f64; n + 1];
+    let mut row_of = vec![0usize; n + 1];
+    let mut way = vec![0usize; n + 1];
+    for row in 1..=n {
+        row_of[0] = row;
+        let mut column = 0;
+        let mut min = vec![f64::INFINITY; n + 1];
+        let mut used = vec![false; n + 1];
+        loop {
+            used[column] = true;
+            let r = row_of[column];
+            let mut delta = f64::INFINITY;
+            let mut next = 0;
+            for c in 1..=n {
+                if used[c] {
+                    continue;
+                }
+                let reduced = cost(r - 1, c - 1) - u[r] - v[c];
+                if reduced < min[c] {
+                    min[c] = reduced;
+                    way[c] = column;
+                }
+                if min[c] < delta {
+                    delta = min[c];
+                    next = c;
+                }
+            }
+            if !delta.is_finite() {
+                // No complete assignment
+                return 0.0;
+            }
+            for c in 0..=n {
+                if used[c] {
+                    u[row_of[c]] += delta;
+                    v[c] -= delta;
+                } else {
+                    min[c] -= delta;
+                }
+            }
+            column = next;
+            if row_of[column] == 0 {
+                break;
+            }
+        }
+        loop {
+            let previous = way[column];
+            row_of[column] = row_of[previous];
+            column = previous;
+            if column == 0 {
+                break;
+            }
+        }
+    }
+    (1..=n).map(|c| cost(row_of[c] - 1, c - 1)).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimization::{LocalSearchSettings, test_utils};
+
+    #[test]
+    fn hungarian_matches_brute_force() {
+        let costs = [[4.0, 1.0, 3.0], [2.0, 0.0, 5.0], [3.0, 2.0, 2.0]];
+        assert_eq!(hungarian(3, |r, c| costs[r][c]), 5.0);
+    }
+
+    #[test]
+    fn bounds_are_below_tours() {
+        let (x, y) = test_utils::random_points(17, 200, [40.0, 40.0]);
+        let points = crate::generation::PointSlice {
+            x: &x[..],
+            y: &y[..],
+        };
+
+        for (cost, include_start) in [((1.0, 1.0, 1.0), false), ((1.5, 0.1, 1.0), true)] {
+            let settings = OptimizationSettings {
+                include_start,
+                ..test_utils::settings([cost.0, cost.1, cost.2], 2.0)
+            };
+            let mut tour = settings.optimize_points(points.clone());
+            settings.improve_points(points.clone(), &mut tour, &LocalSearchSettings::default());
+
+            let bound = settings.points_lower_bound(points.clone());
+            assert!(bound > 0.0 && bound <= tour.cost, "{bound} {}", tour.cost);
+            // Loose, as the bound knows little of the direction changes
+            assert!(tour.gap(bound) < 1.0, "{}", tour.gap(bound));
+        }
+    }
+
+    #[test]
+    fn free_moves_keep_the_bound_below() {
+        let (x, y) = test_utils::random_points(5, 150, [40.0, 40.0]);
+        let points = crate::generation::PointSlice {
+            x: &x[..],
+            y: &y[..],
+        };
+
+        // Going down pays back more than it costs
+        let settings = OptimizationSettings {
+            include_start: false,
+            ..test_utils::settings([2.0, -1.0, 1.0], 0.0)
+        };
+        assert!(settings.specific_energy.min_spec_e() < 0.0);
+        let tour = settings.optimize_points(points.clone());
+        let bound = settings.points_lower_bound(points.clone());
+        assert!(bound <= tour.cost, "{bound} {}", tour.cost);
+    }
+}
@@ -3,148 +3,409 @@
 // see https://pubsonline.informs.org/doi/epdf/10.1287/opre.43.3.399
 
 mod gryf_algo;
+pub use gryf_algo::{
+    CostFunction, MetricSequenceCostFunction, MetricSequenceCostFunctionAdapter, floyd_warshall,
+    mst, rpp,
+};
 
 mod settings;
-pub use settings::{DirectionChangePenalty, OptimizationSettings, SpecificEnergyCost};
+pub use settings::{
+    CostField, CostFieldError, DirectionChangePenalty, FieldAwareCost, KinematicCost,
+    OptimizationSettings, SpecificEnergyCost, VelocityProfile,
+};
 
 mod common;
-use common::OptimizationControlFlow;
+pub use common::{FlightSettings, OptimizationControlFlow};
+
+mod sequence;
+pub use sequence::{SequenceItems, SequenceOptimizationProcess};
+
+mod spatial_index;
+pub use spatial_index::UniformGrid;
+
+mod local_search;
+pub use local_search::LocalSearchSettings;
+
+mod construction;
+pub use construction::TourConstruction;
+
+mod lower_bound;
+pub use lower_bound::optimality_gap;
+
+mod serpentine;
+pub use serpentine::Sweep;
+
+mod repair;
+pub use repair::PointsEdit;
+
+mod transit;
+pub use transit::{ObstacleAwareCost, RoutingError, TransitRouter};
+
+mod fleet;
+pub use fleet::{Balance, FleetSettings, Mission, Partitioning};
+
+mod inks;
+pub use inks::{InkOrder, InkSettings, Schedule, ScheduleStep};
+
+mod sorties;
+pub use sorties::{Sortie, SortieError, SortieSettings};
+
+mod polylines;
+pub use polylines::{Polyline, PolylineSettings, merge_polylines};
 
 mod lines;
-use lines::LinesOptimizationProcess;
+pub use lines::LinesOptimizationProcess;
 
 mod dots;
-use dots::DotsOptimizationProcess;
+pub use dots::{
+    AnnealingSettings, CoolingSchedule, DotsAnnealingProcess, DotsGeneticProcess,
+    DotsLocalSearchProcess, DotsLocalSearchSettings, DotsOptimizationProcess, GeneticSettings,
+};
+
+#[cfg(test)]
+mod test_utils;
 
 #[derive(Debug, Clone)]
 pub enum OptimizationProcess {
     Lines(LinesOptimizationProcess),
     Dots(DotsOptimizationProcess),
-    Mixed(()),
+    /// Dots, too many of them for [`DotsOptimizationProcess`]
+    LargeDots(DotsLocalSearchProcess),
+    /// Lines & dots, ordered together
+    Mixed(LinesOptimizationProcess),
 }
 
 impl OptimizationProcess {
+    /// Above this many dots, [`DotsLocalSearchProcess`] is picked
+    pub const LARGE_DOTS: usize = 2000;
+
+    /// Picks the process matching the primitives of the buffer
+    pub fn new<A>(
+        buffer: &crate::generation::GenerationBufferVec<A>,
+        settings: OptimizationSettings,
+    ) -> Self
+    where
+        A: core::alloc::Allocator,
+    {
+        let points = buffer
+            .points
+            .x
+            .iter()
+            .zip(buffer.points.y.iter())
+            .map(|(x, y)| nalgebra::Point2::new(*x, *y))
+            .collect::<Vec<_>>();
+        let dots = buffer.dots.index.to_vec();
+        let edges = buffer
+            .edges
+            .from
+            .iter()
+            .zip(buffer.edges.to.iter())
+            .map(|(from, to)| [*from, *to])
+            .collect::<Vec<_>>();
+        let reversible = vec![true; edges.len()];
+        Self::from_primitives(settings, points, dots, edges, &reversible)
+    }
+
+    /// Picks the process matching the primitives,
+    /// `dots` & `edges` being indices within `points`,
+    /// `reversible[i]` telling whether the edge `i` may be painted `to -> from`
+    pub fn from_primitives(
+        settings: OptimizationSettings,
+        points: Vec<nalgebra::Point2<f32>>,
+        dots: Vec<usize>,
+        edges: Vec<[usize; 2]>,
+        reversible: &[bool],
+    ) -> Self {
+        match (dots.is_empty(), edges.is_empty()) {
+            (_, true) if dots.len() > Self::LARGE_DOTS => Self::LargeDots(
+                DotsLocalSearchProcess::new(settings, points, dots, Default::default()),
+            ),
+            (_, true) => Self::Dots(DotsOptimizationProcess::new(settings, points, dots)),
+            (true, false) => Self::Lines(LinesOptimizationProcess::new(
+                settings,
+                points,
+                dots,
+                edges,
+                reversible,
+                &Default::default(),
+            )),
+            (false, false) => Self::Mixed(LinesOptimizationProcess::new(
+                settings,
+                points,
+                dots,
+                edges,
+                reversible,
+                &Default::default(),
+            )),
+        }
+    }
+
     /// Runs a chunk of optimization work.
     /// Returns control to the caller with progress updates.
-    fn step(&mut self, iterations: usize) -> OptimizationControlFlow<()> {
-        todo!()
+    pub fn step(&mut self, iterations: usize) -> OptimizationControlFlow<()> {
+        match self {
+            Self::Lines(process) | Self::Mixed(process) => process.step(iterations),
+            Self::Dots(process) => process.step(iterations),
+            Self::LargeDots(process) => process.step(iterations),
+        }
     }
 
     /// Returns the current best tour/path found so far.
-    /// It can be lines, it can be dots
-    fn current_tour(&self) -> &[usize] {
-        todo!()
+    /// It can be lines, it can be dots, indexing the ones of the generation buffer.
+    pub fn current_tour(&self) -> &[crate::path::TourItem] {
+        match self {
+            Self::Lines(process) | Self::Mixed(process) => process.current_tour(),
+            Self::Dots(process) => process.current_tour(),
+            Self::LargeDots(process) => process.current_tour(),
+        }
+    }
+
+    /// Energy of [`Self::current_tour`]
+    pub fn energy(&self) -> f32 {
+        match self {
+            Self::Lines(process) | Self::Mixed(process) => process.energy(),
+            Self::Dots(process) => process.energy(),
+            Self::LargeDots(process) => process.energy(),
+        }
+    }
+
+    /// No tour is cheaper than this, so that the current one is within
+    /// [`Self::gap`] of optimal
+    pub fn lower_bound(&self) -> f32 {
+        match self {
+            Self::Lines(process) | Self::Mixed(process) => process.lower_bound(),
+            Self::Dots(process) => process.lower_bound(),
+            Self::LargeDots(process) => process.lower_bound(),
+        }
     }
+
+    /// See [`optimality_gap`]
+    pub fn gap(&self) -> f32 {
+        optimality_gap(self.energy(), self.lower_bound())
+    }
+
+    /// Makes [`Self::step`] converge once [`Self::gap`] is at most `target_gap`
+    pub fn set_target_gap(&mut self, target_gap: Option<f32>) {
+        match self {
+            Self::Lines(process) | Self::Mixed(process) => process.set_target_gap(target_gap),
+            Self::Dots(process) => process.set_target_gap(target_gap),
+            Self::LargeDots(process) => process.set_target_gap(target_gap),
+        }
+    }
+}
+
+/// An ordering of points, see [`OptimizationSettings::optimize_points`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PointsTour {
+    /// Indices of the points, in visiting order
+    pub order: Vec<usize>,
+    /// Total weight of the tour, including the leg from the start if it is included
+    pub cost: f32,
 }
 
-/*
 impl OptimizationSettings {
-    pub fn optimize_points(&self, points: &[crate::path::Point]) -> Vec<usize> {
-        // This is a fully connnected graph, as such we use an Adjacency Matrix approach
-        use gryf::core::create::Create;
-        let mut graph =
-            gryf::Graph::<
-                usize,
-                _,
-                _,
-                gryf::storage::adj_matrix::AdjMatrix<_, _, _, gryf::core::id::DefaultId>,
-            >::new_directed_in(gryf::storage::adj_matrix::AdjMatrix::with_capacity(
-                points.len(),
-                points.len() * points.len(),
-            ){
-
-        graph.extend_with_vertices(points.iter().enumerate().map(|(i, v)| dbg!(i)){
-        graph.connect_vertices(|src, dst| )
-            if src != dst )
-                let dist_vector = &points[*dst].position - &points[*src].position;
-                let dist_vector_adjusted = )
-                    let y = dist_vector.y
-                        * if dist_vector.y < 0.0 )
-                            // Negative y is going up
-                            self.specific_energy.up_weight_factor()
-                        } else {
-                            self.specific_energy.down_weight_factor()
-                        };
-                    let x = dist_vector.x * self.specific_energy.forward_weight_factor();
-                    nalgebra::Vector2::new(x, y)
-                };
-
-                Some(dist_vector_adjusted)
-            } else {
-                None
-            }
-        }{
+    /// Orders the points with a nearest neighbour heuristic, in the sense of the cost function,
+    /// i.e. taking the direction change of each step into account.
+    ///
+    /// The candidates are looked for around the current point with a [`UniformGrid`],
+    /// so that the construction is close to `O(n log n)` on evenly spread points.
+    ///
+    /// The tour begins at `start`. Its first leg is only accounted for in the cost
+    /// (and in the direction change of the second one) if `include_start` is set.
+    pub fn optimize_points(
+        &self,
+        points: crate::generation::PointSlice<&[f32], &[f32]>,
+    ) -> PointsTour {
+        use core::simd::{self, cmp::SimdPartialOrd};
+        use gryf_algo::CostFunction;
+        const L: usize = 8;
 
-        struct CostFunction<'a> {
-            penalty: f32,
-            points: &'a [crate::path::Point],
-            parameters: &'a OptimizationSettings,
+        let n = points.len();
+        if n == 0 {
+            return PointsTour {
+                order: Vec::new(),
+                cost: 0.0,
+            };
         }
 
-        impl<'a, G> gryf_algo::tsp::TravellingSalesmanCostFunction<G, nalgebra::Vector2<f32>, f32>
-            for CostFunction<'a>
-;
-            G: gryf::core::GraphBase,
-            G::VertexId: core::convert::Into<usize> + Clone,
-        {
-            fn sequence_dependent(&self) -> bool {
-                false
-            }
-
-            fn get_weight(&self, edge: &nalgebra::Vector2<f32>, sequence: &[G::VertexId]) -> f32 {
-                let norm = edge.norm();
-                if sequence.len() < 2 {
-                    return norm;
-                }
+        // The start is appended as an extra point, so that it can be gathered like the others
+        let x = points.x[..n]
+            .iter()
+            .copied()
+            .chain([self.start.x])
+            .collect::<Vec<_>>();
+        let y = points.y[..n]
+            .iter()
+            .copied()
+            .chain([self.start.y])
+            .collect::<Vec<_>>();
+        let start = n;
+        let adapter = gryf_algo::MetricSequenceCostFunctionAdapter {
+            metric: self.clone(),
+            points: crate::generation::PointSlice {
+                x: &x[..],
+                y: &y[..],
+            },
+        };
 
-                let a_id: usize = (sequence[sequence.len() - 2].clone()).into();
-                let b_id: usize = (sequence[sequence.len() - 1].clone()).into();
-                let previous_vector = self.points[b_id].position - self.points[a_id].position;
+        // No cost can be lower than this, at a given distance from the current point
+        let min_spec_e = self.specific_energy.min_spec_e();
+        let min_penalty = self.penalty.0.min(0.0);
+        let lower_bound = |distance: f32| min_spec_e * distance + min_penalty;
 
-                let dot = edge.normalize().dot(&previous_vector.normalize(){
+        let mut index = UniformGrid::new(&points);
+        let mut candidates = Vec::new();
+        let mut order = Vec::with_capacity(n);
+        let (mut prev, mut current) = (None, start);
+        while !index.is_empty() {
+            let position = nalgebra::Point2::new(x[current], y[current]);
+            let mut best = (f32::INFINITY, usize::MAX);
+            for ring in 0..index.ring_count() {
+                candidates.clear();
+                index.ring(position, ring, &mut candidates);
 
-                // Between 0 and 1
-                let dot_normalized = dot.mul_add(-0.5, 0.5)
-                assert!(
-                    dot_normalized >= -f32::EPSILON && dot_normalized <= 1.0 + f32::EPSILON,
-                    "dot penalty: )dot_normalized:?}"
-                )
+                for chunk in candidates.chunks(L) {
+                    let to = simd::Simd::<usize, L>::load_or(chunk, simd::Simd::splat(usize::MAX));
+                    let from = simd::Simd::splat(current);
+                    let (weights, valid) = match prev {
+                        None => adapter.compute(from, to),
+                        Some(prev) => {
+                            adapter.compute_sequence_dependent(simd::Simd::splat(prev), from, to)
+                        }
+                    };
 
-                // The penalty has a constant and a multiplicative component
-                let penalized = dot_normalized.clamp(0.0, 1.0) * self.penalty + norm;
+                    let lanes = simd::Mask::<isize, L>::from_array(core::array::from_fn(|i| {
+                        i < chunk.len()
+                    }));
+                    let candidates =
+                        lanes & valid & weights.simd_lt(simd::Simd::splat(best.0)).cast();
+                    for i in 0..L {
+                        if candidates.test(i) && weights[i] < best.0 {
+                            best = (weights[i], chunk[i]);
+                        }
+                    }
+                }
 
-                penalized
+                if best.0 <= lower_bound(index.ring_distance(ring)) {
+                    break;
+                }
             }
 
-            fn get_const(&self) -> Option<f32> {
+            // Every weight being NaN would leave us stuck
+            let next = if best.1 == usize::MAX {
+                (0..n).find(|i| index.contains(*i)).unwrap()
+            } else {
+                best.1
+            };
+            index.remove(next);
+            order.push(next);
+
+            // Without the start, the vehicle is considered at rest on the first point
+            prev = if current == start && !self.include_start {
                 None
-            }
+            } else {
+                Some(current)
+            };
+            current = next;
+        }
+
+        let cost = self.points_cost(|i| nalgebra::Point2::new(x[i], y[i]), &order);
+        PointsTour { order, cost }
+    }
+
+    /// The weight of visiting the points in order,
+    /// from [`Self::start`] if `include_start` is set
+    fn points_cost(
+        &self,
+        position: impl Fn(usize) -> nalgebra::Point2<f32>,
+        order: &[usize],
+    ) -> f32 {
+        self.points_cost_with(self, position, order)
+    }
+
+    /// [`Self::points_cost`], weighing the steps with another `metric`
+    fn points_cost_with<CF>(
+        &self,
+        metric: &CF,
+        position: impl Fn(usize) -> nalgebra::Point2<f32>,
+        order: &[usize],
+    ) -> f32
+    where
+        CF: MetricSequenceCostFunction<Weight = f32>,
+    {
+        let position = |i: Option<usize>| i.map_or(self.start, &position);
+        let mut sequence = Vec::with_capacity(order.len() + 1);
+        if self.include_start {
+            sequence.push(None);
         }
+        sequence.extend(order.iter().map(|i| Some(*i)));
+        (1..sequence.len())
+            .map(|k| {
+                let prev = (k >= 2).then(|| position(sequence[k - 2]));
+                metric.compute_metric_sequence_weight_scalar(
+                    prev,
+                    position(sequence[k - 1]),
+                    position(sequence[k]),
+                )
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gryf_algo::MetricSequenceCostFunction;
+
+    fn settings(include_start: bool) -> OptimizationSettings {
+        OptimizationSettings {
+            include_start,
+            ..test_utils::settings([1.5, 0.1, 1.0], 2.0)
+        }
+    }
 
-        let cost_function = CostFunction {
-            penalty: 200.0,
-            points,
-            parameters: self,
+    #[test]
+    fn scalar_weight_matches_simd() {
+        let settings = settings(true);
+        let [prev, from, to] =
+            [(0.0, 0.0), (1.0, 2.0), (-3.0, -1.0)].map(|(x, y)| nalgebra::Point2::new(x, y));
+        let splat = |p: nalgebra::Point2<f32>| crate::generation::Point::<4> {
+            x: core::simd::Simd::splat(p.x),
+            y: core::simd::Simd::splat(p.y),
         };
 
-        println!("STARTING TRAVELLING SALESMAN")
-        // TODO: Add a method that allows for penalizing method change.
-        // That requires the algorithm chosen to take into account the previous direction when evaluating next steps.
-        let result = gryf_algo::tsp::TravellingSalesmanBuilder::on(&graph)
-            .start_at(0.into())
-            .with_weight(cost_function)
-            .approximate();
-
-        result
-            .expect("tsp failed")
-            .consume_tour()
-            .into_iter()
-            .map(|v| v.into())
-            .collect::<Vec<_>>()
-
-        /*let mut builder = graph_builder::GraphBuilder::new()
-        .csr_layout(CsrLayout::Deduplicated)
-        .edges_with_values(edges)
-        .build();*/
+        let (simd, mask) =
+            settings.compute_metric_sequence_weight::<4>(Some(splat(prev)), splat(from), splat(to));
+        let scalar = settings.compute_metric_sequence_weight_scalar(Some(prev), from, to);
+        assert!(mask.all());
+        assert!((simd[0] - scalar).abs() < 1e-4, "{simd:?} {scalar}");
     }
-}*/
+
+    #[test]
+    fn going_down_is_cheaper() {
+        let cost = SpecificEnergyCost::from_penalties(1.5, 0.1, 1.0);
+        let down = cost.weight_scalar(nalgebra::Vector2::new(0.0, 1.0));
+        let up = cost.weight_scalar(nalgebra::Vector2::new(0.0, -1.0));
+        assert!((down - 0.1).abs() < 1e-5, "{down}");
+        assert!((up - 1.5).abs() < 1e-5, "{up}");
+    }
+
+    #[test]
+    fn points_are_ordered_from_the_start() {
+        let x = [3.0, 1.0, 2.0, 10.0];
+        let y = [0.0; 4];
+        let points = crate::generation::PointSlice {
+            x: &x[..],
+            y: &y[..],
+        };
+
+        let tour = settings(true).optimize_points(points.clone());
+        assert_eq!(tour.order, vec![1, 2, 0, 3]);
+        assert!((tour.cost - 10.0).abs() < 1e-4, "{}", tour.cost);
+
+        // Without the start, the first leg is free
+        let tour = settings(false).optimize_points(points);
+        assert_eq!(tour.order, vec![1, 2, 0, 3]);
+        assert!((tour.cost - 9.0).abs() < 1e-4, "{}", tour.cost);
+    }
+}
@@ -0,0 +1,382 @@
+use crate::generation::{EdgeSlice, PointSlice};
+use crate::path::TourItem;
+
+/// How [`merge_polylines`] joins segments
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PolylineSettings {
+    /// Endpoints closer than this are considered shared
+    pub tolerance: f32,
+    /// Segments turning by more than this angle (in radians) where they meet are not joined
+    pub max_corner: f32,
+}
+
+impl Default for PolylineSettings {
+    fn default() -> Self {
+        Self {
+            tolerance: 1e-3,
+            max_corner: core::f32::consts::FRAC_PI_3,
+        }
+    }
+}
+
+/// Segments painted as one continuous pass
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Polyline {
+    /// The joined edges, in painting order, see [`TourItem::Edge`]
+    pub edges: Vec<TourItem>,
+    /// Whether the polyline may also be painted from its end, i.e. all its edges can be
+    pub reversible: bool,
+    /// Whether it ends where it starts
+    pub closed: bool,
+}
+
+impl Polyline {
+    /// The start of the first edge, then the end of each edge (indices within the points)
+    pub fn vertices(&self, edges: &[[usize; 2]]) -> Vec<usize> {
+        let mut vertices = Vec::with_capacity(self.edges.len() + 1);
+        for (k, item) in self.edges.iter().enumerate() {
+            let TourItem::Edge { index, reversed } = *item else {
+                unreachable!("polylines are made of edges")
+            };
+            let [from, to] = edges[index];
+            let [from, to] = if reversed { [to, from] } else { [from, to] };
+            if k == 0 {
+                vertices.push(from);
+            }
+            vertices.push(to);
+        }
+        vertices
+    }
+
+    /// The edges, painted from the end of the polyline
+    pub fn reversed_edges(&self) -> impl Iterator<Item = TourItem> + '_ {
+        self.edges.iter().rev().map(|item| match *item {
+            TourItem::Edge { index, reversed } => TourItem::Edge {
+                index,
+                reversed: !reversed,
+            },
+            dot => dot,
+        })
+    }
+}
+
+/// Joins the segments `edges` (indices within `points`) sharing endpoints into polylines,
+/// the straightest continuations first, splitting at the corners sharper than allowed.
+///
+/// `reversible[i]` tells whether the edge `i` may be painted `to -> from`.
+/// Segments are only joined when the polyline can be painted with none of its
+/// irreversible edges reversed.
+pub fn merge_polylines(
+    points: PointSlice<&[f32], &[f32]>,
+    edges: EdgeSlice<&[usize], &[usize]>,
+    reversible: &[bool],
+    settings: &PolylineSettings,
+) -> Vec<Polyline> {
+    let n = edges.len();
+    debug_assert_eq!(reversible.len(), n);
+    let position = |i: usize| nalgebra::Point2::new(points.x[i], points.y[i]);
+    // The point at end `0` (`from`) or `1` (`to`) of an edge
+    let end = |e: usize, end: usize| if end == 0 { edges.from[e] } else { edges.to[e] };
+
+    // Endpoints within the tolerance share a node
+    let mut nodes = Nodes::new(settings.tolerance);
+    let node_of = (0..2 * n)
+        .map(|k| nodes.find_or_insert(position(end(k / 2, k % 2))))
+        .collect::<Vec<_>>();
+    let mut incident = vec![Vec::new(); nodes.positions.len()];
+    for (k, node) in node_of.iter().enumerate() {
+        // Edges collapsed to a node are kept on their own
+        if node_of[k ^ 1] != *node {
+            incident[*node].push(k);
+        }
+    }
+
+    // The possible joints, by how much the pass turns through them
+    let mut joints = Vec::new();
+    for ends in incident.iter() {
+        for (i, a) in ends.iter().enumerate() {
+            for b in ends[i + 1..].iter() {
+                let outward =
+                    |k: usize| position(end(k / 2, 1 - k % 2)) - position(end(k / 2, k % 2));
+                let (u, v) = (outward(*a), outward(*b));
+                let cos = (u.dot(&v) / (u.norm() * v.norm())).clamp(-1.0, 1.0);
+                let turn = core::f32::consts::PI - cos.acos();
+                if a / 2 != b / 2 && turn <= settings.max_corner {
+                    joints.push((turn, *a, *b));
+                }
+            }
+        }
+    }
+    joints.sort_by(|(a, ..), (b, ..)| a.total_cmp(b));
+
+    // Each end joins at most one other, the components being paths or cycles
+    let mut link = vec![None; 2 * n];
+    let mut components = Components::new(reversible);
+    for (_, a, b) in joints {
+        if link[a].is_some() || link[b].is_some() {
+            continue;
+        }
+        // Walking in by `a` and out by `b`, an edge is reversed if entered by its `from`
+        let flipped = (a % 2 == 0) != (b % 2 == 1);
+        if components.union(a / 2, b / 2, flipped) {
+            link[a] = Some(b);
+            link[b] = Some(a);
+        }
+    }
+
+    // Walks the polyline entering `first` by the end `entry`
+    let mut visited = vec![false; n];
+    let walk = |visited: &mut [bool], first: usize, entry: usize| {
+        let mut polyline = Polyline {
+            edges: Vec::new(),
+            reversible: true,
+            closed: false,
+        };
+        let mut k = 2 * first + entry;
+        loop {
+            let e = k / 2;
+            visited[e] = true;
+            polyline.edges.push(TourItem::Edge {
+                index: e,
+                reversed: k % 2 == 1,
+            });
+            polyline.reversible &= reversible[e];
+            match link[k ^ 1] {
+                Some(next) if next / 2 == first => {
+                    polyline.closed = true;
+                    break;
+                }
+                Some(next) => k = next,
+                None => break,
+            }
+        }
+
+        // Irreversible edges agree on the direction, which may be the other one
+        let backward = polyline.edges.iter().any(
+            |item| matches!(item, TourItem::Edge { index, reversed: true } if !reversible[*index]),
+        );
+        if backward {
+            polyline.edges = polyline.reversed_edges().collect();
+        }
+        polyline
+    };
+
+    let mut polylines = Vec::new();
+    for e in 0..n {
+        if visited[e] {
+            continue;
+        }
+        // Paths are walked from one of their ends
+        if link[2 * e].is_none() {
+            polylines.push(walk(&mut visited, e, 0));
+        } else if link[2 * e + 1].is_none() {
+            polylines.push(walk(&mut visited, e, 1));
+        }
+    }
+    // Only cycles are left
+    for e in 0..n {
+        if !visited[e] {
+            polylines.push(walk(&mut visited, e, 0));
+        }
+    }
+    polylines
+}
+
+/// Clusters of endpoints, found by hashing them on a grid of the tolerance
+struct Nodes {
+    tolerance: f32,
+    cell_size: f32,
+    positions: Vec<nalgebra::Point2<f32>>,
+    cells: std::collections::HashMap<(i64, i64), Vec<usize>>,
+}
+
+impl Nodes {
+    fn new(tolerance: f32) -> Self {
+        Self {
+            tolerance,
+            cell_size: tolerance.max(1e-6),
+            positions: Vec::new(),
+            cells: std::collections::HashMap::new(),
+        }
+    }
+
+    fn cell(&self, p: nalgebra::Point2<f32>) -> (i64, i64) {
+        (
+            (p.x / self.cell_size).floor() as i64,
+            (p.y / self.cell_size).floor() as i64,
+        )
+    }
+
+    fn find_or_insert(&mut self, p: nalgebra::Point2<f32>) -> usize {
+        let (cx, cy) = self.cell(p);
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                let Some(nodes) = self.cells.get(&(cx + dx, cy + dy)) else {
+                    continue;
+                };
+                if let Some(node) = nodes
+                    .iter()
+                    .find(|node| (self.positions[**node] - p).norm() <= self.tolerance)
+                {
+                    return *node;
+                }
+            }
+        }
+
+        let node = self.positions.len();
+        self.positions.push(p);
+        self.cells.entry((cx, cy)).or_default().push(node);
+        node
+    }
+}
+
+/// Disjoint sets of edges, each edge knowing whether it is flipped relative to its root
+struct Components {
+    parent: Vec<usize>,
+    /// Relative to the parent
+    flipped: Vec<bool>,
+    /// For roots, how the root must be flipped for no irreversible edge to be reversed
+    orientation: Vec<Option<bool>>,
+}
+
+impl Components {
+    fn new(reversible: &[bool]) -> Self {
+        Self {
+            parent: (0..reversible.len()).collect(),
+            flipped: vec![false; reversible.len()],
+            orientation: reversible
+                .iter()
+                .map(|reversible| (!reversible).then_some(false))
+                .collect(),
+        }
+    }
+
+    /// The root of `e`, and whether `e` is flipped relative to it
+    fn find(&mut self, e: usize) -> (usize, bool) {
+        let mut path = Vec::new();
+        let mut root = e;
+        while self.parent[root] != root {
+            path.push(root);
+            root = self.parent[root];
+        }
+        // From the root down, each becomes a child of the root
+        let mut flipped = false;
+        for v in path.iter().rev() {
+            flipped ^= self.flipped[*v];
+            self.flipped[*v] = flipped;
+            self.parent[*v] = root;
+        }
+        (root, flipped)
+    }
+
+    /// Requires `a` & `b` to be flipped relative to each other as told,
+    /// unless it contradicts the requirements so far
+    fn union(&mut self, a: usize, b: usize, flipped: bool) -> bool {
+        let (root_a, flipped_a) = self.find(a);
+        let (root_b, flipped_b) = self.find(b);
+        let roots_flipped = flipped ^ flipped_a ^ flipped_b;
+        if root_a == root_b {
+            // Closing a cycle
+            return !roots_flipped;
+        }
+
+        let orientation = match (self.orientation[root_a], self.orientation[root_b]) {
+            (Some(a), Some(b)) if a ^ b != roots_flipped => return false,
+            (a, b) => a.or(b.map(|b| b ^ roots_flipped)),
+        };
+        self.parent[root_b] = root_a;
+        self.flipped[root_b] = roots_flipped;
+        self.orientation[root_a] = orientation;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merge(
+        points: &[(f32, f32)],
+        edges: &[[usize; 2]],
+        reversible: &[bool],
+        settings: &PolylineSettings,
+    ) -> Vec<Polyline> {
+        let (x, y): (Vec<f32>, Vec<f32>) = points.iter().copied().unzip();
+        let from = edges.iter().map(|[a, _]| *a).collect::<Vec<_>>();
+        let to = edges.iter().map(|[_, b]| *b).collect::<Vec<_>>();
+        merge_polylines(
+            PointSlice {
+                x: &x[..],
+                y: &y[..],
+            },
+            EdgeSlice {
+                from: &from[..],
+                to: &to[..],
+            },
+            reversible,
+            settings,
+        )
+    }
+
+    #[test]
+    fn touching_segments_are_joined() {
+        // A zig-zag of independent segments, some backward, endpoints slightly apart,
+        // and a sharp corner at (3, 0)
+        let points = [
+            (0.0, 0.0),
+            (1.0, 0.1),
+            (1.0, 0.1),
+            (2.0, 0.0),
+            (3.0, 0.0001),
+            (2.0, 0.0),
+            (3.0, 0.0),
+            (2.5, 0.1),
+        ];
+        let edges = [[0, 1], [2, 3], [4, 5], [6, 7]];
+        let polylines = merge(&points, &edges, &[true; 4], &PolylineSettings::default());
+
+        assert_eq!(polylines.len(), 2);
+        assert_eq!(polylines[0].vertices(&edges), [0, 1, 3, 4]);
+        assert!(polylines[0].reversible && !polylines[0].closed);
+        assert_eq!(polylines[1].vertices(&edges), [6, 7]);
+
+        // A looser corner keeps them together
+        let settings = PolylineSettings {
+            max_corner: core::f32::consts::PI,
+            ..Default::default()
+        };
+        assert_eq!(merge(&points, &edges, &[true; 4], &settings).len(), 1);
+    }
+
+    #[test]
+    fn irreversible_edges_set_the_direction() {
+        // A closed square, with one edge against the others
+        let points = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+        let edges = [[0, 1], [1, 2], [3, 2], [3, 0]];
+        let settings = PolylineSettings {
+            max_corner: 1.6,
+            ..Default::default()
+        };
+
+        let polylines = merge(&points, &edges, &[true, true, false, true], &settings);
+        assert_eq!(polylines.len(), 1);
+        assert!(polylines[0].closed && !polylines[0].reversible);
+        assert!(polylines[0].edges.contains(&TourItem::Edge {
+            index: 2,
+            reversed: false
+        }));
+
+        // Two irreversible edges in opposite directions cannot be painted in one pass
+        let polylines = merge(&points, &edges, &[false, true, false, true], &settings);
+        assert_eq!(polylines.len(), 2);
+        for polyline in polylines.iter() {
+            assert!(!polyline.edges.iter().any(|item| matches!(
+                item,
+                TourItem::Edge {
+                    index: 0 | 2,
+                    reversed: true
+                }
+            )));
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use crate::optimization::local_search::{LocalSearch, neighbour_lists};
+use crate::optimization::{
+    LocalSearchSettings, MetricSequenceCostFunction, OptimizationSettings, PointsTour,
+};
+
+/// Points added to or taken from a job under way, see [`OptimizationSettings::repair_points`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PointsEdit {
+    /// Indices of the new points, which are not in the tour yet
+    pub inserted: Vec<usize>,
+    /// Indices of the points to leave out
+    pub removed: Vec<usize>,
+}
+
+impl OptimizationSettings {
+    /// Repairs a tour after points were added or removed while it is being followed,
+    /// its first `done` points being already visited.
+    ///
+    /// The removed points are taken out, unless they were visited, and each new point is put
+    /// where it adds the least weight, after the visited ones; the 2-opt & Or-opt moves of
+    /// [`Self::improve_points`] then only look around the changes. The visited points keep their
+    /// order, and the tour keeps its cost, the visited part included.
+    ///
+    /// `points` holds the points of the tour and the new ones,
+    /// the removed ones may stay there as they are left out of the tour.
+    pub fn repair_points(
+        &self,
+        points: crate::generation::PointSlice<&[f32], &[f32]>,
+        tour: &mut PointsTour,
+        done: usize,
+        edit: &PointsEdit,
+        settings: &LocalSearchSettings,
+    ) {
+        self.repair_points_with(self.clone(), points, tour, done, edit, settings);
+    }
+
+    /// [`Self::repair_points`], weighing the steps with another `metric`,
+    /// from the start of these settings
+    pub fn repair_points_with<CF>(
+        &self,
+        metric: CF,
+        points: crate::generation::PointSlice<&[f32], &[f32]>,
+        tour: &mut PointsTour,
+        done: usize,
+        edit: &PointsEdit,
+        settings: &LocalSearchSettings,
+    ) where
+        CF: MetricSequenceCostFunction<Weight = f32>,
+    {
+        let n = points.len();
+        let done = done.min(tour.order.len());
+
+        // The start is appended as an extra point, as in `optimize_points`
+        let positions = points.x[..n]
+            .iter()
+            .zip(points.y[..n].iter())
+            .map(|(x, y)| nalgebra::Point2::new(*x, *y))
+            .chain([self.start])
+            .collect::<Vec<_>>();
+        let start = n;
+        let fixed = usize::from(self.include_start) + done;
+
+        let mut sequence = Vec::with_capacity(tour.order.len() + edit.inserted.len() + 1);
+        if self.include_start {
+            sequence.push(start);
+        }
+        sequence.extend_from_slice(&tour.order[..done]);
+
+        let mut removed = vec![false; n];
+        for point in edit.removed.iter().copied() {
+            removed[point] = true;
+        }
+
+        // The points left next to each other by the removals are worth a look
+        let mut changed = Vec::new();
+        let mut gap = false;
+        for point in tour.order[done..].iter().copied() {
+            if removed[point] {
+                gap = true;
+                continue;
+            }
+            if gap {
+                changed.extend(sequence.last());
+                changed.push(point);
+                gap = false;
+            }
+            sequence.push(point);
+        }
+        if gap {
+            changed.extend(sequence.last());
+        }
+
+        // Cheapest insertion, the weights only changing around the new point
+        let weight = |window: &[usize]| -> f32 {
+            (1..window.len())
+                .map(|k| {
+                    metric.compute_metric_sequence_weight_scalar(
+                        k.checked_sub(2).map(|p| positions[window[p]]),
+                        positions[window[k - 1]],
+                        positions[window[k]],
+                    )
+                })
+                .sum()
+        };
+        let mut present = vec![false; n + 1];
+        for point in sequence.iter().copied() {
+            present[point] = true;
+        }
+        let mut window = Vec::with_capacity(5);
+        for point in edit.inserted.iter().copied() {
+            if present[point] {
+                continue;
+            }
+            present[point] = true;
+            let best = (fixed..=sequence.len())
+                .map(|q| {
+                    let (lo, hi) = (q.saturating_sub(2), (q + 2).min(sequence.len()));
+                    window.clear();
+                    window.extend_from_slice(&sequence[lo..q]);
+                    window.push(point);
+                    window.extend_from_slice(&sequence[q..hi]);
+                    (q, weight(&window) - weight(&sequence[lo..hi]))
+                })
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(q, _)| q)
+                .unwrap_or(sequence.len());
+            sequence.insert(best, point);
+            changed.push(point);
+        }
+        if sequence.is_empty() {
+            tour.order.clear();
+            tour.cost = 0.0;
+            return;
+        }
+
+        let neighbours = neighbour_lists(&positions, settings.neighbours);
+        let mut search = LocalSearch::new(metric, positions, sequence, fixed);
+        search.focus(changed);
+        search.run(&neighbours, settings);
+
+        tour.cost = search.cost();
+        let mut order = search.into_sequence();
+        order.drain(..usize::from(self.include_start));
+        tour.order = order;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimization::test_utils;
+
+    #[test]
+    fn repairs_the_rest_of_the_tour() {
+        let (x, y) = test_utils::random_points(11, 150, [40.0, 40.0]);
+        let points = |n: usize| crate::generation::PointSlice {
+            x: &x[..n],
+            y: &y[..n],
+        };
+        let settings = test_utils::settings([1.5, 0.5, 1.0], 2.0);
+
+        // The last 30 points are drawn while the first 120 are being visited
+        let mut tour = settings.optimize_points(points(120));
+        settings.improve_points(points(120), &mut tour, &Default::default());
+        let done = 50;
+        let visited = tour.order[..done].to_vec();
+        let removed = [
+            tour.order[10],
+            tour.order[60],
+            tour.order[61],
+            tour.order[119],
+        ];
+        let edit = PointsEdit {
+            inserted: (120..150).collect(),
+            removed: removed.to_vec(),
+        };
+
+        let mut repaired = tour.clone();
+        settings.repair_points(points(150), &mut repaired, done, &edit, &Default::default());
+        assert_eq!(repaired.order[..done], visited[..]);
+        let mut remaining = repaired.order.clone();
+        remaining.sort_unstable();
+        let mut expected = (0..150)
+            .filter(|i| !removed[1..].contains(i))
+            .collect::<Vec<_>>();
+        expected.sort_unstable();
+        assert_eq!(remaining, expected);
+
+        // The cost is the one of the tour, not far from a tour planned from scratch
+        let mut check = repaired.clone();
+        settings.improve_points(
+            points(150),
+            &mut check,
+            &LocalSearchSettings {
+                max_passes: 0,
+                ..Default::default()
+            },
+        );
+        assert!((check.cost - repaired.cost).abs() < 1e-3 * repaired.cost);
+        let mut scratch = settings.optimize_points(points(150));
+        settings.improve_points(points(150), &mut scratch, &Default::default());
+        assert!(
+            repaired.cost < scratch.cost * 1.25,
+            "{} {}",
+            repaired.cost,
+            scratch.cost
+        );
+    }
+
+    #[test]
+    fn every_point_may_be_removed() {
+        let (x, y) = ([0.0, 1.0, 2.0], [0.0, 1.0, 0.0]);
+        let points = || crate::generation::PointSlice {
+            x: &x[..],
+            y: &y[..],
+        };
+        let settings = OptimizationSettings {
+            include_start: false,
+            ..test_utils::settings([1.5, 0.5, 1.0], 2.0)
+        };
+
+        let mut tour = settings.optimize_points(points());
+        let edit = PointsEdit {
+            inserted: Vec::new(),
+            removed: (0..3).collect(),
+        };
+        settings.repair_points(points(), &mut tour, 0, &edit, &Default::default());
+        assert!(tour.order.is_empty());
+        assert_eq!(tour.cost, 0.0);
+        // Nor does the search mind an empty sequence
+        let search = LocalSearch::new(settings.clone(), vec![settings.start], Vec::new(), 0);
+        assert_eq!(search.cost(), 0.0);
+    }
+}
@@ -0,0 +1,722 @@
+use super::OptimizationSettings;
+use super::common::OptimizationControlFlow;
+use super::gryf_algo::MetricSequenceCostFunction;
+use super::polylines::Polyline;
+use crate::path::TourItem;
+
+/// Energies differing by less than this are considered equal,
+/// so that float noise does not make a local search cycle.
+const EPSILON: f32 = 1e-4;
+
+#[derive(Debug, Clone)]
+enum Phase {
+    /// Nearest neighbour, one item per iteration
+    Construction {
+        tour: Vec<TourItem>,
+        visited: Vec<bool>,
+    },
+    /// 2-opt sweep on the best tour, one candidate move per iteration
+    Improvement {
+        i: usize,
+        j: usize,
+        improved: bool,
+    },
+    Converged,
+}
+
+/// The anytime optimization of the order (and direction) in which the primitives are painted.
+///
+/// The painted sequence is seen as a sequence of vertices (a dot is one, an edge is two,
+/// a polyline is all of its vertices),
+/// and its energy is the sum of [`MetricSequenceCostFunction`] weights along it.
+/// Reversing a run of items (2-opt) reverses the vertex run, flipping the edges in it.
+///
+/// A complete tour is always available: the generation order, until something better is found.
+#[derive(Debug, Clone)]
+pub(super) struct SequenceOptimizer {
+    settings: OptimizationSettings,
+    /// Positions of all the points, then of the start
+    points: Vec<nalgebra::Point2<f32>>,
+    dots: Vec<usize>,
+    /// The vertices of each edge, or polyline
+    edges: Vec<Vec<usize>>,
+    /// Whether each edge may be painted backward
+    reversible: Vec<bool>,
+    /// The weight of each edge past its first step, forward & backward
+    spray: Vec<[f32; 2]>,
+
+    best: Vec<TourItem>,
+    best_energy: f32,
+    /// The vertices of the best tour, starting with the start if included
+    vertices: Vec<usize>,
+    /// Index of the first vertex of each item of the best tour within `vertices`
+    offsets: Vec<usize>,
+    /// The number of irreversible edges before each item of the best tour
+    irreversible: Vec<usize>,
+    /// `forward[k]` is the energy of `vertices` up to `k`,
+    /// `backward[k]` the sum of the steps `c(V[t], V[t-1], V[t-2])` for `t <= k`
+    forward: Vec<f32>,
+    backward: Vec<f32>,
+
+    phase: Phase,
+    total_iterations: usize,
+
+    /// No tour is cheaper, see [`OptimizationSettings::lower_bound`], computed when first needed
+    lower_bound: std::sync::OnceLock<f32>,
+    /// Converging once the best tour is within this gap of the bound
+    target_gap: Option<f32>,
+}
+
+impl SequenceOptimizer {
+    pub(super) fn new(
+        settings: OptimizationSettings,
+        points: Vec<nalgebra::Point2<f32>>,
+        dots: Vec<usize>,
+        edges: Vec<[usize; 2]>,
+    ) -> Self {
+        let reversible = vec![true; edges.len()];
+        let edges = edges.into_iter().map(Vec::from).collect();
+        Self::with_polylines(settings, points, dots, edges, reversible)
+    }
+
+    /// The edges are polylines, each with at least two vertices,
+    /// only painted backward when `reversible`
+    pub(super) fn with_polylines(
+        settings: OptimizationSettings,
+        mut points: Vec<nalgebra::Point2<f32>>,
+        dots: Vec<usize>,
+        edges: Vec<Vec<usize>>,
+        reversible: Vec<bool>,
+    ) -> Self {
+        debug_assert!(edges.iter().all(|edge| edge.len() >= 2));
+        points.push(settings.start);
+
+        let best = (0..dots.len())
+            .map(TourItem::Dot)
+            .chain((0..edges.len()).map(|index| TourItem::Edge {
+                index,
+                reversed: false,
+            }))
+            .collect::<Vec<_>>();
+        let item_count = best.len();
+
+        let mut optimizer = Self {
+            settings,
+            points,
+            dots,
+            edges,
+            reversible,
+            spray: Vec::new(),
+            best: Vec::new(),
+            best_energy: 0.0,
+            vertices: Vec::new(),
+            offsets: Vec::new(),
+            irreversible: Vec::new(),
+            forward: Vec::new(),
+            backward: Vec::new(),
+            phase: Phase::Construction {
+                tour: Vec::with_capacity(item_count),
+                visited: vec![false; item_count],
+            },
+            total_iterations: 0,
+            lower_bound: std::sync::OnceLock::new(),
+            target_gap: None,
+        };
+        optimizer.spray = optimizer
+            .edges
+            .iter()
+            .map(|edge| {
+                let forward = (2..edge.len()).map(|k| optimizer.step_cost(|i| edge[i], k));
+                let backward =
+                    (2..edge.len()).map(|k| optimizer.step_cost(|i| edge[edge.len() - 1 - i], k));
+                [forward.sum(), backward.sum()]
+            })
+            .collect();
+        optimizer.adopt(best);
+        optimizer
+    }
+
+    pub(super) fn current_tour(&self) -> &[TourItem] {
+        &self.best
+    }
+
+    pub(super) fn energy(&self) -> f32 {
+        self.best_energy
+    }
+
+    pub(super) fn lower_bound(&self) -> f32 {
+        *self.lower_bound.get_or_init(|| {
+            let chords = self
+                .edges
+                .iter()
+                .map(|edge| [edge[0], edge[edge.len() - 1]])
+                .collect::<Vec<_>>();
+            self.settings
+                .lower_bound(&self.points[..self.start()], &self.dots, &chords)
+        })
+    }
+
+    pub(super) fn set_target_gap(&mut self, target_gap: Option<f32>) {
+        self.target_gap = target_gap;
+    }
+
+    fn start(&self) -> usize {
+        self.points.len() - 1
+    }
+
+    fn item_vertices(&self, item: TourItem) -> ItemVertices<'_> {
+        match item {
+            TourItem::Dot(index) => ItemVertices {
+                vertices: core::slice::from_ref(&self.dots[index]),
+                reversed: false,
+            },
+            TourItem::Edge { index, reversed } => ItemVertices {
+                vertices: &self.edges[index],
+                reversed,
+            },
+        }
+    }
+
+    /// The weight of reaching `to` from `from`, coming from `prev`
+    #[inline]
+    fn cost(&self, prev: Option<usize>, from: usize, to: usize) -> f32 {
+        self.settings.compute_metric_sequence_weight_scalar(
+            prev.map(|p| self.points[p]),
+            self.points[from],
+            self.points[to],
+        )
+    }
+
+    /// The weight of the `k`-th step of a vertex sequence
+    #[inline]
+    fn step_cost(&self, vertex: impl Fn(usize) -> usize, k: usize) -> f32 {
+        let prev = if k >= 2 { Some(vertex(k - 2)) } else { None };
+        self.cost(prev, vertex(k - 1), vertex(k))
+    }
+
+    /// Makes this tour the best one
+    fn adopt(&mut self, tour: Vec<TourItem>) {
+        let mut vertices = Vec::with_capacity(tour.len() * 2 + 1);
+        let mut offsets = Vec::with_capacity(tour.len());
+        if self.settings.include_start {
+            vertices.push(self.start());
+        }
+        for item in tour.iter() {
+            offsets.push(vertices.len());
+            let v = self.item_vertices(*item);
+            vertices.extend((0..v.len()).map(|k| v.get(k)));
+        }
+        let irreversible = core::iter::once(0)
+            .chain(tour.iter().scan(0, |count, item| {
+                if let TourItem::Edge { index, .. } = item {
+                    *count += !self.reversible[*index] as usize;
+                }
+                Some(*count)
+            }))
+            .collect();
+
+        self.best = tour;
+        self.forward = vec![0.0; vertices.len()];
+        self.backward = vec![0.0; vertices.len()];
+        self.vertices = vertices;
+        self.offsets = offsets;
+        self.irreversible = irreversible;
+        self.update_sums(0, self.vertices.len().saturating_sub(1));
+    }
+
+    /// Recomputes the prefix sums once the vertices `lo..=hi` changed.
+    /// Only the steps up to `hi + 2` change, the following sums are shifted.
+    fn update_sums(&mut self, lo: usize, hi: usize) {
+        let len = self.vertices.len();
+        if len == 0 {
+            self.best_energy = 0.0;
+            return;
+        }
+        let last = (hi + 2).min(len - 1);
+        let (forward, backward) = (self.forward[last], self.backward[last]);
+        for k in lo.max(1)..=last {
+            let v = &self.vertices;
+            let step = self.step_cost(|i| v[i], k);
+            let backward_step = if k >= 2 {
+                self.cost(Some(v[k]), v[k - 1], v[k - 2])
+            } else {
+                0.0
+            };
+            self.forward[k] = self.forward[k - 1] + step;
+            self.backward[k] = self.backward[k - 1] + backward_step;
+        }
+
+        let (forward, backward) = (self.forward[last] - forward, self.backward[last] - backward);
+        for k in last + 1..len {
+            self.forward[k] += forward;
+            self.backward[k] += backward;
+        }
+        self.best_energy = self.forward[len - 1];
+    }
+
+    /// The closest item (in energy) to the end of the tour under construction,
+    /// or `None` once every item is visited.
+    fn construct(&self, tour: &[TourItem], visited: &[bool]) -> Option<TourItem> {
+        // The state of the vehicle: the two last vertices
+        let (prev, current) = match tour.last() {
+            Some(last) => {
+                let v = self.item_vertices(*last);
+                let prev = if v.len() >= 2 {
+                    Some(v.get(v.len() - 2))
+                } else if tour.len() >= 2 {
+                    Some(self.item_vertices(tour[tour.len() - 2]).last())
+                } else if self.settings.include_start {
+                    Some(self.start())
+                } else {
+                    None
+                };
+                (prev, v.last())
+            }
+            None => (None, self.start()),
+        };
+
+        let mut best: Option<(TourItem, f32)> = None;
+        let mut consider = |item: TourItem, cost: f32| {
+            if best.is_none_or(|(_, best)| cost < best) {
+                best = Some((item, cost));
+            }
+        };
+
+        for (index, visited) in visited.iter().enumerate() {
+            if *visited {
+                continue;
+            }
+
+            if index < self.dots.len() {
+                let dot = self.dots[index];
+                consider(TourItem::Dot(index), self.cost(prev, current, dot));
+            } else {
+                let index = index - self.dots.len();
+                for reversed in [false, true] {
+                    if reversed && !self.reversible[index] {
+                        continue;
+                    }
+                    let item = TourItem::Edge { index, reversed };
+                    let v = self.item_vertices(item);
+                    let cost = self.cost(prev, current, v.get(0))
+                        + self.cost(Some(current), v.get(0), v.get(1))
+                        + self.spray[index][reversed as usize];
+                    consider(item, cost);
+                }
+            }
+        }
+
+        best.map(|(item, _)| item)
+    }
+
+    /// The energy change of reversing the items `i..=j` of the best tour.
+    ///
+    /// The steps within the reversed run are walked backwards, read off the prefix sums,
+    /// so that only the (at most four) steps across its ends are evaluated.
+    fn reversal_delta(&self, i: usize, j: usize) -> f32 {
+        let len = self.vertices.len();
+        let a = self.offsets[i];
+        let b = self.offsets.get(j + 1).copied().unwrap_or(len) - 1;
+        let reversed = |k: usize| {
+            if a <= k && k <= b {
+                self.vertices[a + b - k]
+            } else {
+                self.vertices[k]
+            }
+        };
+
+        let mut delta = 0.0;
+        if b >= a + 2 {
+            delta +=
+                (self.backward[b] - self.backward[a + 1]) - (self.forward[b] - self.forward[a + 1]);
+        }
+        let mut previous = 0;
+        for k in [a, a + 1, b + 1, b + 2] {
+            // Distinct steps, apart from the ones within the run
+            if k <= previous || k >= len || (a + 2..=b).contains(&k) {
+                continue;
+            }
+            previous = k;
+            delta += self.step_cost(reversed, k) - (self.forward[k] - self.forward[k - 1]);
+        }
+        delta
+    }
+
+    /// Reverses the items `i..=j` of the best tour, in place:
+    /// their vertices are reversed as a whole, only the offsets of the items in between moving
+    fn reverse(&mut self, i: usize, j: usize) {
+        self.best[i..=j].reverse();
+        for item in self.best[i..=j].iter_mut() {
+            if let TourItem::Edge { reversed, .. } = item {
+                *reversed = !*reversed;
+            }
+        }
+
+        let a = self.offsets[i];
+        let b = self
+            .offsets
+            .get(j + 1)
+            .copied()
+            .unwrap_or(self.vertices.len());
+        self.vertices[a..b].reverse();
+        let mut offset = a;
+        for k in i..=j {
+            self.offsets[k] = offset;
+            offset += self.item_vertices(self.best[k]).len();
+        }
+        // Reversals never span an irreversible edge, so their counts are unchanged
+
+        self.update_sums(a, b - 1);
+        debug_assert!({
+            let energy: f32 = (1..self.vertices.len())
+                .map(|k| self.step_cost(|v| self.vertices[v], k))
+                .sum();
+            (self.best_energy - energy).abs()
+                <= EPSILON * energy.max(1.0) * (self.best.len() as f32)
+        });
+    }
+
+    pub(super) fn step(&mut self, iterations: usize) -> OptimizationControlFlow<()> {
+        let energy = self.best_energy;
+        let n = self.best.len();
+
+        for _ in 0..iterations {
+            self.total_iterations += 1;
+
+            match core::mem::replace(&mut self.phase, Phase::Converged) {
+                Phase::Construction {
+                    mut tour,
+                    mut visited,
+                } => match self.construct(&tour, &visited) {
+                    Some(item) => {
+                        let index = match item {
+                            TourItem::Dot(index) => index,
+                            TourItem::Edge { index, .. } => self.dots.len() + index,
+                        };
+                        visited[index] = true;
+                        tour.push(item);
+                        self.phase = Phase::Construction { tour, visited };
+                    }
+                    None => {
+                        // Only keep the constructed tour if it is better than the initial one
+                        let previous = (self.best.clone(), self.best_energy);
+                        self.adopt(tour);
+                        if self.best_energy > previous.1 {
+                            self.adopt(previous.0);
+                        }
+                        self.phase = Phase::Improvement {
+                            i: 0,
+                            j: 0,
+                            improved: false,
+                        };
+                    }
+                },
+                Phase::Improvement { i, j, improved } => {
+                    if i >= n {
+                        if !improved {
+                            // A whole sweep without improvement, we are at a local minimum
+                            break;
+                        }
+                        self.phase = Phase::Improvement {
+                            i: 0,
+                            j: 0,
+                            improved: false,
+                        };
+                        continue;
+                    }
+
+                    // Reversing a single dot does nothing, but reversing a single edge flips it
+                    let useful = (i != j || matches!(self.best[i], TourItem::Edge { .. }))
+                        && self.irreversible[j + 1] == self.irreversible[i];
+                    let mut improved = improved;
+                    if useful {
+                        let delta = self.reversal_delta(i, j);
+                        if delta < -EPSILON {
+                            self.reverse(i, j);
+                            improved = true;
+                        }
+                    }
+
+                    let (i, j) = if j + 1 < n {
+                        (i, j + 1)
+                    } else {
+                        (i + 1, i + 1)
+                    };
+                    self.phase = Phase::Improvement { i, j, improved };
+                }
+                Phase::Converged => break,
+            }
+        }
+
+        if let Some(target) = self.target_gap
+            && super::optimality_gap(self.best_energy, self.lower_bound()) <= target
+        {
+            self.phase = Phase::Converged;
+        }
+
+        if matches!(self.phase, Phase::Converged) {
+            OptimizationControlFlow::Converged
+        } else {
+            OptimizationControlFlow::Ongoing {
+                delta_energy: self.best_energy - energy,
+                total_iterations: self.total_iterations,
+            }
+        }
+    }
+}
+
+/// How the items of a [`SequenceOptimizationProcess`] are painted as primitives
+pub trait SequenceItems: core::fmt::Debug + Clone {
+    /// Appends the primitives of `item` to `tour`
+    fn expand(&self, item: TourItem, tour: &mut Vec<TourItem>);
+}
+
+/// Each item is a primitive
+impl SequenceItems for () {
+    fn expand(&self, item: TourItem, tour: &mut Vec<TourItem>) {
+        tour.push(item);
+    }
+}
+
+/// The edges are polylines, expanded into their segments
+impl SequenceItems for Vec<Polyline> {
+    fn expand(&self, item: TourItem, tour: &mut Vec<TourItem>) {
+        match item {
+            TourItem::Dot(index) => tour.push(TourItem::Dot(index)),
+            TourItem::Edge { index, reversed } => {
+                let polyline = &self[index];
+                if reversed {
+                    tour.extend(polyline.reversed_edges());
+                } else {
+                    tour.extend_from_slice(&polyline.edges);
+                }
+            }
+        }
+    }
+}
+
+/// An anytime ordering process, see [`crate::optimization::DotsOptimizationProcess`]
+/// & [`crate::optimization::LinesOptimizationProcess`].
+#[derive(Debug, Clone)]
+pub struct SequenceOptimizationProcess<I> {
+    inner: SequenceOptimizer,
+    items: I,
+    /// The best tour of the inner optimizer, with its items expanded
+    tour: Vec<TourItem>,
+}
+
+impl<I: SequenceItems> SequenceOptimizationProcess<I> {
+    pub(super) fn from_optimizer(inner: SequenceOptimizer, items: I) -> Self {
+        let mut process = Self {
+            inner,
+            items,
+            tour: Vec::new(),
+        };
+        process.expand();
+        process
+    }
+
+    pub fn step(&mut self, iterations: usize) -> OptimizationControlFlow<()> {
+        let flow = self.inner.step(iterations);
+        self.expand();
+        flow
+    }
+
+    pub fn current_tour(&self) -> &[TourItem] {
+        &self.tour
+    }
+
+    fn expand(&mut self) {
+        self.tour.clear();
+        for item in self.inner.current_tour() {
+            self.items.expand(*item, &mut self.tour);
+        }
+    }
+
+    /// Energy of [`Self::current_tour`]
+    pub fn energy(&self) -> f32 {
+        self.inner.energy()
+    }
+
+    /// No tour is cheaper
+    pub fn lower_bound(&self) -> f32 {
+        self.inner.lower_bound()
+    }
+
+    /// Converging once the tour is within this gap of [`Self::lower_bound`],
+    /// see [`crate::optimization::optimality_gap`]
+    pub fn set_target_gap(&mut self, target_gap: Option<f32>) {
+        self.inner.set_target_gap(target_gap);
+    }
+}
+
+/// The vertices of a tour item, in painting order
+struct ItemVertices<'a> {
+    vertices: &'a [usize],
+    reversed: bool,
+}
+
+impl ItemVertices<'_> {
+    fn len(&self) -> usize {
+        self.vertices.len()
+    }
+
+    fn get(&self, k: usize) -> usize {
+        if self.reversed {
+            self.vertices[self.vertices.len() - 1 - k]
+        } else {
+            self.vertices[k]
+        }
+    }
+
+    fn last(&self) -> usize {
+        self.get(self.len() - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimization::test_utils;
+
+    fn settings(penalty: f32) -> OptimizationSettings {
+        test_utils::settings([1.0, 1.0, 1.0], penalty)
+    }
+
+    fn run(optimizer: &mut SequenceOptimizer) {
+        for _ in 0..10_000 {
+            if let OptimizationControlFlow::Converged = optimizer.step(64) {
+                return;
+            }
+        }
+        panic!("did not converge");
+    }
+
+    #[test]
+    fn dots_are_sorted_along_a_line() {
+        let xs = [5.0, 1.0, 9.0, 3.0, 7.0, 2.0, 8.0, 4.0, 6.0];
+        let points = xs.map(|x| nalgebra::Point2::new(x, 0.0)).to_vec();
+        let mut optimizer = SequenceOptimizer::new(settings(1.0), points, (0..9).collect(), vec![]);
+        let initial = optimizer.energy();
+
+        run(&mut optimizer);
+        assert!(optimizer.energy() < initial);
+        assert!(
+            (optimizer.energy() - 9.0).abs() < 1e-3,
+            "{}",
+            optimizer.energy()
+        );
+
+        let visited = optimizer
+            .current_tour()
+            .iter()
+            .map(|item| match item {
+                TourItem::Dot(index) => xs[*index],
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        assert!(visited.is_sorted(), "{visited:?}");
+    }
+
+    #[test]
+    fn reversal_delta_matches_recomputation() {
+        // Dots & polylines mixed, under an asymmetric metric
+        let points = test_utils::random_positions(4, 16, [20.0, 20.0]);
+        let edges = vec![vec![6, 7, 8], vec![9, 10], vec![11, 12, 13, 14, 15]];
+        let optimizer = SequenceOptimizer::with_polylines(
+            test_utils::settings([1.5, 0.1, 1.0], 2.0),
+            points,
+            (0..6).collect(),
+            edges,
+            vec![true; 3],
+        );
+
+        let n = optimizer.current_tour().len();
+        for i in 0..n {
+            for j in i..n {
+                let delta = optimizer.reversal_delta(i, j);
+                let mut reversed = optimizer.clone();
+                let energy = reversed.energy();
+                reversed.reverse(i, j);
+                let recomputed = (1..reversed.vertices.len())
+                    .map(|k| reversed.step_cost(|v| reversed.vertices[v], k))
+                    .sum::<f32>();
+                assert!(
+                    (recomputed - energy - delta).abs() < 1e-3,
+                    "{i}..={j}: {delta} {}",
+                    recomputed - energy
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn stops_within_target_gap() {
+        let xs = [5.0, 1.0, 9.0, 3.0, 7.0, 2.0, 8.0, 4.0, 6.0];
+        let points = xs.map(|x| nalgebra::Point2::new(x, 0.0)).to_vec();
+        let mut optimizer = SequenceOptimizer::new(settings(1.0), points, (0..9).collect(), vec![]);
+        assert!(optimizer.lower_bound() > 0.0);
+        assert!(
+            optimizer.lower_bound() <= 9.0 + 1e-3,
+            "{}",
+            optimizer.lower_bound()
+        );
+
+        optimizer.set_target_gap(Some(f32::INFINITY));
+        assert!(matches!(
+            optimizer.step(1),
+            OptimizationControlFlow::Converged
+        ));
+        assert_eq!(optimizer.total_iterations, 1);
+    }
+
+    #[test]
+    fn lines_are_flipped() {
+        let points = [(2.0, 0.0), (1.0, 0.0), (3.0, 0.0), (4.0, 0.0)]
+            .map(|(x, y)| nalgebra::Point2::new(x, y))
+            .to_vec();
+        let mut optimizer =
+            SequenceOptimizer::new(settings(1.0), points, vec![], vec![[2, 3], [0, 1]]);
+
+        run(&mut optimizer);
+        assert_eq!(
+            optimizer.current_tour(),
+            &[
+                TourItem::Edge {
+                    index: 1,
+                    reversed: true
+                },
+                TourItem::Edge {
+                    index: 0,
+                    reversed: false
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn irreversible_polylines_are_kept_forward() {
+        let points = [(2.0, 0.0), (1.0, 0.0), (0.0, 1.0), (3.0, 0.0), (4.0, 0.0)]
+            .map(|(x, y)| nalgebra::Point2::new(x, y))
+            .to_vec();
+        let mut optimizer = SequenceOptimizer::with_polylines(
+            settings(1.0),
+            points,
+            vec![],
+            vec![vec![3, 4], vec![0, 1, 2]],
+            vec![true, false],
+        );
+
+        run(&mut optimizer);
+        assert!(optimizer.current_tour().contains(&TourItem::Edge {
+            index: 1,
+            reversed: false
+        }));
+        // The energy accounts for every vertex of the polyline
+        let tour = optimizer.current_tour().to_vec();
+        let energy = optimizer.energy();
+        optimizer.adopt(tour);
+        assert!((optimizer.energy() - energy).abs() < 1e-4);
+        assert!(energy > 2.0 + 2.0f32.sqrt());
+    }
+}
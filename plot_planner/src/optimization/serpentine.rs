@@ -0,0 +1,158 @@
+use crate::generation::ScreeningGrid;
+use crate::optimization::{OptimizationSettings, PointsTour};
+
+/// Along which lattice index a serpentine sweeps, see [`OptimizationSettings::serpentine_points`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sweep {
+    /// Row by row (constant `j`), along `i`
+    Rows,
+    /// Column by column (constant `i`), along `j`
+    Columns,
+}
+
+impl OptimizationSettings {
+    /// Orders points laid on the lattice of `grid` (e.g. dots of a grid screen) in a serpentine:
+    /// row by row or column by column, every other one backwards.
+    ///
+    /// Both sweeps are tried, from either end and either way, and the cheapest one in the sense of
+    /// the cost function is kept, e.g. the one going down the rows under an expensive climb.
+    /// It is `O(n log n)`, an instant preview & a baseline for the heavier optimizers.
+    pub fn serpentine_points(
+        &self,
+        points: crate::generation::PointSlice<&[f32], &[f32]>,
+        grid: &ScreeningGrid,
+    ) -> (PointsTour, Sweep) {
+        let n = points.len();
+        let positions = points.x[..n]
+            .iter()
+            .zip(points.y[..n].iter())
+            .map(|(x, y)| nalgebra::Point2::new(*x, *y))
+            .collect::<Vec<_>>();
+
+        // The lattice indices, rounded for the points off the lattice
+        let world_to_grid =
+            nalgebra::Isometry2::new(grid.origin.coords, grid.orientation).inverse();
+        let lattice = positions
+            .iter()
+            .map(|p| {
+                let local = world_to_grid.transform_point(p) / grid.resolution;
+                (local, [local.x.round() as i64, local.y.round() as i64])
+            })
+            .collect::<Vec<_>>();
+
+        let mut best: Option<(PointsTour, Sweep)> = None;
+        for sweep in [Sweep::Rows, Sweep::Columns] {
+            // The index of the lines, then the one along them
+            let (line, along) = match sweep {
+                Sweep::Rows => (1, 0),
+                Sweep::Columns => (0, 1),
+            };
+            let mut sorted = (0..n).collect::<Vec<_>>();
+            sorted.sort_unstable_by(|a, b| {
+                let (local_a, index_a) = &lattice[*a];
+                let (local_b, index_b) = &lattice[*b];
+                index_a[line]
+                    .cmp(&index_b[line])
+                    .then(local_a[along].total_cmp(&local_b[along]))
+            });
+            let lines = sorted
+                .chunk_by(|a, b| lattice[*a].1[line] == lattice[*b].1[line])
+                .collect::<Vec<_>>();
+
+            for (lines_backwards, first_backwards) in
+                [(false, false), (false, true), (true, false), (true, true)]
+            {
+                let mut ordered = lines.clone();
+                if lines_backwards {
+                    ordered.reverse();
+                }
+                let mut order = Vec::with_capacity(n);
+                for (k, line) in ordered.into_iter().enumerate() {
+                    if (k % 2 == 1) != first_backwards {
+                        order.extend(line.iter().rev());
+                    } else {
+                        order.extend(line.iter());
+                    }
+                }
+
+                let cost = self.points_cost(|i| positions[i], &order);
+                if best.as_ref().is_none_or(|(tour, _)| cost < tour.cost) {
+                    best = Some((PointsTour { order, cost }, sweep));
+                }
+            }
+        }
+        best.unwrap_or((
+            PointsTour {
+                order: Vec::new(),
+                cost: 0.0,
+            },
+            Sweep::Rows,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimization::test_utils;
+    use rand::SeedableRng;
+    use rand::seq::SliceRandom;
+
+    #[test]
+    fn sweeps_down_the_rows() {
+        let grid = ScreeningGrid {
+            origin: nalgebra::Point2::new(3.0, 2.0),
+            orientation: 0.1,
+            resolution: 2.0,
+            ..Default::default()
+        };
+        let grid_to_world = nalgebra::Isometry2::new(grid.origin.coords, grid.orientation);
+        let mut lattice = (0..20)
+            .flat_map(|i| (0..6).map(move |j| (i, j)))
+            .collect::<Vec<_>>();
+        lattice.shuffle(&mut rand_xoshiro::Xoroshiro64Star::seed_from_u64(9));
+        let (x, y): (Vec<f32>, Vec<f32>) = lattice
+            .iter()
+            .map(|(i, j)| {
+                let p = grid_to_world.transform_point(&nalgebra::Point2::new(
+                    *i as f32 * grid.resolution,
+                    *j as f32 * grid.resolution,
+                ));
+                (p.x, p.y)
+            })
+            .unzip();
+        // Climbing (towards -y) is expensive
+        let settings = test_utils::settings([4.0, 1.0, 1.0], 0.1);
+
+        let (tour, sweep) = settings.serpentine_points(
+            crate::generation::PointSlice {
+                x: &x[..],
+                y: &y[..],
+            },
+            &grid,
+        );
+        assert_eq!(sweep, Sweep::Rows);
+        let mut visited = tour.order.clone();
+        visited.sort_unstable();
+        assert_eq!(visited, (0..lattice.len()).collect::<Vec<_>>());
+
+        // Row after row, downwards, each one in a single pass
+        let rows = tour.order.iter().map(|k| lattice[*k].1).collect::<Vec<_>>();
+        assert!(rows.windows(2).all(|pair| pair[0] <= pair[1]), "{rows:?}");
+        let ascending = tour
+            .order
+            .chunks(20)
+            .map(|row| {
+                let columns = row.iter().map(|k| lattice[*k].0).collect::<Vec<_>>();
+                let expected = (0..20).collect::<Vec<_>>();
+                if columns == expected {
+                    true
+                } else {
+                    assert_eq!(columns, expected.into_iter().rev().collect::<Vec<_>>());
+                    false
+                }
+            })
+            .collect::<Vec<_>>();
+        assert!(ascending.windows(2).all(|pair| pair[0] != pair[1]));
+    }
+}
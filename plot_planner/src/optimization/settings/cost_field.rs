@@ -0,0 +1,320 @@
+use super::SpecificEnergyCost;
+use crate::generation::Point;
+use crate::optimization::gryf_algo::MetricSequenceCostFunction;
+use nalgebra::{Point2, Vector2};
+
+/// A spatially varying [`SpecificEnergyCost`], constant over the cells of a grid,
+/// e.g. for gusts along the edge of a building, updrafts, or an energy growing with the height.
+///
+/// The weight of a translation is the integral of the metric along it, see [`Self::weight`].
+/// Outside of the grid, the metric is the one of the nearest cell.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CostField {
+    /// The corner of the first cell
+    pub origin: Point2<f32>,
+    /// The side of the cells
+    resolution: f32,
+    /// Number of cells in a row
+    width: usize,
+    /// Row by row, from the origin
+    cells: Vec<SpecificEnergyCost>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CostFieldError {
+    /// The side of the cells is not a positive number
+    Resolution(f32),
+    /// The grid has no cell
+    Empty,
+    /// The cells do not fill whole rows of the width
+    Size { width: usize, cells: usize },
+}
+
+impl CostField {
+    /// A field of `cells`, row by row from `origin`, `width` to a row
+    pub fn new(
+        origin: Point2<f32>,
+        resolution: f32,
+        width: usize,
+        cells: Vec<SpecificEnergyCost>,
+    ) -> Result<Self, CostFieldError> {
+        if !(resolution.is_finite() && resolution > 0.0) {
+            return Err(CostFieldError::Resolution(resolution));
+        }
+        if width == 0 || cells.is_empty() {
+            return Err(CostFieldError::Empty);
+        }
+        if cells.len() % width != 0 {
+            return Err(CostFieldError::Size {
+                width,
+                cells: cells.len(),
+            });
+        }
+        Ok(Self {
+            origin,
+            resolution,
+            width,
+            cells,
+        })
+    }
+
+    /// A `width` by `height` field, whose cells take the metric at their center
+    pub fn from_fn(
+        origin: Point2<f32>,
+        resolution: f32,
+        [width, height]: [usize; 2],
+        mut metric: impl FnMut(Point2<f32>) -> SpecificEnergyCost,
+    ) -> Result<Self, CostFieldError> {
+        let cells = (0..height)
+            .flat_map(|j| (0..width).map(move |i| (i, j)))
+            .map(|(i, j)| {
+                metric(origin + Vector2::new(i as f32 + 0.5, j as f32 + 0.5) * resolution)
+            })
+            .collect();
+        Self::new(origin, resolution, width, cells)
+    }
+
+    /// A field of `base`, drifted by a flow (e.g. the wind), given row by row:
+    /// moving along it gets cheaper by the dot product, against it more expensive.
+    ///
+    /// The flow should stay weaker than `base`, lest moving along it weigh less than nothing.
+    pub fn from_flow(
+        origin: Point2<f32>,
+        resolution: f32,
+        width: usize,
+        base: SpecificEnergyCost,
+        flow: &[Vector2<f32>],
+    ) -> Result<Self, CostFieldError> {
+        let cells = flow
+            .iter()
+            .map(|flow| SpecificEnergyCost {
+                asymmetry: base.asymmetry - nalgebra::Matrix2::from_diagonal(flow),
+                ..base
+            })
+            .collect();
+        Self::new(origin, resolution, width, cells)
+    }
+
+    pub fn resolution(&self) -> f32 {
+        self.resolution
+    }
+
+    /// Number of cells in a row
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Row by row, from the origin
+    pub fn cells(&self) -> &[SpecificEnergyCost] {
+        &self.cells
+    }
+
+    /// Number of rows
+    pub fn height(&self) -> usize {
+        self.cells.len() / self.width
+    }
+
+    /// The metric at a point, the one of the nearest cell outside of the grid
+    pub fn metric(&self, point: Point2<f32>) -> &SpecificEnergyCost {
+        let local = (point - self.origin) / self.resolution;
+        let i = (local.x.floor().max(0.0) as usize).min(self.width - 1);
+        let j = (local.y.floor().max(0.0) as usize).min(self.height() - 1);
+        &self.cells[j * self.width + i]
+    }
+
+    /// The weight of the translation from `from` to `to`: the sum of the weights of its pieces
+    /// within each cell, an exact line integral as the metric is constant over them
+    pub fn weight(&self, from: Point2<f32>, to: Point2<f32>) -> f32 {
+        let translation = to - from;
+        let (a, b) = (
+            (from - self.origin) / self.resolution,
+            (to - self.origin) / self.resolution,
+        );
+
+        // Where the segment crosses the boundaries between the cells, as fractions of it
+        let mut cuts = vec![0.0, 1.0];
+        for (a, b, cells) in [(a.x, b.x, self.width), (a.y, b.y, self.height())] {
+            if a == b {
+                continue;
+            }
+            let (low, high) = (a.min(b).ceil().max(1.0), b.max(a).min(cells as f32 - 1.0));
+            let mut boundary = low;
+            while boundary <= high {
+                cuts.push((boundary - a) / (b - a));
+                boundary += 1.0;
+            }
+        }
+        cuts.sort_unstable_by(f32::total_cmp);
+
+        cuts.windows(2)
+            .filter(|cut| cut[1] > cut[0])
+            .map(|cut| {
+                let middle = from + translation * ((cut[0] + cut[1]) / 2.0);
+                self.metric(middle)
+                    .weight_scalar(translation * (cut[1] - cut[0]))
+            })
+            .sum()
+    }
+}
+
+/// A cost function whose moves also weigh the integral of a [`CostField`] along them.
+///
+/// The field usually stands for the whole translation cost, `metric` then only adding
+/// the sequence-dependent part, e.g. [`crate::optimization::OptimizationSettings`]
+/// with a [`SpecificEnergyCost::zero`] specific energy.
+/// Through a [`crate::optimization::MetricSequenceCostFunctionAdapter`], it serves
+/// the graph algorithms as well.
+#[derive(Debug, Clone)]
+pub struct FieldAwareCost<'a, CF> {
+    pub metric: CF,
+    pub field: &'a CostField,
+}
+
+impl<CF> MetricSequenceCostFunction for FieldAwareCost<'_, CF>
+where
+    CF: MetricSequenceCostFunction<Weight = f32>,
+{
+    type Weight = f32;
+
+    fn compute_metric_sequence_weight<const L: usize>(
+        &self,
+        prev: Option<Point<L>>,
+        from: Point<L>,
+        to: Point<L>,
+    ) -> (
+        core::simd::Simd<Self::Weight, L>,
+        core::simd::Mask<isize, L>,
+    ) {
+        let (weights, mask) = self.metric.compute_metric_sequence_weight(prev, from, to);
+        let lane = |p: &Point<L>, i: usize| Point2::new(p.x[i], p.y[i]);
+        let field = core::array::from_fn(|i| self.field.weight(lane(&from, i), lane(&to, i)));
+        (weights + core::simd::Simd::from_array(field), mask)
+    }
+
+    fn compute_metric_sequence_weight_scalar(
+        &self,
+        prev: Option<Point2<f32>>,
+        from: Point2<f32>,
+        to: Point2<f32>,
+    ) -> Self::Weight {
+        self.metric
+            .compute_metric_sequence_weight_scalar(prev, from, to)
+            + self.field.weight(from, to)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimization::{DirectionChangePenalty, OptimizationSettings};
+
+    #[test]
+    fn integrates_along_segments() {
+        // The energy grows with the height (towards -y)
+        let field = CostField::from_fn(Point2::new(-5.0, -5.0), 0.5, [40, 30], |p| {
+            SpecificEnergyCost::from_penalties(2.0 - p.y * 0.1, 0.5, 1.0 - p.y * 0.05)
+        })
+        .unwrap();
+        assert_eq!(field.height(), 30);
+
+        let (a, b, c) = (
+            Point2::new(-7.0, 9.0),
+            Point2::new(2.0, 3.0),
+            Point2::new(15.5, -6.0),
+        );
+        // The pieces add up
+        let whole = field.weight(a, c);
+        assert!((field.weight(a, b) + field.weight(b, c) - whole).abs() < 1e-3 * whole);
+        // Higher is more expensive
+        let translation = Vector2::new(3.0, -1.0);
+        let low = Point2::new(0.0, 8.0);
+        let high = Point2::new(0.0, -3.0);
+        assert!(field.weight(high, high + translation) > field.weight(low, low + translation));
+
+        // A uniform field is the metric itself
+        let metric = SpecificEnergyCost::from_penalties(1.5, 0.5, 1.0);
+        let uniform = CostField::from_fn(Point2::origin(), 0.3, [50, 50], |_| metric).unwrap();
+        for (from, to) in [(a, b), (b, c), (c, a), (a, a)] {
+            let expected = metric.weight_scalar(to - from);
+            assert!((uniform.weight(from, to) - expected).abs() < 1e-4 * (1.0 + expected.abs()));
+        }
+    }
+
+    #[test]
+    fn gusts_drift_the_moves() {
+        // A gust blowing towards +x along a band, e.g. the edge of a building
+        let base = SpecificEnergyCost::identity();
+        let (width, height) = (20, 20);
+        let flow = (0..height)
+            .flat_map(|j| {
+                (0..width).map(move |_| {
+                    if (8..12).contains(&j) {
+                        Vector2::new(0.6, 0.0)
+                    } else {
+                        Vector2::zeros()
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        let field = CostField::from_flow(Point2::origin(), 1.0, width, base, &flow).unwrap();
+
+        let (west, east) = (Point2::new(2.0, 10.0), Point2::new(18.0, 10.0));
+        assert!((field.weight(west, east) - 16.0 * 0.4).abs() < 1e-4);
+        assert!((field.weight(east, west) - 16.0 * 1.6).abs() < 1e-4);
+        // Out of the band, nothing changes
+        let (west, east) = (Point2::new(2.0, 3.0), Point2::new(18.0, 3.0));
+        assert!((field.weight(west, east) - 16.0).abs() < 1e-4);
+
+        // The penalty of the settings is kept
+        let settings = OptimizationSettings {
+            specific_energy: SpecificEnergyCost::zero(),
+            penalty: DirectionChangePenalty(2.0),
+            start: Point2::origin(),
+            include_start: false,
+        };
+        let cost = FieldAwareCost {
+            metric: settings.clone(),
+            field: &field,
+        };
+        let prev = Point2::new(2.0, 0.0);
+        let expected = settings.compute_metric_sequence_weight_scalar(Some(prev), west, east)
+            + field.weight(west, east);
+        let weight = cost.compute_metric_sequence_weight_scalar(Some(prev), west, east);
+        assert!((weight - expected).abs() < 1e-5);
+        assert!(weight > field.weight(west, east));
+
+        let lanes = |p: Point2<f32>| Point::<4> {
+            x: core::simd::Simd::splat(p.x),
+            y: core::simd::Simd::splat(p.y),
+        };
+        let (weights, _) =
+            cost.compute_metric_sequence_weight(Some(lanes(prev)), lanes(west), lanes(east));
+        assert!((weights[2] - weight).abs() < 1e-4);
+    }
+
+    #[test]
+    fn malformed_grids_are_rejected() {
+        let metric = SpecificEnergyCost::identity();
+        let origin = Point2::origin();
+        assert_eq!(
+            CostField::from_fn(origin, 1.0, [0, 4], |_| metric),
+            Err(CostFieldError::Empty)
+        );
+        assert_eq!(
+            CostField::from_fn(origin, 1.0, [4, 0], |_| metric),
+            Err(CostFieldError::Empty)
+        );
+        assert_eq!(
+            CostField::from_fn(origin, 0.0, [4, 4], |_| metric),
+            Err(CostFieldError::Resolution(0.0))
+        );
+        assert_eq!(
+            CostField::new(origin, 1.0, 4, vec![metric; 6]),
+            Err(CostFieldError::Size { width: 4, cells: 6 })
+        );
+        assert_eq!(
+            CostField::from_flow(origin, 1.0, 3, metric, &[]),
+            Err(CostFieldError::Empty)
+        );
+    }
+}
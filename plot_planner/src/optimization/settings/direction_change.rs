@@ -26,6 +26,18 @@ impl DirectionChangePenalty {
         (simd::Simd::splat(1.0) - cos_theta.0) * simd::Simd::splat(self.0 * 0.5)
     }
 
+    /// Scalar version of [`Self::weight`]
+    #[inline(always)]
+    pub fn weight_scalar(
+        &self,
+        v_in: nalgebra::Vector2<f32>,
+        v_out: nalgebra::Vector2<f32>,
+    ) -> f32 {
+        let safe_divisor = (v_in.magnitude() * v_out.magnitude()).max(f32::EPSILON);
+        let cos_theta = v_in.dot(&v_out) / safe_divisor;
+        (1.0 - cos_theta) * (self.0 * 0.5)
+    }
+
     pub fn weight<const L: usize>(
         &self,
         v_in: nalgebra::Vector2<simba::simd::Simd<simd::Simd<f32, L>>>,
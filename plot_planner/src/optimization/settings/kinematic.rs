@@ -0,0 +1,341 @@
+use crate::generation::Point;
+use crate::optimization::gryf_algo::MetricSequenceCostFunction;
+use nalgebra::{Point2, Vector2};
+
+/// How the speed changes along a segment
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum VelocityProfile {
+    /// Constant acceleration up to the cruise speed, then constant deceleration
+    #[default]
+    Trapezoidal,
+    /// The acceleration itself ramps up & down (S-curve)
+    JerkLimited { max_jerk: f32 },
+}
+
+/// The actual traversal time, from the limits of the vehicle.
+///
+/// Each vertex is passed at a cornering speed derived from its turn angle
+/// (the junction deviation model), and each segment is flown from & to those speeds,
+/// as fast as the per-axis velocity & acceleration limits allow.
+/// As the vertices around it may be stops, a vertex is also passed no faster than
+/// the vehicle speeds up from rest along the segment before it (the forward pass),
+/// and slows down to rest along the segment after it (the backward pass).
+///
+/// The time of a step `prev -> from -> to` is the second half of `prev -> from`
+/// and the first half of `from -> to`: both only depend on the turn at `from`.
+/// Without `prev`, the vehicle leaves `from` at rest.
+/// The second half of the last segment is left out, see [`Self::stopping_time`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KinematicCost {
+    /// Per axis
+    pub max_velocity: Vector2<f32>,
+    /// Per axis
+    pub max_acceleration: Vector2<f32>,
+    pub profile: VelocityProfile,
+    /// How far from the corner the vehicle may cut it, the higher the faster the turns
+    pub junction_deviation: f32,
+}
+
+impl KinematicCost {
+    /// The limit along a unit `direction`, so that no axis exceeds its own
+    fn along(limits: Vector2<f32>, direction: Vector2<f32>) -> f32 {
+        let x = limits.x / direction.x.abs();
+        let y = limits.y / direction.y.abs();
+        x.min(y)
+    }
+
+    /// Time to change the speed by `dv`
+    fn ramp(&self, dv: f32, acceleration: f32) -> f32 {
+        match self.profile {
+            VelocityProfile::Trapezoidal => dv / acceleration,
+            VelocityProfile::JerkLimited { max_jerk } => {
+                if dv >= acceleration * acceleration / max_jerk {
+                    dv / acceleration + acceleration / max_jerk
+                } else {
+                    2.0 * (dv / max_jerk).sqrt()
+                }
+            }
+        }
+    }
+
+    /// Time over `distance`, from `speed` & speeding up towards `max_speed`.
+    /// By symmetry, also the time to slow down to `speed` over that distance.
+    fn half(&self, distance: f32, speed: f32, max_speed: f32, acceleration: f32) -> f32 {
+        if distance <= 0.0 {
+            return 0.0;
+        }
+        let speed = speed.min(max_speed);
+        let full = self.covered(speed, max_speed, acceleration);
+        if full <= distance {
+            return self.ramp(max_speed - speed, acceleration) + (distance - full) / max_speed;
+        }
+        let peak = self.peak(distance, speed, max_speed, acceleration);
+        self.ramp(peak - speed, acceleration)
+    }
+
+    /// Distance covered while speeding up from `speed` to `peak`
+    fn covered(&self, speed: f32, peak: f32, acceleration: f32) -> f32 {
+        // The profiles are symmetric, so that the mean speed of a ramp is the mean of its ends
+        (speed + peak) / 2.0 * self.ramp(peak - speed, acceleration)
+    }
+
+    /// The speed reached over `distance`, from `speed` & speeding up towards `max_speed`
+    fn peak(&self, distance: f32, speed: f32, max_speed: f32, acceleration: f32) -> f32 {
+        const BISECTIONS: usize = 32;
+
+        let speed = speed.min(max_speed);
+        if self.covered(speed, max_speed, acceleration) <= distance {
+            return max_speed;
+        }
+        match self.profile {
+            VelocityProfile::Trapezoidal => (speed * speed + 2.0 * acceleration * distance).sqrt(),
+            VelocityProfile::JerkLimited { .. } => {
+                let (mut low, mut high) = (speed, max_speed);
+                for _ in 0..BISECTIONS {
+                    let middle = (low + high) / 2.0;
+                    if self.covered(speed, middle, acceleration) < distance {
+                        low = middle;
+                    } else {
+                        high = middle;
+                    }
+                }
+                (low + high) / 2.0
+            }
+        }
+    }
+
+    /// The fastest speed at the end of `segment`, starting it at rest
+    fn reachable(&self, segment: Vector2<f32>) -> f32 {
+        let length = segment.norm();
+        let direction = segment / length;
+        self.peak(
+            length,
+            0.0,
+            Self::along(self.max_velocity, direction),
+            Self::along(self.max_acceleration, direction),
+        )
+    }
+
+    /// The speed at which the turn from `incoming` to `outgoing` (unit vectors) is taken
+    pub fn cornering_speed(&self, incoming: Vector2<f32>, outgoing: Vector2<f32>) -> f32 {
+        let max_speed =
+            Self::along(self.max_velocity, incoming).min(Self::along(self.max_velocity, outgoing));
+        let acceleration = Self::along(self.max_acceleration, incoming)
+            .min(Self::along(self.max_acceleration, outgoing));
+
+        // The cosine of half the turn: 1 going straight, 0 turning back
+        let cos_half = ((1.0 + incoming.dot(&outgoing)) / 2.0)
+            .clamp(0.0, 1.0)
+            .sqrt();
+        if cos_half >= 1.0 {
+            return max_speed;
+        }
+        (acceleration * self.junction_deviation * cos_half / (1.0 - cos_half))
+            .sqrt()
+            .min(max_speed)
+    }
+
+    /// Time of the second half of `from -> to`, down to rest
+    pub fn stopping_time(&self, from: Point2<f32>, to: Point2<f32>) -> f32 {
+        let translation = to - from;
+        let length = translation.norm();
+        if length <= 0.0 {
+            return 0.0;
+        }
+        let direction = translation / length;
+        self.half(
+            length / 2.0,
+            0.0,
+            Self::along(self.max_velocity, direction),
+            Self::along(self.max_acceleration, direction),
+        )
+    }
+
+    /// Time of the step, see [`KinematicCost`]
+    pub fn time(&self, prev: Option<Point2<f32>>, from: Point2<f32>, to: Point2<f32>) -> f32 {
+        let outgoing = to - from;
+        let length = outgoing.norm();
+        let incoming = prev.map(|prev| from - prev).filter(|v| v.norm() > 0.0);
+
+        let corner = match (incoming, length > 0.0) {
+            (Some(incoming), true) => self
+                .cornering_speed(incoming.normalize(), outgoing / length)
+                .min(self.reachable(incoming))
+                // Slowing down is symmetric to speeding up
+                .min(self.reachable(outgoing)),
+            _ => 0.0,
+        };
+
+        let leaving = if length > 0.0 {
+            let direction = outgoing / length;
+            self.half(
+                length / 2.0,
+                corner,
+                Self::along(self.max_velocity, direction),
+                Self::along(self.max_acceleration, direction),
+            )
+        } else {
+            0.0
+        };
+        let arriving = incoming.map_or(0.0, |incoming| {
+            let length = incoming.norm();
+            let direction = incoming / length;
+            self.half(
+                length / 2.0,
+                corner,
+                Self::along(self.max_velocity, direction),
+                Self::along(self.max_acceleration, direction),
+            )
+        });
+        leaving + arriving
+    }
+}
+
+impl MetricSequenceCostFunction for KinematicCost {
+    type Weight = f32;
+
+    fn compute_metric_sequence_weight<const L: usize>(
+        &self,
+        prev: Option<Point<L>>,
+        from: Point<L>,
+        to: Point<L>,
+    ) -> (
+        core::simd::Simd<Self::Weight, L>,
+        core::simd::Mask<isize, L>,
+    ) {
+        let lane = |p: &Point<L>, i: usize| Point2::new(p.x[i], p.y[i]);
+        let weights = core::array::from_fn(|i| {
+            self.time(
+                prev.as_ref().map(|p| lane(p, i)),
+                lane(&from, i),
+                lane(&to, i),
+            )
+        });
+        (
+            core::simd::Simd::from_array(weights),
+            core::simd::Mask::splat(true),
+        )
+    }
+
+    #[inline(always)]
+    fn compute_metric_sequence_weight_scalar(
+        &self,
+        prev: Option<Point2<f32>>,
+        from: Point2<f32>,
+        to: Point2<f32>,
+    ) -> Self::Weight {
+        self.time(prev, from, to)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cost(profile: VelocityProfile) -> KinematicCost {
+        KinematicCost {
+            max_velocity: Vector2::new(5.0, 2.0),
+            max_acceleration: Vector2::new(1.0, 1.0),
+            profile,
+            junction_deviation: 0.05,
+        }
+    }
+
+    #[test]
+    fn segments_take_their_traversal_time() {
+        let trapezoidal = cost(VelocityProfile::Trapezoidal);
+        let (from, to) = (Point2::new(0.0, 0.0), Point2::new(100.0, 0.0));
+        // 5s up to speed (12.5 covered) & back down, the rest cruising
+        let total = trapezoidal.time(None, from, to) + trapezoidal.stopping_time(from, to);
+        assert!((total - 25.0).abs() < 1e-4, "{total}");
+        // Too short to reach the cruise speed: 1 up, 1 down
+        let short = Point2::new(1.0, 0.0);
+        let total = trapezoidal.time(None, from, short) + trapezoidal.stopping_time(from, short);
+        assert!((total - 2.0).abs() < 1e-4, "{total}");
+
+        // The y axis is slower
+        let up = Point2::new(0.0, 100.0);
+        assert!(trapezoidal.time(None, from, up) > trapezoidal.time(None, from, to));
+
+        // Limiting the jerk only slows down
+        let jerk = cost(VelocityProfile::JerkLimited { max_jerk: 2.0 });
+        assert!(jerk.time(None, from, to) > trapezoidal.time(None, from, to));
+        assert!(jerk.time(None, from, short) > trapezoidal.time(None, from, short));
+    }
+
+    #[test]
+    fn sharper_turns_are_slower() {
+        let cost = cost(VelocityProfile::Trapezoidal);
+        let (from, to) = (Point2::new(20.0, 0.0), Point2::new(40.0, 0.0));
+        let straight = cost.time(Some(Point2::new(0.0, 0.0)), from, to);
+        let square = cost.time(Some(Point2::new(20.0, -10.0)), from, to);
+        let back = cost.time(Some(Point2::new(40.0, 0.0)), from, to);
+        assert!(
+            straight < square && square < back,
+            "{straight} {square} {back}"
+        );
+
+        // Going straight, the segments are flown at cruise speed
+        assert!((straight - 20.0 / 5.0).abs() < 1e-4, "{straight}");
+        // And turning back, stopping in between
+        let stop = 2.0 * cost.stopping_time(from, to);
+        assert!((back - stop).abs() < 1e-4, "{back} {stop}");
+    }
+
+    #[test]
+    fn short_segments_limit_the_corners() {
+        let cost = cost(VelocityProfile::Trapezoidal);
+        // A gentle turn, taken at cruise speed between long segments
+        let (prev, from, to) = (
+            Point2::new(0.0, 0.0),
+            Point2::new(20.0, 0.0),
+            Point2::new(40.0, 1.0),
+        );
+        let long = cost.time(Some(prev), from, to);
+        assert!(long < 41.0 / 5.0 / 2.0 + 0.1, "{long}");
+
+        // Around short segments, the vertices next to it may be stops
+        let scale = 1e-3;
+        let (prev, from, to) = (prev * scale, from * scale, to * scale);
+        // No faster than speeding up from rest over a segment and a half
+        let fastest = (2.0 * 1.0 * 30.0 * scale).sqrt();
+        let short = cost.time(Some(prev), from, to);
+        assert!(short >= 20.0 * scale / fastest, "{short}");
+        // Still no slower than stopping at the corner
+        let stop = cost.time(Some(prev), from, prev);
+        assert!(short <= stop, "{short} {stop}");
+    }
+
+    #[test]
+    fn local_search_minimises_time() {
+        use crate::optimization::test_utils;
+
+        let (x, y) = test_utils::random_points(3, 120, [30.0, 30.0]);
+        let points = crate::generation::PointSlice {
+            x: &x[..],
+            y: &y[..],
+        };
+        let settings = test_utils::settings([1.0, 1.0, 1.0], 0.0);
+        let cost = cost(VelocityProfile::Trapezoidal);
+        let time = |order: &[usize]| {
+            let vertices = core::iter::once(settings.start)
+                .chain(order.iter().map(|i| Point2::new(x[*i], y[*i])))
+                .collect::<Vec<_>>();
+            (1..vertices.len())
+                .map(|k| {
+                    cost.time(
+                        k.checked_sub(2).map(|p| vertices[p]),
+                        vertices[k - 1],
+                        vertices[k],
+                    )
+                })
+                .sum::<f32>()
+        };
+
+        let mut tour = settings.optimize_points(points.clone());
+        let before = time(&tour.order);
+        settings.improve_points_with(cost, points, &mut tour, &Default::default());
+        assert!((tour.cost - time(&tour.order)).abs() < 1e-2 * tour.cost);
+        assert!(tour.cost < before, "{} {before}", tour.cost);
+    }
+}
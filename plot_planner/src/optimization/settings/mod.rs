@@ -4,6 +4,12 @@ pub use direction_change::DirectionChangePenalty;
 mod specific_energy;
 pub use specific_energy::SpecificEnergyCost;
 
+mod kinematic;
+pub use kinematic::{KinematicCost, VelocityProfile};
+
+mod cost_field;
+pub use cost_field::{CostField, CostFieldError, FieldAwareCost};
+
 use crate::generation::Point;
 
 /// When creating a path from the generated primitives, and
@@ -68,6 +74,21 @@ impl crate::optimization::gryf_algo::MetricSequenceCostFunction for Optimization
             }
         };
 
-        (weight + penalty, unimplemented!())
+        (weight + penalty, core::simd::Mask::splat(true))
+    }
+
+    #[inline(always)]
+    fn compute_metric_sequence_weight_scalar(
+        &self,
+        prev: Option<nalgebra::Point2<f32>>,
+        from: nalgebra::Point2<f32>,
+        to: nalgebra::Point2<f32>,
+    ) -> Self::Weight {
+        let weight = self.specific_energy.weight_scalar(to - from);
+        let penalty = match prev {
+            None => 0.0,
+            Some(prev) => self.penalty.weight_scalar(from - prev, to - from),
+        };
+        weight + penalty
     }
 }
@@ -4,6 +4,8 @@ use core::simd;
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
 pub struct SpecificEnergyCost {
     pub weights: nalgebra::Matrix2<f32>,
+    /// The drift, on the diagonal: a translation `v` adds `v.x * a_xx + v.y * a_yy`,
+    /// see [`Self::from_penalties`]
     pub asymmetry: nalgebra::Matrix2<f32>,
 }
 
@@ -38,15 +40,42 @@ impl SpecificEnergyCost {
         }
     }
 
+    /// The euclidean metric, without any drift
     pub fn identity() -> Self {
         Self {
             weights: nalgebra::Matrix2::identity(),
-            asymmetry: nalgebra::Matrix2::identity(),
+            asymmetry: nalgebra::Matrix2::zeros(),
         }
     }
 
-    const fn min_spec_e(&self) -> f32 {
-        todo!()
+    /// No translation cost at all, e.g. when a [`crate::optimization::CostField`] stands for it
+    pub fn zero() -> Self {
+        Self {
+            weights: nalgebra::Matrix2::zeros(),
+            asymmetry: nalgebra::Matrix2::zeros(),
+        }
+    }
+
+    /// A lower bound of the weight of a unit translation, whatever its direction.
+    ///
+    /// The weight of any translation is then at least this times its length,
+    /// which bounds the search for cheap candidates, see [`crate::optimization::UniformGrid`].
+    /// It can be negative, when the drift is stronger than the metric.
+    pub fn min_spec_e(&self) -> f32 {
+        const SAMPLES: usize = 1024;
+        let step = core::f32::consts::TAU / (SAMPLES as f32);
+
+        let minimum = (0..SAMPLES)
+            .map(|i| {
+                let (sin, cos) = ((i as f32) * step).sin_cos();
+                self.weight_scalar(nalgebra::Vector2::new(cos, sin))
+            })
+            .fold(f32::INFINITY, f32::min);
+
+        // The weight is Lipschitz along the unit circle,
+        // so the true minimum is at most half a step (times that constant) below the sampled one
+        let lipschitz = self.weights.abs().max().sqrt() * 2.0 + self.asymmetry.diagonal().norm();
+        minimum - lipschitz * step / 2.0
     }
 
     /// Computes the weight for a given translation
@@ -75,7 +104,7 @@ impl SpecificEnergyCost {
         // Asymmetric Drift (Linear part)
         // Ma * v
         // This is a simple dot product: (Ax * v.x) + (Ay * v.y)
-        let asymmetric_cost = (translation.transpose() * self.asymmetry.cast()).x;
+        let asymmetric_cost = translation.dot(&self.asymmetry.diagonal().cast());
 
         use nalgebra::SimdComplexField;
         let combined_cost = symmetric_cost.x.simd_sqrt() + asymmetric_cost;
@@ -83,6 +112,15 @@ impl SpecificEnergyCost {
         combined_cost.0
     }
 
+    /// Scalar version of [`Self::weight`]
+    #[inline(always)]
+    pub fn weight_scalar(&self, translation: nalgebra::Vector2<f32>) -> f32 {
+        let absed = translation.abs();
+        let symmetric_cost = (absed.transpose() * self.weights * absed).x;
+        let asymmetric_cost = translation.dot(&self.asymmetry.diagonal());
+        symmetric_cost.sqrt() + asymmetric_cost
+    }
+
     /// SIMD version of the directional weighting
     pub fn weight_points_simba<const L: usize>(
         &self,
@@ -163,3 +201,29 @@ impl SpecificEnergyCost {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drift_is_read_off_the_diagonal() {
+        let cost = SpecificEnergyCost::from_penalties(1.5, 0.1, 1.0);
+        let translations = [[0.0, 1.0], [0.0, -1.0], [3.0, -2.0], [-1.0, 0.5]];
+        for [x, y] in translations {
+            let scalar = cost.weight_scalar(nalgebra::Vector2::new(x, y));
+            let lanes = nalgebra::Vector2::new(
+                simba::simd::Simd(simd::Simd::<f32, 4>::splat(x)),
+                simba::simd::Simd(simd::Simd::<f32, 4>::splat(y)),
+            );
+            assert!((cost.weight(lanes)[1] - scalar).abs() < 1e-5);
+        }
+
+        // Without penalties, every direction weighs the same
+        let identity = SpecificEnergyCost::identity();
+        for translation in [[1.0, 0.0], [-1.0, 0.0], [0.0, 1.0], [0.0, -1.0]] {
+            let weight = identity.weight_scalar(nalgebra::Vector2::from(translation));
+            assert!((weight - 1.0).abs() < 1e-6);
+        }
+    }
+}
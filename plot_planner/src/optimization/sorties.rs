@@ -0,0 +1,258 @@
+use crate::optimization::{MetricSequenceCostFunction, OptimizationSettings};
+use crate::path::TourItem;
+use nalgebra::Point2;
+
+/// What a single flight can do, see [`OptimizationSettings::split_sorties`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SortieSettings {
+    /// Where each sortie takes off from & comes back to, to swap battery & refill paint
+    pub base: Point2<f32>,
+    /// Energy of a sortie, in the sense of [`OptimizationSettings`], including the way back
+    pub energy_budget: f32,
+    /// Paint of a sortie
+    pub paint_budget: f32,
+    /// Paint sprayed for a dot
+    pub paint_per_dot: f32,
+    /// Paint sprayed along a line, per unit of length
+    pub paint_per_length: f32,
+}
+
+/// A single flight, from the base & back
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sortie {
+    /// Painted in this order, after taking off from the base
+    pub tour: Vec<TourItem>,
+    /// Including the legs from & to the base
+    pub energy: f32,
+    pub paint: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortieError {
+    /// Even alone in its sortie, this primitive exceeds a budget
+    ExceedsBudget(TourItem),
+}
+
+impl SortieSettings {
+    /// Paint sprayed for a primitive, `edges` being indices within `points`
+    pub fn paint(&self, item: TourItem, points: &[Point2<f32>], edges: &[[usize; 2]]) -> f32 {
+        match item {
+            TourItem::Dot(_) => self.paint_per_dot,
+            TourItem::Edge { index, .. } => {
+                let [from, to] = edges[index];
+                (points[to] - points[from]).norm() * self.paint_per_length
+            }
+        }
+    }
+}
+
+impl OptimizationSettings {
+    /// Splits `tour` (of `dots` & `edges`, indices within `points`) into consecutive sorties,
+    /// each within the budgets of `sorties`.
+    ///
+    /// The split is optimal for that order (Prins' route-first cluster-second):
+    /// a shortest path over the positions within the tour, where the arcs are the feasible sorties.
+    /// As every sortie leaves from & comes back to the base, their total only differs in deadheading.
+    pub fn split_sorties(
+        &self,
+        points: &[Point2<f32>],
+        dots: &[usize],
+        edges: &[[usize; 2]],
+        tour: &[TourItem],
+        sorties: &SortieSettings,
+    ) -> Result<Vec<Sortie>, SortieError> {
+        let n = tour.len();
+        // best[j]: the cheapest split of the first j items, and where its last sortie begins
+        let mut best = vec![(f32::INFINITY, 0); n + 1];
+        best[0].0 = 0.0;
+
+        for i in 0..n {
+            let offset = best[i].0;
+            if offset.is_infinite() {
+                continue;
+            }
+            let mut prev = None;
+            let mut current = sorties.base;
+            let mut energy = 0.0;
+            let mut paint = 0.0;
+            for (j, item) in tour.iter().enumerate().skip(i) {
+                for vertex in item_vertices(*item, points, dots, edges) {
+                    energy += self.compute_metric_sequence_weight_scalar(prev, current, vertex);
+                    prev = Some(current);
+                    current = vertex;
+                }
+                paint += sorties.paint(*item, points, edges);
+
+                let total = energy
+                    + self.compute_metric_sequence_weight_scalar(prev, current, sorties.base);
+                let feasible = total <= sorties.energy_budget && paint <= sorties.paint_budget;
+                if !feasible && j == i {
+                    return Err(SortieError::ExceedsBudget(*item));
+                }
+                // Only the way back may get cheaper with more items
+                if energy > sorties.energy_budget || paint > sorties.paint_budget {
+                    break;
+                }
+                if feasible && offset + total < best[j + 1].0 {
+                    best[j + 1] = (offset + total, i);
+                }
+            }
+        }
+
+        let mut bounds = vec![n];
+        while let Some(&end) = bounds.last()
+            && end > 0
+        {
+            bounds.push(best[end].1);
+        }
+        bounds.reverse();
+
+        Ok(bounds
+            .windows(2)
+            .map(|bound| {
+                let tour = tour[bound[0]..bound[1]].to_vec();
+                let energy = self.sortie_energy(points, dots, edges, &tour, sorties.base);
+                let paint = tour
+                    .iter()
+                    .map(|item| sorties.paint(*item, points, edges))
+                    .sum();
+                Sortie {
+                    tour,
+                    energy,
+                    paint,
+                }
+            })
+            .collect())
+    }
+
+    /// Energy of painting `tour` from `base` and back
+    fn sortie_energy(
+        &self,
+        points: &[Point2<f32>],
+        dots: &[usize],
+        edges: &[[usize; 2]],
+        tour: &[TourItem],
+        base: Point2<f32>,
+    ) -> f32 {
+        let vertices = tour
+            .iter()
+            .flat_map(|item| item_vertices(*item, points, dots, edges))
+            .chain([base])
+            .collect::<Vec<_>>();
+        let order = (0..vertices.len()).collect::<Vec<_>>();
+        let from_base = Self {
+            start: base,
+            include_start: true,
+            ..self.clone()
+        };
+        from_base.points_cost(|k| vertices[k], &order)
+    }
+}
+
+/// The positions visited by a primitive, in painting order
+fn item_vertices(
+    item: TourItem,
+    points: &[Point2<f32>],
+    dots: &[usize],
+    edges: &[[usize; 2]],
+) -> impl Iterator<Item = Point2<f32>> {
+    let vertices = match item {
+        TourItem::Dot(index) => [Some(points[dots[index]]), None],
+        TourItem::Edge { index, reversed } => {
+            let [from, to] = edges[index];
+            let [from, to] = if reversed { [to, from] } else { [from, to] };
+            [Some(points[from]), Some(points[to])]
+        }
+    };
+    vertices.into_iter().flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimization::test_utils;
+
+    #[test]
+    fn sorties_stay_within_budgets() {
+        let settings = test_utils::settings([1.0, 1.0, 1.0], 0.1);
+        // A row of dots, then lines back above it
+        let mut points = (0..20)
+            .map(|i| Point2::new(i as f32, 1.0))
+            .collect::<Vec<_>>();
+        points.extend((0..10).flat_map(|i| {
+            let x = 19.0 - 2.0 * i as f32;
+            [Point2::new(x, 3.0), Point2::new(x - 1.5, 3.0)]
+        }));
+        let dots = (0..20).collect::<Vec<_>>();
+        let edges = (0..10)
+            .map(|i| [20 + 2 * i, 21 + 2 * i])
+            .collect::<Vec<_>>();
+        let tour = (0..20)
+            .map(TourItem::Dot)
+            .chain((0..10).map(|index| TourItem::Edge {
+                index,
+                reversed: false,
+            }))
+            .collect::<Vec<_>>();
+        let sorties = SortieSettings {
+            base: Point2::new(10.0, -2.0),
+            energy_budget: 60.0,
+            paint_budget: 6.0,
+            paint_per_dot: 1.0,
+            paint_per_length: 1.0,
+        };
+
+        let split = settings
+            .split_sorties(&points, &dots, &edges, &tour, &sorties)
+            .unwrap();
+        assert_eq!(
+            split
+                .iter()
+                .flat_map(|sortie| sortie.tour.iter().copied())
+                .collect::<Vec<_>>(),
+            tour
+        );
+        for sortie in &split {
+            assert!(sortie.energy <= sorties.energy_budget, "{sortie:?}");
+            assert!(sortie.paint <= sorties.paint_budget, "{sortie:?}");
+        }
+
+        // At least as good as filling each sortie up in turn
+        let mut greedy = 0.0;
+        let mut begin = 0;
+        while begin < tour.len() {
+            let mut end = begin + 1;
+            while end < tour.len() {
+                let candidate = &tour[begin..end + 1];
+                let paint = candidate
+                    .iter()
+                    .map(|item| sorties.paint(*item, &points, &edges))
+                    .sum::<f32>();
+                let energy =
+                    settings.sortie_energy(&points, &dots, &edges, candidate, sorties.base);
+                if paint > sorties.paint_budget || energy > sorties.energy_budget {
+                    break;
+                }
+                end += 1;
+            }
+            greedy +=
+                settings.sortie_energy(&points, &dots, &edges, &tour[begin..end], sorties.base);
+            begin = end;
+        }
+        let total = split.iter().map(|sortie| sortie.energy).sum::<f32>();
+        assert!(total <= greedy + 1e-3, "{total} {greedy}");
+
+        // A line longer than the paint budget cannot be painted at all
+        let too_much = SortieSettings {
+            paint_budget: 1.0,
+            ..sorties
+        };
+        assert_eq!(
+            settings.split_sorties(&points, &dots, &edges, &tour, &too_much),
+            Err(SortieError::ExceedsBudget(TourItem::Edge {
+                index: 0,
+                reversed: false
+            }))
+        );
+    }
+}
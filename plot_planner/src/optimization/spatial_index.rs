@@ -0,0 +1,290 @@
+/// A uniform grid over a set of points, from which points can be removed once visited.
+///
+/// Queries walk the cells in square rings around the query point.
+/// After ring `r`, every point left unseen is at least [`Self::ring_distance`]`(r)` away,
+/// which lets cost-aware queries stop early, as long as the cost is bounded by the distance.
+#[derive(Debug, Clone)]
+pub struct UniformGrid {
+    origin: nalgebra::Point2<f32>,
+    cell_size: f32,
+    width: usize,
+    height: usize,
+
+    positions: Vec<nalgebra::Point2<f32>>,
+    /// The points of each cell, the alive ones first: cell `c` holds `entries[starts[c]..starts[c + 1]]`
+    starts: Vec<usize>,
+    entries: Vec<usize>,
+    /// Number of alive points of each cell
+    alive: Vec<usize>,
+    /// Cell of each point, and its slot within `entries`
+    cell_of: Vec<usize>,
+    slot_of: Vec<usize>,
+    len: usize,
+}
+
+impl UniformGrid {
+    /// Points per cell, on average
+    const OCCUPANCY: f32 = 2.0;
+
+    pub fn new<X, Y>(points: &crate::generation::PointSlice<X, Y>) -> Self
+    where
+        X: core::ops::Deref<Target = [f32]>,
+        Y: core::ops::Deref<Target = [f32]>,
+    {
+        let n = points.len();
+        let positions = points.x[..n]
+            .iter()
+            .zip(points.y[..n].iter())
+            .map(|(x, y)| nalgebra::Point2::new(*x, *y))
+            .collect::<Vec<_>>();
+
+        let (min, max) = positions.iter().fold(
+            (
+                nalgebra::Point2::new(f32::INFINITY, f32::INFINITY),
+                nalgebra::Point2::new(f32::NEG_INFINITY, f32::NEG_INFINITY),
+            ),
+            |(min, max), p| (min.inf(p), max.sup(p)),
+        );
+        let (origin, extent) = if n == 0 {
+            (nalgebra::Point2::origin(), nalgebra::Vector2::zeros())
+        } else {
+            (min, max - min)
+        };
+
+        let area = (extent.x * extent.y).max(f32::EPSILON);
+        let cell_size = (area * Self::OCCUPANCY / (n.max(1) as f32))
+            .sqrt()
+            .max(extent.x.max(extent.y) / (n.max(1) as f32))
+            .max(f32::EPSILON);
+        let width = ((extent.x / cell_size) as usize) + 1;
+        let height = ((extent.y / cell_size) as usize) + 1;
+
+        let mut grid = Self {
+            origin,
+            cell_size,
+            width,
+            height,
+            positions,
+            starts: vec![0; width * height + 1],
+            entries: vec![0; n],
+            alive: vec![0; width * height],
+            cell_of: vec![0; n],
+            slot_of: vec![0; n],
+            len: n,
+        };
+
+        // Counting sort of the points by cell
+        for i in 0..n {
+            let cell = grid.cell(grid.positions[i]);
+            grid.cell_of[i] = cell;
+            grid.alive[cell] += 1;
+        }
+        for cell in 0..width * height {
+            grid.starts[cell + 1] = grid.starts[cell] + grid.alive[cell];
+        }
+        let mut fill = grid.starts.clone();
+        for i in 0..n {
+            let cell = grid.cell_of[i];
+            grid.entries[fill[cell]] = i;
+            grid.slot_of[i] = fill[cell];
+            fill[cell] += 1;
+        }
+
+        grid
+    }
+
+    /// Number of points left
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn contains(&self, index: usize) -> bool {
+        let cell = self.cell_of[index];
+        self.slot_of[index] < self.starts[cell] + self.alive[cell]
+    }
+
+    pub fn position(&self, index: usize) -> nalgebra::Point2<f32> {
+        self.positions[index]
+    }
+
+    /// Removes a point from the queries, in constant time
+    pub fn remove(&mut self, index: usize) {
+        if !self.contains(index) {
+            return;
+        }
+
+        // Swap it with the last alive point of its cell
+        let cell = self.cell_of[index];
+        let last = self.starts[cell] + self.alive[cell] - 1;
+        let slot = self.slot_of[index];
+        let other = self.entries[last];
+        self.entries.swap(slot, last);
+        self.slot_of[other] = slot;
+        self.slot_of[index] = last;
+
+        self.alive[cell] -= 1;
+        self.len -= 1;
+    }
+
+    fn coordinates(&self, position: nalgebra::Point2<f32>) -> (usize, usize) {
+        let local = (position - self.origin) / self.cell_size;
+        let clamp = |v: f32, size: usize| (v.max(0.0) as usize).min(size - 1);
+        (clamp(local.x, self.width), clamp(local.y, self.height))
+    }
+
+    fn cell(&self, position: nalgebra::Point2<f32>) -> usize {
+        let (i, j) = self.coordinates(position);
+        j * self.width + i
+    }
+
+    /// Number of rings needed to cover the whole grid from any point
+    pub fn ring_count(&self) -> usize {
+        self.width.max(self.height)
+    }
+
+    /// The distance under which every point has been seen, once the rings up to `ring` are walked
+    pub fn ring_distance(&self, ring: usize) -> f32 {
+        (ring as f32) * self.cell_size
+    }
+
+    /// Pushes the points left in the cells at a Chebyshev distance of `ring` cells from `query`
+    pub fn ring(&self, query: nalgebra::Point2<f32>, ring: usize, out: &mut Vec<usize>) {
+        let (ci, cj) = self.coordinates(query);
+        let (ci, cj, r) = (ci as isize, cj as isize, ring as isize);
+
+        let mut push_cell = |i: isize, j: isize| {
+            if i < 0 || j < 0 || i >= self.width as isize || j >= self.height as isize {
+                return;
+            }
+            let cell = (j as usize) * self.width + (i as usize);
+            let start = self.starts[cell];
+            out.extend_from_slice(&self.entries[start..start + self.alive[cell]]);
+        };
+
+        if r == 0 {
+            push_cell(ci, cj);
+            return;
+        }
+        for i in (ci - r)..=(ci + r) {
+            push_cell(i, cj - r);
+            push_cell(i, cj + r);
+        }
+        for j in (cj - r + 1)..=(cj + r - 1) {
+            push_cell(ci - r, j);
+            push_cell(ci + r, j);
+        }
+    }
+
+    /// The `k` nearest points left, sorted by increasing distance
+    pub fn k_nearest(&self, query: nalgebra::Point2<f32>, k: usize) -> Vec<(usize, f32)> {
+        let mut found = Vec::with_capacity(k + 1);
+        if k == 0 {
+            return found;
+        }
+        let mut candidates = Vec::new();
+        for ring in 0..self.ring_count() {
+            candidates.clear();
+            self.ring(query, ring, &mut candidates);
+            found.extend(
+                candidates
+                    .iter()
+                    .map(|i| (*i, (self.positions[*i] - query).norm())),
+            );
+            found.sort_by(|(_, a): &(usize, f32), (_, b)| a.total_cmp(b));
+            found.truncate(k);
+
+            if found.len() == k && found[k - 1].1 <= self.ring_distance(ring) {
+                break;
+            }
+        }
+        found
+    }
+
+    /// The point left with the lowest cost.
+    ///
+    /// `lower_bound` gives the minimal cost of a point at a given distance from the query,
+    /// so that the search stops as soon as no unseen point can beat the best one.
+    /// It only stops early if the bound grows with the distance: with a bound never above
+    /// the best cost (e.g. a cost which may be zero or negative), every point is looked at.
+    pub fn nearest_by(
+        &self,
+        query: nalgebra::Point2<f32>,
+        lower_bound: impl Fn(f32) -> f32,
+        mut cost: impl FnMut(usize) -> f32,
+    ) -> Option<(usize, f32)> {
+        let mut best: Option<(usize, f32)> = None;
+        let mut candidates = Vec::new();
+        for ring in 0..self.ring_count() {
+            candidates.clear();
+            self.ring(query, ring, &mut candidates);
+            for i in candidates.iter() {
+                let cost = cost(*i);
+                if best.is_none_or(|(_, best)| cost < best) {
+                    best = Some((*i, cost));
+                }
+            }
+
+            if best.is_some_and(|(_, best)| best <= lower_bound(self.ring_distance(ring))) {
+                break;
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimization::test_utils;
+
+    #[test]
+    fn k_nearest_matches_brute_force() {
+        let (x, y) = test_utils::random_points(7, 500, [100.0, 30.0]);
+        let slice = crate::generation::PointSlice {
+            x: &x[..],
+            y: &y[..],
+        };
+        let mut grid = UniformGrid::new(&slice);
+        for i in (0..500).step_by(3) {
+            grid.remove(i);
+        }
+        assert_eq!(grid.len(), 500 - 167);
+
+        let query = nalgebra::Point2::new(42.0, 12.0);
+        let mut brute = (0..500)
+            .filter(|i| i % 3 != 0)
+            .map(|i| (i, (nalgebra::Point2::new(x[i], y[i]) - query).norm()))
+            .collect::<Vec<_>>();
+        brute.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+        brute.truncate(10);
+
+        assert_eq!(grid.k_nearest(query, 10), brute);
+    }
+
+    #[test]
+    fn nearest_by_asymmetric_cost() {
+        let (x, y) = test_utils::random_points(7, 300, [100.0, 30.0]);
+        let slice = crate::generation::PointSlice {
+            x: &x[..],
+            y: &y[..],
+        };
+        let grid = UniformGrid::new(&slice);
+
+        let cost = crate::optimization::SpecificEnergyCost::from_penalties(1.5, 0.1, 1.0);
+        let min = cost.min_spec_e();
+        assert!(min <= 0.1 && min > 0.09, "{min}");
+
+        let query = nalgebra::Point2::new(60.0, 5.0);
+        let weight = |i: usize| cost.weight_scalar(grid.position(i) - query);
+        let brute = (0..300)
+            .map(|i| (i, weight(i)))
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .unwrap();
+
+        assert_eq!(grid.nearest_by(query, |d| d * min, weight), Some(brute));
+    }
+}
@@ -0,0 +1,40 @@
+// Fixtures shared by the tests of the optimizers
+
+use super::{DirectionChangePenalty, OptimizationSettings, SpecificEnergyCost};
+use rand::{Rng, SeedableRng};
+
+/// Settings from the directional penalties of [`SpecificEnergyCost::from_penalties`],
+/// leaving from the origin
+pub(crate) fn settings([up, down, sideways]: [f32; 3], penalty: f32) -> OptimizationSettings {
+    OptimizationSettings {
+        specific_energy: SpecificEnergyCost::from_penalties(up, down, sideways),
+        penalty: DirectionChangePenalty(penalty),
+        start: nalgebra::Point2::origin(),
+        include_start: true,
+    }
+}
+
+/// `n` points drawn uniformly over `[0, width) x [0, height)`
+pub(crate) fn random_points(
+    seed: u64,
+    n: usize,
+    [width, height]: [f32; 2],
+) -> (Vec<f32>, Vec<f32>) {
+    let mut rng = rand_xoshiro::Xoroshiro64Star::seed_from_u64(seed);
+    (0..n)
+        .map(|_| (rng.random_range(0.0..width), rng.random_range(0.0..height)))
+        .unzip()
+}
+
+/// Same as [`random_points`], as positions
+pub(crate) fn random_positions(
+    seed: u64,
+    n: usize,
+    extent: [f32; 2],
+) -> Vec<nalgebra::Point2<f32>> {
+    let (x, y) = random_points(seed, n, extent);
+    x.into_iter()
+        .zip(y)
+        .map(|(x, y)| nalgebra::Point2::new(x, y))
+        .collect()
+}
@@ -0,0 +1,485 @@
+use super::floyd_warshall::{RoutingTable, floyd_warshall_blocked};
+use super::gryf_algo::MetricSequenceCostFunction;
+use crate::generation::Point;
+use crate::path::TourItem;
+use nalgebra::Point2;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Intersections closer than this to the boundary of an obstacle are grazing it, not crossing it
+const EPSILON: f32 = 1e-4;
+
+/// A tour that cannot be flown, see [`crate::path::SectionBuffer::iter_tour_routed`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RoutingError {
+    /// No obstacle-free path leads to this primitive, e.g. it is enclosed by obstacles
+    Unroutable(TourItem),
+}
+
+/// Shortest obstacle-free transit paths between points, through a visibility graph
+/// of the vertices of the (inflated) obstacles.
+#[derive(Debug, Clone)]
+pub struct TransitRouter {
+    /// The inflated obstacles, convex & counter-clockwise
+    obstacles: Vec<Vec<Point2<f32>>>,
+    /// The bounding box of each obstacle, `[min, max]`
+    bounds: Vec<[Point2<f32>; 2]>,
+    /// The vertices of the obstacles, the waypoints of the detours.
+    /// Those within another obstacle cannot be flown to.
+    nodes: Vec<Point2<f32>>,
+    usable: Vec<bool>,
+    /// Shortest paths between the nodes
+    table: RoutingTable<f32>,
+}
+
+impl TransitRouter {
+    /// `obstacles` are world-space polygons, kept at `clearance` (e.g. the drone radius and a
+    /// safety margin). Concave polygons are replaced by their convex hull.
+    pub fn new(obstacles: &[Vec<Point2<f32>>], clearance: f32) -> Self {
+        /// Each corner is rounded with arcs of at most this angle
+        const ARC: f32 = core::f32::consts::FRAC_PI_4;
+        /// The pivots of the shortest paths are blocked by this many
+        const BLOCK_SIZE: usize = 64;
+
+        let obstacles = obstacles
+            .iter()
+            .map(|polygon| convex_hull(polygon))
+            .filter(|hull| !hull.is_empty())
+            .map(|hull| inflate(&hull, clearance.max(0.0), ARC))
+            .collect::<Vec<_>>();
+        let bounds = obstacles
+            .iter()
+            .map(|polygon| {
+                polygon.iter().fold(
+                    [
+                        Point2::new(f32::MAX, f32::MAX),
+                        Point2::new(f32::MIN, f32::MIN),
+                    ],
+                    |[min, max], p| [min.inf(p), max.sup(p)],
+                )
+            })
+            .collect();
+
+        let mut router = Self {
+            obstacles,
+            bounds,
+            nodes: Vec::new(),
+            usable: Vec::new(),
+            table: RoutingTable::new(0),
+        };
+        router.nodes = router.obstacles.iter().flatten().copied().collect();
+        router.usable = router
+            .nodes
+            .iter()
+            .map(|node| !router.obstacles.iter().any(|o| contains(o, *node)))
+            .collect();
+
+        let n = router.nodes.len();
+        router.table = RoutingTable::new(n);
+        for a in 0..n {
+            for b in a + 1..n {
+                let (p, q) = (router.nodes[a], router.nodes[b]);
+                if router.usable[a] && router.usable[b] && router.is_clear_between_nodes(p, q) {
+                    let distance = (q - p).norm();
+                    router.table.add_edge(a, b, distance);
+                    router.table.add_edge(b, a, distance);
+                }
+            }
+        }
+        floyd_warshall_blocked(&mut router.table, BLOCK_SIZE);
+        router
+    }
+
+    /// The inflated obstacles, convex & counter-clockwise
+    pub fn obstacles(&self) -> &[Vec<Point2<f32>>] {
+        &self.obstacles
+    }
+
+    /// Whether the straight move crosses no obstacle.
+    ///
+    /// The obstacles containing either end are ignored, so that points painted
+    /// within the clearance can still be reached.
+    pub fn is_clear(&self, from: Point2<f32>, to: Point2<f32>) -> bool {
+        self.crossed(from, to)
+            .all(|polygon| contains(polygon, from) || contains(polygon, to))
+    }
+
+    fn is_clear_between_nodes(&self, from: Point2<f32>, to: Point2<f32>) -> bool {
+        self.crossed(from, to).next().is_none()
+    }
+
+    /// The obstacles whose interior the segment crosses
+    fn crossed(
+        &self,
+        from: Point2<f32>,
+        to: Point2<f32>,
+    ) -> impl Iterator<Item = &Vec<Point2<f32>>> + '_ {
+        let (min, max) = (from.inf(&to), from.sup(&to));
+        self.obstacles
+            .iter()
+            .zip(self.bounds.iter())
+            .filter(move |(_, [low, high])| {
+                min.x <= high.x && low.x <= max.x && min.y <= high.y && low.y <= max.y
+            })
+            .map(|(polygon, _)| polygon)
+            .filter(move |polygon| crosses(polygon, from, to))
+    }
+
+    /// The waypoints of the shortest obstacle-free path from `from` to `to`, both excluded:
+    /// none if the straight move is clear, and `None` if there is no way around.
+    pub fn detour(&self, from: Point2<f32>, to: Point2<f32>) -> Option<Vec<Point2<f32>>> {
+        if self.is_clear(from, to) {
+            return Some(Vec::new());
+        }
+
+        let visible = |p: Point2<f32>| {
+            (0..self.nodes.len())
+                .filter(|node| self.usable[*node] && self.is_clear(p, self.nodes[*node]))
+                .collect::<Vec<_>>()
+        };
+        let (entries, exits) = (visible(from), visible(to));
+        let mut best: Option<(f32, usize, usize)> = None;
+        for entry in entries.iter() {
+            let approach = (self.nodes[*entry] - from).norm();
+            for exit in exits.iter() {
+                let Some(distance) = self.table.distance(*entry, *exit) else {
+                    continue;
+                };
+                let length = approach + distance + (to - self.nodes[*exit]).norm();
+                if best.is_none_or(|(best, ..)| length < best) {
+                    best = Some((length, *entry, *exit));
+                }
+            }
+        }
+
+        let (_, entry, exit) = best?;
+        let path = self.table.path(entry, exit)?;
+        Some(path.into_iter().map(|node| self.nodes[node]).collect())
+    }
+
+    /// The length of the shortest obstacle-free path, if any
+    pub fn transit_length(&self, from: Point2<f32>, to: Point2<f32>) -> Option<f32> {
+        let detour = self.detour(from, to)?;
+        let legs = core::iter::once(from)
+            .chain(detour)
+            .chain([to])
+            .collect::<Vec<_>>();
+        Some(legs.windows(2).map(|leg| (leg[1] - leg[0]).norm()).sum())
+    }
+}
+
+/// The waypoints of a move, `None` if it has no way around, see [`TransitRouter::detour`]
+type Detour = Option<Vec<Point2<f32>>>;
+
+/// A cost function whose moves go around the obstacles of a [`TransitRouter`].
+///
+/// A blocked move weighs its detour, each leg after the previous one.
+/// Moves without a way around are masked out.
+/// The detours are looked up once per pair of points, then cached.
+#[derive(Debug)]
+pub struct ObstacleAwareCost<'a, CF> {
+    pub metric: CF,
+    pub router: &'a TransitRouter,
+    /// The detours, by the bits of the endpoints of the moves
+    detours: Mutex<HashMap<[u32; 4], Detour>>,
+}
+
+impl<'a, CF> ObstacleAwareCost<'a, CF> {
+    pub fn new(metric: CF, router: &'a TransitRouter) -> Self {
+        Self {
+            metric,
+            router,
+            detours: Mutex::default(),
+        }
+    }
+}
+
+impl<CF: Clone> Clone for ObstacleAwareCost<'_, CF> {
+    fn clone(&self) -> Self {
+        Self {
+            metric: self.metric.clone(),
+            router: self.router,
+            detours: Mutex::new(self.detours.lock().unwrap().clone()),
+        }
+    }
+}
+
+impl<CF> ObstacleAwareCost<'_, CF>
+where
+    CF: MetricSequenceCostFunction<Weight = f32>,
+{
+    /// The cached detour of the move, see [`TransitRouter::detour`]
+    fn with_detour<R>(
+        &self,
+        from: Point2<f32>,
+        to: Point2<f32>,
+        f: impl FnOnce(Option<&[Point2<f32>]>) -> R,
+    ) -> R {
+        let key = [from.x, from.y, to.x, to.y].map(f32::to_bits);
+        let mut detours = self.detours.lock().unwrap();
+        let detour = detours
+            .entry(key)
+            .or_insert_with(|| self.router.detour(from, to));
+        f(detour.as_deref())
+    }
+
+    /// The weight of the legs of a detour, each after the previous one
+    fn detour_weight(
+        &self,
+        prev: Option<Point2<f32>>,
+        from: Point2<f32>,
+        to: Point2<f32>,
+        detour: &[Point2<f32>],
+    ) -> f32 {
+        let mut prev = prev;
+        let mut weight = 0.0;
+        let mut position = from;
+        for waypoint in detour.iter().copied().chain([to]) {
+            weight += self
+                .metric
+                .compute_metric_sequence_weight_scalar(prev, position, waypoint);
+            prev = Some(position);
+            position = waypoint;
+        }
+        weight
+    }
+}
+
+impl<CF> MetricSequenceCostFunction for ObstacleAwareCost<'_, CF>
+where
+    CF: MetricSequenceCostFunction<Weight = f32>,
+{
+    type Weight = f32;
+
+    fn compute_metric_sequence_weight<const L: usize>(
+        &self,
+        prev: Option<Point<L>>,
+        from: Point<L>,
+        to: Point<L>,
+    ) -> (
+        core::simd::Simd<Self::Weight, L>,
+        core::simd::Mask<isize, L>,
+    ) {
+        let (weights, mask) = self.metric.compute_metric_sequence_weight(prev, from, to);
+        let (mut weights, mut mask) = (weights.to_array(), mask);
+
+        let lane = |p: &Point<L>, i: usize| Point2::new(p.x[i], p.y[i]);
+        for (i, weight) in weights.iter_mut().enumerate() {
+            let (a, b) = (lane(&from, i), lane(&to, i));
+            self.with_detour(a, b, |detour| match detour {
+                Some([]) => {}
+                Some(detour) => {
+                    *weight = self.detour_weight(prev.as_ref().map(|p| lane(p, i)), a, b, detour)
+                }
+                None => {
+                    *weight = f32::INFINITY;
+                    mask.set(i, false);
+                }
+            });
+        }
+        (core::simd::Simd::from_array(weights), mask)
+    }
+
+    fn compute_metric_sequence_weight_scalar(
+        &self,
+        prev: Option<Point2<f32>>,
+        from: Point2<f32>,
+        to: Point2<f32>,
+    ) -> Self::Weight {
+        self.with_detour(from, to, |detour| match detour {
+            Some([]) => self
+                .metric
+                .compute_metric_sequence_weight_scalar(prev, from, to),
+            Some(detour) => self.detour_weight(prev, from, to, detour),
+            None => f32::INFINITY,
+        })
+    }
+}
+
+/// The convex hull of the points, counter-clockwise (Andrew's monotone chain)
+fn convex_hull(points: &[Point2<f32>]) -> Vec<Point2<f32>> {
+    let mut points = points.to_vec();
+    points.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+
+    let cross = |o: Point2<f32>, a: Point2<f32>, b: Point2<f32>| (a - o).perp(&(b - o));
+    let mut hull: Vec<Point2<f32>> = Vec::with_capacity(points.len() * 2);
+    for pass in [points.clone(), points.into_iter().rev().collect()] {
+        let start = hull.len();
+        for p in pass {
+            while hull.len() >= start + 2
+                && cross(hull[hull.len() - 2], hull[hull.len() - 1], p) <= 0.0
+            {
+                hull.pop();
+            }
+            hull.push(p);
+        }
+        // The last point is the first of the other chain
+        hull.pop();
+    }
+    hull
+}
+
+/// The polygon circumscribing the points within `clearance` of the convex polygon,
+/// its corners rounded with arcs of at most `arc`
+fn inflate(hull: &[Point2<f32>], clearance: f32, arc: f32) -> Vec<Point2<f32>> {
+    let n = hull.len();
+    if clearance == 0.0 && n >= 3 {
+        return hull.to_vec();
+    }
+
+    // The outward normal of the edge from `hull[i]`, around degenerate hulls too
+    let normal = |i: usize| {
+        let edge = hull[(i + 1) % n] - hull[i];
+        if edge.norm() > 0.0 {
+            nalgebra::Vector2::new(edge.y, -edge.x).normalize()
+        } else {
+            nalgebra::Vector2::new(0.0, -1.0)
+        }
+    };
+    let angle = |v: nalgebra::Vector2<f32>| v.y.atan2(v.x);
+
+    let mut polygon = Vec::new();
+    for (i, corner) in hull.iter().enumerate() {
+        // Around the corner, from the normal of the previous edge to that of the next one
+        let from = angle(normal((i + n - 1) % n));
+        let mut turn = angle(normal(i)) - from;
+        if turn < 0.0 {
+            turn += core::f32::consts::TAU;
+        }
+        // A single point degenerates to a full circle
+        if n == 1 {
+            turn = core::f32::consts::TAU;
+        }
+        let steps = ((turn / arc).ceil() as usize).max(1);
+        let step = turn / steps as f32;
+
+        // The tangents to the arc at each step meet at this distance from the corner
+        let distance = clearance / (step / 2.0).cos();
+        for s in 0..steps {
+            let direction = from + step * (s as f32 + 0.5);
+            polygon
+                .push(corner + nalgebra::Vector2::new(direction.cos(), direction.sin()) * distance);
+        }
+    }
+    polygon
+}
+
+/// Whether the point is strictly within the convex polygon
+fn contains(polygon: &[Point2<f32>], p: Point2<f32>) -> bool {
+    polygon.len() >= 3
+        && (0..polygon.len()).all(|i| {
+            let (a, b) = (polygon[i], polygon[(i + 1) % polygon.len()]);
+            (b - a).perp(&(p - a)) > EPSILON * (b - a).norm()
+        })
+}
+
+/// Whether the segment crosses the interior of the convex polygon (Cyrus–Beck clipping)
+fn crosses(polygon: &[Point2<f32>], from: Point2<f32>, to: Point2<f32>) -> bool {
+    if polygon.len() < 3 {
+        return false;
+    }
+    let d = to - from;
+    let (mut t0, mut t1) = (0.0f32, 1.0f32);
+    for i in 0..polygon.len() {
+        let (a, b) = (polygon[i], polygon[(i + 1) % polygon.len()]);
+        let edge = b - a;
+        // Inside is on the left of the edges, by a margin
+        let inside = edge.perp(&(from - a)) - EPSILON * edge.norm();
+        let rate = edge.perp(&d);
+        if rate == 0.0 {
+            if inside <= 0.0 {
+                return false;
+            }
+            continue;
+        }
+        let t = -inside / rate;
+        if rate > 0.0 {
+            t0 = t0.max(t);
+        } else {
+            t1 = t1.min(t);
+        }
+        if t0 >= t1 {
+            return false;
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimization::{
+        MetricSequenceCostFunctionAdapter, OptimizationSettings, rpp, test_utils,
+    };
+
+    fn square(x: f32, y: f32, size: f32) -> Vec<Point2<f32>> {
+        vec![
+            Point2::new(x, y),
+            Point2::new(x + size, y),
+            Point2::new(x + size, y + size),
+            Point2::new(x, y + size),
+        ]
+    }
+
+    #[test]
+    fn detours_go_around_inflated_obstacles() {
+        let router = TransitRouter::new(&[square(-1.0, -1.0, 2.0)], 0.5);
+        let (from, to) = (Point2::new(-5.0, 0.0), Point2::new(5.0, 0.0));
+        assert!(!router.is_clear(from, to));
+        assert!(router.is_clear(Point2::new(-5.0, 2.0), Point2::new(5.0, 2.0)));
+
+        // Around the square, at least the clearance away
+        let detour = router.detour(from, to).unwrap();
+        assert!(!detour.is_empty());
+        let clearance = |p: Point2<f32>| (p.x.abs() - 1.0).max(p.y.abs() - 1.0);
+        for p in detour.iter() {
+            assert!(p.x.abs() >= 1.5 - 1e-3 || p.y.abs() >= 1.5 - 1e-3, "{p}");
+            assert!(clearance(*p) >= 0.5 - 1e-3);
+        }
+        let length = router.transit_length(from, to).unwrap();
+        assert!(length > 10.0 && length < 10.0 + 2.0 * 1.5, "{length}");
+
+        // Points within the clearance can still be reached
+        assert!(
+            router
+                .detour(Point2::new(1.2, 0.0), Point2::new(5.0, 0.0))
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn rpp_avoids_obstacles() {
+        // Two lines on either side of a wall
+        let router = TransitRouter::new(
+            &[vec![Point2::new(0.0, -10.0), Point2::new(0.0, 10.0)]],
+            0.5,
+        );
+        let x = [-2.0, -2.0, 2.0, 2.0];
+        let y = [-1.0, 1.0, 1.0, -1.0];
+        let settings = OptimizationSettings {
+            start: Point2::new(-3.0, 0.0),
+            ..test_utils::settings([1.0, 1.0, 1.0], 0.0)
+        };
+        let points = crate::generation::PointSlice {
+            x: &[&x[..], &[-3.0]].concat()[..],
+            y: &[&y[..], &[0.0]].concat()[..],
+        };
+        let adapter = MetricSequenceCostFunctionAdapter {
+            metric: ObstacleAwareCost::new(settings, &router),
+            points,
+        };
+
+        let edges = crate::generation::EdgeSlice {
+            from: vec![0, 2],
+            to: vec![1, 3],
+        };
+        let tour = rpp::path_scanning::<_, _, 4, _>(&adapter, &edges, &[true; 2], 4, 0.0).unwrap();
+        // Crossing the wall means going around its end, 9 away
+        assert!(tour.deadhead > 18.0, "{}", tour.deadhead);
+        assert!((tour.spray - 4.0).abs() < 1e-3);
+    }
+}
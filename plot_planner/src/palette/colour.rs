@@ -0,0 +1,264 @@
+/// A colour in the CIE L*a*b* space (D65 white point).
+///
+/// `l` is within `[0, 100]`, `a` & `b` are roughly within `[-128, 128]`.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Default)]
+pub struct Lab {
+    pub l: f32,
+    pub a: f32,
+    pub b: f32,
+}
+
+// D65 reference white, in XYZ (Y normalized to 1)
+const WHITE_X: f32 = 0.95047;
+const WHITE_Y: f32 = 1.0;
+const WHITE_Z: f32 = 1.08883;
+
+const EPSILON: f32 = 216.0 / 24389.0;
+const KAPPA: f32 = 24389.0 / 27.0;
+
+#[inline]
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[inline]
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+impl Lab {
+    pub const fn new(l: f32, a: f32, b: f32) -> Self {
+        Self { l, a, b }
+    }
+
+    /// From gamma-encoded sRGB, each channel within `[0, 1]`
+    pub fn from_srgb(rgb: [f32; 3]) -> Self {
+        Self::from_linear_rgb(rgb.map(srgb_to_linear))
+    }
+
+    pub fn from_srgb8(rgb: [u8; 3]) -> Self {
+        Self::from_srgb(rgb.map(|c| (c as f32) / (u8::MAX as f32)))
+    }
+
+    /// From linear sRGB (no gamma), each channel within `[0, 1]`
+    pub fn from_linear_rgb([r, g, b]: [f32; 3]) -> Self {
+        let x = 0.4124564 * r + 0.3575761 * g + 0.1804375 * b;
+        let y = 0.2126729 * r + 0.7151522 * g + 0.0721750 * b;
+        let z = 0.0193339 * r + 0.119192 * g + 0.9503041 * b;
+
+        let f = |t: f32| {
+            if t > EPSILON {
+                t.cbrt()
+            } else {
+                (KAPPA * t + 16.0) / 116.0
+            }
+        };
+        let (fx, fy, fz) = (f(x / WHITE_X), f(y / WHITE_Y), f(z / WHITE_Z));
+
+        Self {
+            l: 116.0 * fy - 16.0,
+            a: 500.0 * (fx - fy),
+            b: 200.0 * (fy - fz),
+        }
+    }
+
+    /// To linear sRGB, clamped within `[0, 1]`
+    pub fn to_linear_rgb(&self) -> [f32; 3] {
+        let fy = (self.l + 16.0) / 116.0;
+        let fx = fy + self.a / 500.0;
+        let fz = fy - self.b / 200.0;
+
+        let f_inv = |t: f32| {
+            let t3 = t * t * t;
+            if t3 > EPSILON {
+                t3
+            } else {
+                (116.0 * t - 16.0) / KAPPA
+            }
+        };
+        let (x, y, z) = (
+            f_inv(fx) * WHITE_X,
+            f_inv(fy) * WHITE_Y,
+            f_inv(fz) * WHITE_Z,
+        );
+
+        [
+            3.2404542 * x - 1.5371385 * y - 0.4985314 * z,
+            -0.969266 * x + 1.8760108 * y + 0.0415560 * z,
+            0.0556434 * x - 0.2040259 * y + 1.0572252 * z,
+        ]
+        .map(|c| c.clamp(0.0, 1.0))
+    }
+
+    pub fn to_srgb8(&self) -> [u8; 3] {
+        self.to_linear_rgb()
+            .map(|c| (linear_to_srgb(c) * (u8::MAX as f32)).round() as u8)
+    }
+
+    /// Chroma, i.e. the distance to the neutral axis
+    #[inline]
+    pub fn chroma(&self) -> f32 {
+        self.a.hypot(self.b)
+    }
+}
+
+impl core::ops::Add for Lab {
+    type Output = Self;
+
+    #[inline(always)]
+    fn add(self, rhs: Self) -> Self::Output {
+        Self {
+            l: self.l + rhs.l,
+            a: self.a + rhs.a,
+            b: self.b + rhs.b,
+        }
+    }
+}
+
+impl core::ops::Sub for Lab {
+    type Output = Self;
+
+    #[inline(always)]
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self {
+            l: self.l - rhs.l,
+            a: self.a - rhs.a,
+            b: self.b - rhs.b,
+        }
+    }
+}
+
+impl core::ops::Mul<f32> for Lab {
+    type Output = Self;
+
+    #[inline(always)]
+    fn mul(self, rhs: f32) -> Self::Output {
+        Self {
+            l: self.l * rhs,
+            a: self.a * rhs,
+            b: self.b * rhs,
+        }
+    }
+}
+
+/// The perceptual colour difference used to match a pixel to a can.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColourDistance {
+    /// Euclidean distance in L*a*b*. Cheap, but overestimates differences in saturated colours.
+    Cie76,
+    /// Graphic arts weighting of CIE94
+    Cie94,
+    /// Most accurate, and the most expensive
+    #[default]
+    Ciede2000,
+}
+
+impl ColourDistance {
+    /// The ΔE between two colours
+    pub fn delta_e(&self, x: &Lab, y: &Lab) -> f32 {
+        match self {
+            Self::Cie76 => delta_e_76(x, y),
+            Self::Cie94 => delta_e_94(x, y),
+            Self::Ciede2000 => delta_e_2000(x, y),
+        }
+    }
+}
+
+fn delta_e_76(x: &Lab, y: &Lab) -> f32 {
+    let d = *x - *y;
+    (d.l * d.l + d.a * d.a + d.b * d.b).sqrt()
+}
+
+fn delta_e_94(x: &Lab, y: &Lab) -> f32 {
+    // Graphic arts constants
+    const K_1: f32 = 0.045;
+    const K_2: f32 = 0.015;
+
+    let d = *x - *y;
+    let (c_1, c_2) = (x.chroma(), y.chroma());
+    let d_c = c_1 - c_2;
+    let d_h_squared = (d.a * d.a + d.b * d.b - d_c * d_c).max(0.0);
+
+    let s_c = 1.0 + K_1 * c_1;
+    let s_h = 1.0 + K_2 * c_1;
+
+    (d.l * d.l + (d_c / s_c).powi(2) + d_h_squared / (s_h * s_h)).sqrt()
+}
+
+// see http://www2.ece.rochester.edu/~gsharma/ciede2000/ciede2000noteCRNA.pdf
+fn delta_e_2000(x: &Lab, y: &Lab) -> f32 {
+    use core::f32::consts::PI;
+
+    let c_mean = (x.chroma() + y.chroma()) / 2.0;
+    let c_mean_7 = c_mean.powi(7);
+    let g = 0.5 * (1.0 - (c_mean_7 / (c_mean_7 + 25.0f32.powi(7))).sqrt());
+
+    let a_1 = (1.0 + g) * x.a;
+    let a_2 = (1.0 + g) * y.a;
+    let c_1 = a_1.hypot(x.b);
+    let c_2 = a_2.hypot(y.b);
+
+    let hue = |b: f32, a: f32| {
+        if a == 0.0 && b == 0.0 {
+            0.0
+        } else {
+            b.atan2(a).rem_euclid(2.0 * PI)
+        }
+    };
+    let h_1 = hue(x.b, a_1);
+    let h_2 = hue(y.b, a_2);
+
+    let d_l = y.l - x.l;
+    let d_c = c_2 - c_1;
+    let d_h = if c_1 * c_2 == 0.0 {
+        0.0
+    } else {
+        let d = h_2 - h_1;
+        if d > PI {
+            d - 2.0 * PI
+        } else if d < -PI {
+            d + 2.0 * PI
+        } else {
+            d
+        }
+    };
+    let d_hh = 2.0 * (c_1 * c_2).sqrt() * (d_h / 2.0).sin();
+
+    let l_mean = (x.l + y.l) / 2.0;
+    let c_mean = (c_1 + c_2) / 2.0;
+    let h_mean = if c_1 * c_2 == 0.0 {
+        h_1 + h_2
+    } else if (h_1 - h_2).abs() <= PI {
+        (h_1 + h_2) / 2.0
+    } else if h_1 + h_2 < 2.0 * PI {
+        (h_1 + h_2 + 2.0 * PI) / 2.0
+    } else {
+        (h_1 + h_2 - 2.0 * PI) / 2.0
+    };
+
+    let t = 1.0 - 0.17 * (h_mean - 30.0f32.to_radians()).cos()
+        + 0.24 * (2.0 * h_mean).cos()
+        + 0.32 * (3.0 * h_mean + 6.0f32.to_radians()).cos()
+        - 0.20 * (4.0 * h_mean - 63.0f32.to_radians()).cos();
+
+    let d_theta = 30.0f32.to_radians() * (-((h_mean.to_degrees() - 275.0) / 25.0).powi(2)).exp();
+    let c_mean_7 = c_mean.powi(7);
+    let r_c = 2.0 * (c_mean_7 / (c_mean_7 + 25.0f32.powi(7))).sqrt();
+    let r_t = -(2.0 * d_theta).sin() * r_c;
+
+    let l_offset = (l_mean - 50.0).powi(2);
+    let s_l = 1.0 + (0.015 * l_offset) / (20.0 + l_offset).sqrt();
+    let s_c = 1.0 + 0.045 * c_mean;
+    let s_h = 1.0 + 0.015 * c_mean * t;
+
+    let (l, c, h) = (d_l / s_l, d_c / s_c, d_hh / s_h);
+    (l * l + c * c + h * h + r_t * c * h).max(0.0).sqrt()
+}
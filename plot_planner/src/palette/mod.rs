@@ -0,0 +1,437 @@
+// Colour artwork is reduced to the handful of cans actually carried on site,
+// and each can then gets its own coverage mask, screened independently.
+
+mod colour;
+pub use colour::{ColourDistance, Lab};
+
+use crate::generation::hdp_common::memory::Cube;
+
+/// A paint can, as measured on the wall (not as printed on the label).
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaintCan {
+    pub name: String,
+    pub colour: Lab,
+}
+
+impl PaintCan {
+    pub fn new(name: impl Into<String>, colour: Lab) -> Self {
+        Self {
+            name: name.into(),
+            colour,
+        }
+    }
+
+    pub fn from_srgb8(name: impl Into<String>, rgb: [u8; 3]) -> Self {
+        Self::new(name, Lab::from_srgb8(rgb))
+    }
+}
+
+/// The set of cans available for a job.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Palette {
+    cans: Vec<PaintCan>,
+}
+
+impl Palette {
+    /// The label of unpainted pixels, see [`Quantization::labels`]
+    pub const UNPAINTED: u8 = u8::MAX;
+
+    pub fn new(cans: Vec<PaintCan>) -> Self {
+        assert!(
+            cans.len() < Self::UNPAINTED as usize,
+            "at most {} cans are supported",
+            Self::UNPAINTED
+        );
+        Self { cans }
+    }
+
+    pub fn cans(&self) -> &[PaintCan] {
+        &self.cans
+    }
+
+    pub fn len(&self) -> usize {
+        self.cans.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cans.is_empty()
+    }
+
+    /// The closest can to this colour, and its distance
+    pub fn nearest(&self, colour: &Lab, distance: ColourDistance) -> Option<(usize, f32)> {
+        self.cans
+            .iter()
+            .map(|can| distance.delta_e(colour, &can.colour))
+            .enumerate()
+            .min_by(|(_, x), (_, y)| x.total_cmp(y))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Dithering {
+    /// Every pixel gets the closest can
+    #[default]
+    None,
+    /// Error diffusion between the cans, in L*a*b*, on a serpentine scan.
+    /// `strength` scales the propagated error, `1.0` being the classic kernel.
+    FloydSteinberg { strength: f32 },
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct QuantizationSettings {
+    pub distance: ColourDistance,
+    pub dithering: Dithering,
+    /// The colour of the wall.
+    /// Pixels closest to it are left unpainted rather than sprayed with the closest can.
+    pub substrate: Option<Lab>,
+    /// Pixels with an alpha under this threshold (within `[0, 1]`) are left unpainted.
+    pub alpha_threshold: f32,
+}
+
+/// The result of the quantization of an image to a [`Palette`]
+#[derive(Debug, Clone)]
+pub struct Quantization {
+    pub palette: Palette,
+    /// For each pixel, the index of its can within the palette,
+    /// or [`Palette::UNPAINTED`].
+    pub labels: image::GrayImage,
+}
+
+/// Quantizes the image to the given palette.
+pub fn quantize<I>(image: &I, palette: &Palette, settings: &QuantizationSettings) -> Quantization
+where
+    I: image::GenericImageView,
+{
+    use image::Pixel;
+    use num_traits::ToPrimitive;
+
+    let (width, height) = image.dimensions();
+    let max_value = <<I::Pixel as image::Pixel>::Subpixel as image::Primitive>::DEFAULT_MAX_VALUE
+        .to_f32()
+        .unwrap_or(1.0);
+
+    // The targets are the cans, then, optionally, the substrate
+    let targets = palette
+        .cans
+        .iter()
+        .map(|can| can.colour)
+        .chain(settings.substrate)
+        .collect::<Vec<_>>();
+    let substrate_index = settings.substrate.map(|_| palette.len());
+
+    // Nothing to spray with, everything is left as-is
+    let mut labels = image::GrayImage::from_pixel(width, height, image::Luma([Palette::UNPAINTED]));
+    if palette.is_empty() {
+        return Quantization {
+            palette: palette.clone(),
+            labels,
+        };
+    }
+
+    let mut colours = Vec::with_capacity((width as usize) * (height as usize));
+    let mut opaque = Vec::with_capacity(colours.capacity());
+    for (_, _, pixel) in image.pixels() {
+        let [r, g, b, a] = pixel
+            .to_rgba()
+            .0
+            .map(|c| c.to_f32().unwrap_or(0.0) / max_value);
+        colours.push(Lab::from_srgb([r, g, b]));
+        opaque.push(a >= settings.alpha_threshold);
+    }
+
+    let nearest = |colour: &Lab| {
+        targets
+            .iter()
+            .map(|target| settings.distance.delta_e(colour, target))
+            .enumerate()
+            .min_by(|(_, x), (_, y)| x.total_cmp(y))
+            .map(|(i, _)| i)
+            .unwrap()
+    };
+
+    let label_of = |target: usize| {
+        if Some(target) == substrate_index {
+            Palette::UNPAINTED
+        } else {
+            target as u8
+        }
+    };
+
+    match settings.dithering {
+        Dithering::None => {
+            for (i, (colour, opaque)) in colours.iter().zip(opaque.iter()).enumerate() {
+                if *opaque {
+                    let (x, y) = ((i as u32) % width, (i as u32) / width);
+                    labels.put_pixel(x, y, image::Luma([label_of(nearest(colour))]));
+                }
+            }
+        }
+        Dithering::FloydSteinberg { strength } => {
+            let (w, h) = (width as usize, height as usize);
+            for y in 0..h {
+                // Serpentine scan, to avoid the directional artifacts
+                let forward = y % 2 == 0;
+                let dx: isize = if forward { 1 } else { -1 };
+
+                for step in 0..w {
+                    let x = if forward { step } else { w - 1 - step };
+                    let i = y * w + x;
+                    if !opaque[i] {
+                        continue;
+                    }
+
+                    let target = nearest(&colours[i]);
+                    labels.put_pixel(x as u32, y as u32, image::Luma([label_of(target)]));
+
+                    let error = (colours[i] - targets[target]) * strength;
+                    let mut diffuse = |x: isize, y: usize, weight: f32| {
+                        if x < 0 || (x as usize) >= w || y >= h {
+                            return;
+                        }
+                        let j = y * w + (x as usize);
+                        if opaque[j] {
+                            colours[j] = colours[j] + error * weight;
+                        }
+                    };
+
+                    let x = x as isize;
+                    diffuse(x + dx, y, 7.0 / 16.0);
+                    diffuse(x - dx, y + 1, 3.0 / 16.0);
+                    diffuse(x, y + 1, 5.0 / 16.0);
+                    diffuse(x + dx, y + 1, 1.0 / 16.0);
+                }
+            }
+        }
+    }
+
+    Quantization {
+        palette: palette.clone(),
+        labels,
+    }
+}
+
+/// The coverage of a single can, ready to be screened by its own generator.
+pub struct PaletteLayer<'a> {
+    pub index: usize,
+    pub can: &'a PaintCan,
+    /// `1.0` where this can should be sprayed, `0.0` elsewhere
+    pub coverage: image::ImageBuffer<image::Luma<f32>, Vec<f32>>,
+}
+
+impl<'a> PaletteLayer<'a> {
+    /// Starts the generator of this layer, over the same pixels as [`Self::coverage`].
+    /// The process is to be fed with the returned cube of the coverage,
+    /// e.g. through [`crate::generation::drive`].
+    pub fn start<G>(
+        &self,
+        image: &crate::ImageWorldPlacement,
+        config: G::Config,
+    ) -> (G::Process, Cube<&[f32]>)
+    where
+        G: crate::generation::Generator<f32>,
+    {
+        assert_eq!(
+            (image.im_width, image.im_height),
+            self.coverage.dimensions(),
+            "the placement must be the one of the quantized image"
+        );
+        (
+            G::start(image, config),
+            Cube::from_image(self.coverage.as_raw().as_slice()),
+        )
+    }
+}
+
+impl Quantization {
+    /// The coverage mask of the given can
+    pub fn coverage(&self, can: usize) -> image::ImageBuffer<image::Luma<f32>, Vec<f32>> {
+        let label = can as u8;
+        let (width, height) = self.labels.dimensions();
+        image::ImageBuffer::from_fn(width, height, |x, y| {
+            image::Luma([if self.labels.get_pixel(x, y).0[0] == label {
+                1.0
+            } else {
+                0.0
+            }])
+        })
+    }
+
+    /// Fraction of the pixels sprayed with the given can, within `[0, 1]`
+    pub fn coverage_ratio(&self, can: usize) -> f32 {
+        let label = can as u8;
+        let total = self.labels.as_raw().len();
+        if total == 0 {
+            return 0.0;
+        }
+        let count = self.labels.as_raw().iter().filter(|l| **l == label).count();
+        (count as f32) / (total as f32)
+    }
+
+    /// One layer per can of the palette, including the ones that end up unused
+    pub fn layers(&self) -> impl Iterator<Item = PaletteLayer<'_>> {
+        self.palette
+            .cans
+            .iter()
+            .enumerate()
+            .map(|(index, can)| PaletteLayer {
+                index,
+                can,
+                coverage: self.coverage(index),
+            })
+    }
+
+    /// The quantized image, as it would look once sprayed
+    pub fn preview(&self, substrate: Lab) -> image::RgbImage {
+        let (width, height) = self.labels.dimensions();
+        image::RgbImage::from_fn(width, height, |x, y| {
+            let label = self.labels.get_pixel(x, y).0[0];
+            let colour = self
+                .palette
+                .cans
+                .get(label as usize)
+                .map_or(substrate, |can| can.colour);
+            image::Rgb(colour.to_srgb8())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn palette() -> Palette {
+        Palette::new(vec![
+            PaintCan::from_srgb8("black", [0, 0, 0]),
+            PaintCan::from_srgb8("red", [200, 20, 30]),
+        ])
+    }
+
+    #[test]
+    fn lab_roundtrip() {
+        for rgb in [[0, 0, 0], [255, 255, 255], [200, 20, 30], [12, 180, 90]] {
+            assert_eq!(Lab::from_srgb8(rgb).to_srgb8(), rgb);
+        }
+    }
+
+    #[test]
+    fn delta_e_2000_reference() {
+        // Pair 1 of the Sharma et al. test data
+        let x = Lab::new(50.0, 2.6772, -79.7751);
+        let y = Lab::new(50.0, 0.0, -82.7485);
+        let d = ColourDistance::Ciede2000.delta_e(&x, &y);
+        assert!((d - 2.0425).abs() < 1e-3, "{d}");
+    }
+
+    #[test]
+    fn quantize_to_nearest_can() {
+        let image = image::RgbImage::from_fn(4, 2, |x, _| {
+            if x < 2 {
+                image::Rgb([10, 10, 10])
+            } else {
+                image::Rgb([220, 40, 40])
+            }
+        });
+
+        let quantized = quantize(&image, &palette(), &QuantizationSettings::default());
+        assert_eq!(quantized.labels.get_pixel(0, 0).0[0], 0);
+        assert_eq!(quantized.labels.get_pixel(3, 1).0[0], 1);
+        assert_eq!(quantized.coverage_ratio(0), 0.5);
+
+        let layers = quantized.layers().collect::<Vec<_>>();
+        assert_eq!(layers.len(), 2);
+        assert_eq!(layers[1].coverage.get_pixel(3, 0).0[0], 1.0);
+        assert_eq!(layers[1].coverage.get_pixel(0, 0).0[0], 0.0);
+    }
+
+    /// Starts nothing, whatever the image
+    struct IdleGenerator;
+
+    impl crate::generation::Generator<f32> for IdleGenerator {
+        type Config = ();
+        type Process = IdleGenerator;
+
+        fn start(_image: &crate::ImageWorldPlacement, _config: ()) -> Self::Process {
+            IdleGenerator
+        }
+    }
+
+    impl crate::generation::GenerationProcess<f32> for IdleGenerator {
+        type Error = ();
+
+        fn generate<B: crate::generation::GenerationBuffer>(
+            &mut self,
+            _image: &Cube<&[f32]>,
+            _buffer: &mut B,
+            _count: usize,
+        ) -> crate::generation::GenerationControlFlow<Self::Error> {
+            crate::generation::GenerationControlFlow::Finished
+        }
+
+        fn min_left(&self) -> (usize, Option<usize>) {
+            (0, Some(0))
+        }
+    }
+
+    fn gray_quantized() -> (image::RgbImage, Quantization) {
+        let image = image::RgbImage::from_pixel(4, 2, image::Rgb([10, 10, 10]));
+        let quantized = quantize(&image, &palette(), &QuantizationSettings::default());
+        (image, quantized)
+    }
+
+    #[test]
+    fn layers_start_on_their_coverage() {
+        let (image, quantized) = gray_quantized();
+        let layer = quantized.layers().next().unwrap();
+
+        let placement = crate::ImageWorldPlacement::from_image(&image, Default::default(), 1.0);
+        let _ = layer.start::<IdleGenerator>(&placement, ());
+    }
+
+    #[test]
+    #[should_panic(expected = "placement")]
+    fn layers_reject_another_placement() {
+        let (_, quantized) = gray_quantized();
+        let layer = quantized.layers().next().unwrap();
+
+        // A placement of another size would screen the wrong pixels
+        let placement = crate::ImageWorldPlacement::new(8, 4, Default::default(), 2.0);
+        let _ = layer.start::<IdleGenerator>(&placement, ());
+    }
+
+    #[test]
+    fn substrate_is_left_unpainted() {
+        let image = image::RgbImage::from_pixel(2, 2, image::Rgb([250, 250, 250]));
+        let settings = QuantizationSettings {
+            substrate: Some(Lab::from_srgb8([255, 255, 255])),
+            ..Default::default()
+        };
+
+        let quantized = quantize(&image, &palette(), &settings);
+        assert!(
+            quantized
+                .labels
+                .pixels()
+                .all(|l| l.0[0] == Palette::UNPAINTED)
+        );
+    }
+
+    #[test]
+    fn dithering_mixes_cans() {
+        // Mid-gray between black and white cans should be dithered roughly half & half
+        let palette = Palette::new(vec![
+            PaintCan::from_srgb8("black", [0, 0, 0]),
+            PaintCan::from_srgb8("white", [255, 255, 255]),
+        ]);
+        let gray = Lab::new(50.0, 0.0, 0.0).to_srgb8();
+        let image = image::RgbImage::from_pixel(32, 32, image::Rgb(gray));
+        let settings = QuantizationSettings {
+            distance: ColourDistance::Cie76,
+            dithering: Dithering::FloydSteinberg { strength: 1.0 },
+            ..Default::default()
+        };
+
+        let quantized = quantize(&image, &palette, &settings);
+        let black = quantized.coverage_ratio(0);
+        assert!(black > 0.35 && black < 0.65, "{black}");
+    }
+}
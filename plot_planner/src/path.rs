@@ -10,27 +10,325 @@ impl Point {
     }
 }
 
+/// A primitive of a [`SectionBuffer`], in the order it is painted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TourItem {
+    /// Index within [`SectionBuffer::dots`]
+    Dot(usize),
+    /// Index within [`SectionBuffer::edges`], painted `to -> from` when reversed
+    Edge { index: usize, reversed: bool },
+}
+
+/// What is painted at a given step of a tour
+#[derive(Debug, Clone, Copy)]
+pub enum Stroke {
+    Dot(Point),
+    Line {
+        from: Point,
+        to: Point,
+        width: f32,
+    },
+    /// A waypoint flown without painting, e.g. around an obstacle
+    Transit(nalgebra::Point2<f32>),
+}
+
+impl Stroke {
+    /// Where the applicator starts painting
+    pub fn start(&self) -> nalgebra::Point2<f32> {
+        match self {
+            Self::Dot(p) => p.position,
+            Self::Line { from, .. } => from.position,
+            Self::Transit(p) => *p,
+        }
+    }
+
+    /// Where the applicator stops painting
+    pub fn end(&self) -> nalgebra::Point2<f32> {
+        match self {
+            Self::Dot(p) => p.position,
+            Self::Line { to, .. } => to.position,
+            Self::Transit(p) => *p,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct SectionBuffer {
     pub points: Vec<Point>,
+    /// Indices of the points painted as dots
+    pub dots: Vec<usize>,
+    /// Pairs of point indices painted as lines
     pub edges: Vec<[usize; 2]>,
+    /// The width of each edge
+    pub edge_widths: Vec<f32>,
 }
 
 impl SectionBuffer {
     pub fn new() -> Self {
         Self {
             points: Vec::new(),
+            dots: Vec::new(),
             edges: Vec::new(),
+            edge_widths: Vec::new(),
+        }
+    }
+
+    /// Adds a point without painting it, e.g. to be shared by several edges.
+    /// Returns its index.
+    pub fn push_vertex(&mut self, p: Point) -> usize {
+        self.points.push(p);
+        self.points.len() - 1
+    }
+
+    /// Paints a dot, returns its index within [`Self::dots`]
+    pub fn push_point(&mut self, p: Point) -> usize {
+        let index = self.push_vertex(p);
+        self.dots.push(index);
+        self.dots.len() - 1
+    }
+
+    /// Paints a line between two existing points, returns its index within [`Self::edges`]
+    pub fn push_edge(&mut self, from: usize, to: usize, width: f32) -> usize {
+        debug_assert!(from < self.points.len() && to < self.points.len());
+        self.edges.push([from, to]);
+        self.edge_widths.push(width);
+        self.edges.len() - 1
+    }
+
+    /// Paints a line, returns its index within [`Self::edges`]
+    pub fn push_line(&mut self, from: Point, to: Point, width: f32) -> usize {
+        let from = self.push_vertex(from);
+        let to = self.push_vertex(to);
+        self.push_edge(from, to, width)
+    }
+
+    /// Paints a polyline, each segment with its own width.
+    /// The vertices are shared by consecutive segments.
+    ///
+    /// Returns the range of its edges within [`Self::edges`].
+    pub fn push_polyline(&mut self, vertices: &[Point], widths: &[f32]) -> core::ops::Range<usize> {
+        assert_eq!(
+            widths.len(),
+            vertices.len().saturating_sub(1),
+            "one width per segment is expected"
+        );
+
+        let start = self.edges.len();
+        let first = self.points.len();
+        self.points.extend_from_slice(vertices);
+        for (i, width) in widths.iter().enumerate() {
+            self.push_edge(first + i, first + i + 1, *width);
+        }
+        start..self.edges.len()
+    }
+
+    /// Converts the primitives of a generation.
+    /// Generated points have no size: all dots get `dot_size`, and all lines `line_width`.
+    pub fn from_generation<A>(
+        buffer: &crate::generation::GenerationBufferVec<A>,
+        dot_size: f32,
+        line_width: f32,
+    ) -> Self
+    where
+        A: core::alloc::Allocator,
+    {
+        let points = buffer
+            .points
+            .x
+            .iter()
+            .zip(buffer.points.y.iter())
+            .map(|(x, y)| Point::new(nalgebra::Point2::new(*x, *y), dot_size))
+            .collect();
+        let edges = buffer
+            .edges
+            .from
+            .iter()
+            .zip(buffer.edges.to.iter())
+            .map(|(from, to)| [*from, *to])
+            .collect::<Vec<_>>();
+
+        Self {
+            points,
+            dots: buffer.dots.index.to_vec(),
+            edge_widths: vec![line_width; edges.len()],
+            edges,
+        }
+    }
+
+    /// Every primitive, dots first, lines in their recorded direction
+    pub fn items(&self) -> impl Iterator<Item = TourItem> + '_ {
+        (0..self.dots.len())
+            .map(TourItem::Dot)
+            .chain((0..self.edges.len()).map(|index| TourItem::Edge {
+                index,
+                reversed: false,
+            }))
+    }
+
+    pub fn stroke(&self, item: TourItem) -> Stroke {
+        match item {
+            TourItem::Dot(index) => Stroke::Dot(self.points[self.dots[index]]),
+            TourItem::Edge { index, reversed } => {
+                let [mut from, mut to] = self.edges[index];
+                if reversed {
+                    core::mem::swap(&mut from, &mut to);
+                }
+                Stroke::Line {
+                    from: self.points[from],
+                    to: self.points[to],
+                    width: self.edge_widths[index],
+                }
+            }
         }
     }
 
-    pub fn push_point(&mut self, p: Point) {
-        //println!("TODO: paint point p: )p:?}")
-        self.points.push(p)
+    /// The strokes, in the order of the tour
+    pub fn iter_tour<'a>(&'a self, tour: &'a [TourItem]) -> impl Iterator<Item = Stroke> + 'a {
+        tour.iter().map(|item| self.stroke(*item))
     }
 
-    pub fn push_line(&mut self, from: Point, to: Point) {
-        println!("TODO: paint line {from:?} -> {to:?}");
-        todo!()
+    /// The strokes, in the order of the tour, from `start`,
+    /// with the waypoints of the detours around the obstacles in between.
+    ///
+    /// A primitive without a way around yields an error instead of its stroke,
+    /// rather than being flown to through the obstacles.
+    pub fn iter_tour_routed<'a>(
+        &'a self,
+        tour: &'a [TourItem],
+        router: &'a crate::optimization::TransitRouter,
+        start: nalgebra::Point2<f32>,
+    ) -> impl Iterator<Item = Result<Stroke, crate::optimization::RoutingError>> + 'a {
+        let mut position = start;
+        tour.iter().flat_map(move |item| {
+            let stroke = self.stroke(*item);
+            let detour = router.detour(position, stroke.start());
+            position = stroke.end();
+            let (waypoints, stroke) = match detour {
+                Some(waypoints) => (waypoints, Ok(stroke)),
+                None => (
+                    Vec::new(),
+                    Err(crate::optimization::RoutingError::Unroutable(*item)),
+                ),
+            };
+            waypoints
+                .into_iter()
+                .map(|waypoint| Ok(Stroke::Transit(waypoint)))
+                .chain([stroke])
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(x: f32, y: f32) -> Point {
+        Point::new(nalgebra::Point2::new(x, y), 1.0)
+    }
+
+    #[test]
+    fn polyline_shares_vertices() {
+        let mut buffer = SectionBuffer::new();
+        buffer.push_point(point(-1.0, 0.0));
+        let edges = buffer.push_polyline(
+            &[point(0.0, 0.0), point(1.0, 0.0), point(1.0, 1.0)],
+            &[0.5, 2.0],
+        );
+
+        assert_eq!(edges, 0..2);
+        assert_eq!(buffer.points.len(), 4);
+        assert_eq!(buffer.edges, vec![[1, 2], [2, 3]]);
+        assert_eq!(buffer.edge_widths, vec![0.5, 2.0]);
+    }
+
+    #[test]
+    fn tour_reverses_lines() {
+        let mut buffer = SectionBuffer::new();
+        let line = buffer.push_line(point(0.0, 0.0), point(2.0, 0.0), 0.5);
+        let dot = buffer.push_point(point(3.0, 3.0));
+
+        let tour = [
+            TourItem::Dot(dot),
+            TourItem::Edge {
+                index: line,
+                reversed: true,
+            },
+        ];
+        let strokes = buffer.iter_tour(&tour).collect::<Vec<_>>();
+        assert_eq!(strokes[0].end(), nalgebra::Point2::new(3.0, 3.0));
+        assert_eq!(strokes[1].start(), nalgebra::Point2::new(2.0, 0.0));
+        assert_eq!(strokes[1].end(), nalgebra::Point2::new(0.0, 0.0));
+        assert_eq!(buffer.items().count(), 2);
+    }
+
+    #[test]
+    fn routed_tour_goes_around_obstacles() {
+        let mut buffer = SectionBuffer::new();
+        let first = buffer.push_point(point(-2.0, 0.0));
+        let second = buffer.push_point(point(2.0, 0.0));
+        let wall = vec![
+            nalgebra::Point2::new(0.0, -1.0),
+            nalgebra::Point2::new(0.0, 1.0),
+        ];
+        let router = crate::optimization::TransitRouter::new(&[wall], 0.1);
+
+        let tour = [TourItem::Dot(first), TourItem::Dot(second)];
+        let strokes = buffer
+            .iter_tour_routed(&tour, &router, nalgebra::Point2::new(-3.0, 0.0))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert!(matches!(strokes[0], Stroke::Dot(_)));
+        assert!(matches!(strokes.last(), Some(Stroke::Dot(_))));
+        assert!(strokes.len() > 2);
+        for leg in strokes.windows(2) {
+            assert!(router.is_clear(leg[0].end(), leg[1].start()));
+        }
+    }
+
+    #[test]
+    fn routed_tour_fails_on_enclosed_strokes() {
+        let mut buffer = SectionBuffer::new();
+        let outside = buffer.push_point(point(-4.0, 0.0));
+        let enclosed = buffer.push_point(point(0.0, 0.0));
+        // Four walls, overlapping at the corners of a box around the second dot
+        let wall = |from: (f32, f32), to: (f32, f32)| {
+            vec![
+                nalgebra::Point2::new(from.0, from.1),
+                nalgebra::Point2::new(to.0, to.1),
+            ]
+        };
+        let walls = [
+            wall((-2.0, -2.5), (-2.0, 2.5)),
+            wall((2.0, -2.5), (2.0, 2.5)),
+            wall((-2.5, -2.0), (2.5, -2.0)),
+            wall((-2.5, 2.0), (2.5, 2.0)),
+        ];
+        let router = crate::optimization::TransitRouter::new(&walls, 0.2);
+
+        let tour = [TourItem::Dot(outside), TourItem::Dot(enclosed)];
+        let strokes = buffer
+            .iter_tour_routed(&tour, &router, nalgebra::Point2::new(-5.0, 0.0))
+            .collect::<Vec<_>>();
+        assert!(matches!(strokes[0], Ok(Stroke::Dot(_))));
+        assert_eq!(
+            strokes.last().unwrap().as_ref().unwrap_err(),
+            &crate::optimization::RoutingError::Unroutable(TourItem::Dot(enclosed))
+        );
+    }
+
+    #[test]
+    fn from_generation_keeps_indices() {
+        let mut generated = crate::generation::GenerationBufferVec::new();
+        generated.points.x.extend([0.0, 1.0, 2.0]);
+        generated.points.y.extend([0.0, 0.0, 5.0]);
+        generated.edges.from.push(0);
+        generated.edges.to.push(1);
+        generated.dots.index.push(2);
+
+        let buffer = SectionBuffer::from_generation(&generated, 1.5, 0.5);
+        assert_eq!(buffer.dots, vec![2]);
+        assert_eq!(buffer.edges, vec![[0, 1]]);
+        assert_eq!(buffer.points[2].position, nalgebra::Point2::new(2.0, 5.0));
+        assert_eq!(buffer.points[2].size, 1.5);
     }
 }
@@ -0,0 +1,380 @@
+// Flying through the dots instead of stopping at each of them:
+// a spline is laid through the tour, flown as fast as the limits allow,
+// and the spray is triggered early enough for the paint to land on the dots.
+
+use nalgebra::{Point2, Vector2};
+
+/// The limits of the vehicle & of the applicator
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrajectorySettings {
+    pub max_speed: f32,
+    /// Along the path
+    pub max_acceleration: f32,
+    /// Across the path, which bounds the speed where it curves: `v² κ <= max_lateral_acceleration`
+    pub max_lateral_acceleration: f32,
+    /// When passing over a dot, for the paint not to smear
+    pub max_spray_speed: f32,
+    /// Between the trigger & the paint leaving the nozzle
+    pub nozzle_latency: f32,
+    /// Between the paint leaving the nozzle & landing, carried along by the vehicle meanwhile
+    pub paint_travel_time: f32,
+    /// Distance between the samples of the spline, see [`Self::new`]
+    spacing: f32,
+}
+
+/// A sample of a [`Trajectory`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Waypoint {
+    pub time: f32,
+    pub position: Point2<f32>,
+    pub velocity: Vector2<f32>,
+}
+
+/// When to open the nozzle for a dot
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SprayTrigger {
+    /// Index of the dot, within the tour
+    pub dot: usize,
+    pub time: f32,
+    /// Where the vehicle is at [`Self::time`]
+    pub position: Point2<f32>,
+    /// Distance, along the path, between [`Self::position`] & the dot
+    pub lead: f32,
+}
+
+/// Continuous motion through a tour of dots
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trajectory {
+    pub waypoints: Vec<Waypoint>,
+    /// In the order of the tour, so by increasing time
+    pub triggers: Vec<SprayTrigger>,
+    pub duration: f32,
+}
+
+impl TrajectorySettings {
+    /// Panics unless `spacing` is finite and positive
+    pub fn new(
+        max_speed: f32,
+        max_acceleration: f32,
+        max_lateral_acceleration: f32,
+        max_spray_speed: f32,
+        nozzle_latency: f32,
+        paint_travel_time: f32,
+        spacing: f32,
+    ) -> Self {
+        assert!(
+            spacing.is_finite() && spacing > 0.0,
+            "the spacing should be finite and positive"
+        );
+        Self {
+            max_speed,
+            max_acceleration,
+            max_lateral_acceleration,
+            max_spray_speed,
+            nozzle_latency,
+            paint_travel_time,
+            spacing,
+        }
+    }
+
+    pub fn spacing(&self) -> f32 {
+        self.spacing
+    }
+
+    /// Lays a spline from `start` through `dots` (e.g. the [`crate::path::Stroke::Dot`]s of a tour),
+    /// and times both the motion & the triggers.
+    ///
+    /// The spline is a chordal Catmull-Rom: the tangent at each dot follows its two neighbours,
+    /// scaled by the distances to them, which keeps it from looping between close dots.
+    /// The tangents are at most unit, so that the spline strays from the straight leg
+    /// between two dots by at most a quarter of its length.
+    /// Its curvature is not bounded though: where the tour turns back, the tangent vanishes
+    /// and the spline bends sharply around the dot, the vehicle slowing down accordingly.
+    ///
+    /// The vehicle takes off & ends at rest. Where the first triggers would be due before take-off,
+    /// it hovers at `start` for that long.
+    pub fn fly_through(&self, start: Point2<f32>, dots: &[Point2<f32>]) -> Trajectory {
+        let knots = core::iter::once(start)
+            .chain(dots.iter().copied())
+            .collect::<Vec<_>>();
+        let distances = knots
+            .windows(2)
+            .map(|pair| (pair[1] - pair[0]).norm())
+            .collect::<Vec<_>>();
+        // Derivative of the position along the arc, at each knot
+        let tangents = (0..knots.len())
+            .map(|i| {
+                let before = i.checked_sub(1).filter(|b| distances[*b] > 0.0);
+                let after = (i < distances.len() && distances[i] > 0.0).then_some(i);
+                match (before, after) {
+                    (Some(b), Some(a)) => {
+                        (knots[i + 1] - knots[i - 1]) / (distances[b] + distances[a])
+                    }
+                    (Some(b), None) => (knots[i] - knots[i - 1]) / distances[b],
+                    (None, Some(a)) => (knots[i + 1] - knots[i]) / distances[a],
+                    (None, None) => Vector2::zeros(),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // Samples of the spline: position, unit tangent, curvature, and the knot they land on
+        let mut positions = vec![start];
+        let mut directions = vec![tangents[0].try_normalize(0.0).unwrap_or_default()];
+        let mut curvatures = vec![0.0];
+        let mut knot_samples = vec![0];
+        for (i, distance) in distances.iter().enumerate() {
+            let steps = (distance / self.spacing).ceil().max(1.0) as usize;
+            let (p0, p1) = (knots[i].coords, knots[i + 1].coords);
+            let (m0, m1) = (tangents[i] * *distance, tangents[i + 1] * *distance);
+            for k in 1..=steps {
+                let u = k as f32 / steps as f32;
+                let (u2, u3) = (u * u, u * u * u);
+                let position = p0 * (2.0 * u3 - 3.0 * u2 + 1.0)
+                    + m0 * (u3 - 2.0 * u2 + u)
+                    + p1 * (-2.0 * u3 + 3.0 * u2)
+                    + m1 * (u3 - u2);
+                let first = p0 * (6.0 * u2 - 6.0 * u)
+                    + m0 * (3.0 * u2 - 4.0 * u + 1.0)
+                    + p1 * (-6.0 * u2 + 6.0 * u)
+                    + m1 * (3.0 * u2 - 2.0 * u);
+                let second = p0 * (12.0 * u - 6.0)
+                    + m0 * (6.0 * u - 4.0)
+                    + p1 * (-12.0 * u + 6.0)
+                    + m1 * (6.0 * u - 2.0);
+                let speed = first.norm();
+                positions.push(Point2::from(position));
+                directions.push(first.try_normalize(0.0).unwrap_or_default());
+                curvatures.push(if speed > 0.0 {
+                    (first.x * second.y - first.y * second.x).abs() / speed.powi(3)
+                } else {
+                    0.0
+                });
+            }
+            knot_samples.push(positions.len() - 1);
+        }
+
+        let n = positions.len();
+        let arcs = core::iter::once(0.0)
+            .chain(positions.windows(2).map(|pair| (pair[1] - pair[0]).norm()))
+            .scan(0.0, |length, step| {
+                *length += step;
+                Some(*length)
+            })
+            .collect::<Vec<_>>();
+
+        // The fastest speed profile within the limits: the pointwise ones, then the acceleration both ways
+        let mut speeds = curvatures
+            .iter()
+            .map(|curvature| {
+                if *curvature > 0.0 {
+                    self.max_speed
+                        .min((self.max_lateral_acceleration / curvature).sqrt())
+                } else {
+                    self.max_speed
+                }
+            })
+            .collect::<Vec<_>>();
+        for sample in knot_samples.iter().skip(1) {
+            speeds[*sample] = speeds[*sample].min(self.max_spray_speed);
+        }
+        speeds[0] = 0.0;
+        speeds[n - 1] = 0.0;
+        for k in 1..n {
+            let reachable = (speeds[k - 1].powi(2)
+                + 2.0 * self.max_acceleration * (arcs[k] - arcs[k - 1]))
+                .sqrt();
+            speeds[k] = speeds[k].min(reachable);
+        }
+        for k in (0..n - 1).rev() {
+            let reachable = (speeds[k + 1].powi(2)
+                + 2.0 * self.max_acceleration * (arcs[k + 1] - arcs[k]))
+                .sqrt();
+            speeds[k] = speeds[k].min(reachable);
+        }
+
+        let mut times = vec![0.0; n];
+        for k in 1..n {
+            let mean = (speeds[k - 1] + speeds[k]) / 2.0;
+            let step = arcs[k] - arcs[k - 1];
+            times[k] = times[k - 1] + if step > 0.0 { step / mean } else { 0.0 };
+        }
+
+        // Where the paint leaves the nozzle, it lands as far as the vehicle goes meanwhile
+        let lead_time = self.nozzle_latency + self.paint_travel_time;
+        let mut triggers = knot_samples
+            .iter()
+            .skip(1)
+            .enumerate()
+            .map(|(dot, sample)| {
+                let time = times[*sample] - lead_time;
+                let (position, arc) = at(&times, &positions, &arcs, time);
+                SprayTrigger {
+                    dot,
+                    time,
+                    position,
+                    lead: arcs[*sample] - arc,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let hover = triggers
+            .iter()
+            .map(|trigger| -trigger.time)
+            .fold(0.0, f32::max);
+        for trigger in &mut triggers {
+            trigger.time += hover;
+            // Hovering at the start, the vehicle is not moving yet
+            if trigger.time < hover {
+                trigger.position = start;
+                trigger.lead = arcs[knot_samples[trigger.dot + 1]];
+            }
+        }
+        let waypoints = (hover > 0.0)
+            .then_some(Waypoint {
+                time: 0.0,
+                position: start,
+                velocity: Vector2::zeros(),
+            })
+            .into_iter()
+            .chain((0..n).map(|k| Waypoint {
+                time: times[k] + hover,
+                position: positions[k],
+                velocity: directions[k] * speeds[k],
+            }))
+            .collect();
+
+        Trajectory {
+            waypoints,
+            triggers,
+            duration: times[n - 1] + hover,
+        }
+    }
+}
+
+/// The position & arc length at `time`, linearly interpolated between the samples,
+/// at the start before it
+fn at(times: &[f32], positions: &[Point2<f32>], arcs: &[f32], time: f32) -> (Point2<f32>, f32) {
+    let next = times.partition_point(|t| *t <= time);
+    if next == 0 {
+        return (positions[0], 0.0);
+    }
+    if next == times.len() {
+        return (positions[next - 1], arcs[next - 1]);
+    }
+    let ratio = (time - times[next - 1]) / (times[next] - times[next - 1]);
+    (
+        positions[next - 1] + (positions[next] - positions[next - 1]) * ratio,
+        arcs[next - 1] + (arcs[next] - arcs[next - 1]) * ratio,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> TrajectorySettings {
+        TrajectorySettings::new(4.0, 2.0, 3.0, 2.0, 0.05, 0.2, 0.05)
+    }
+
+    #[test]
+    fn flies_through_within_limits() {
+        let settings = settings();
+        let dots = (0..12)
+            .map(|i| Point2::new(i as f32 * 1.5, if i % 2 == 0 { 0.0 } else { 1.0 }))
+            .collect::<Vec<_>>();
+        let start = Point2::new(-1.0, 0.0);
+        let trajectory = settings.fly_through(start, &dots);
+
+        for pair in trajectory.waypoints.windows(2) {
+            assert!(pair[1].time >= pair[0].time);
+            assert!(pair[1].velocity.norm() <= settings.max_speed + 1e-3);
+            // The mean speed between the samples is also the one of the profile
+            let step = (pair[1].position - pair[0].position).norm();
+            let dt = pair[1].time - pair[0].time;
+            if dt > 0.0 {
+                let mean = (pair[0].velocity.norm() + pair[1].velocity.norm()) / 2.0;
+                assert!((step / dt - mean).abs() < 1e-2 * mean.max(1.0));
+            }
+        }
+        assert_eq!(trajectory.waypoints.last().unwrap().velocity.norm(), 0.0);
+
+        assert_eq!(trajectory.triggers.len(), dots.len());
+        for (trigger, dot) in trajectory.triggers.iter().zip(&dots) {
+            // The vehicle is over the dot exactly when the paint lands
+            let landing = trigger.time + settings.nozzle_latency + settings.paint_travel_time;
+            let over = trajectory
+                .waypoints
+                .iter()
+                .find(|waypoint| (waypoint.position - dot).norm() < 1e-4)
+                .unwrap();
+            assert!((over.time - landing).abs() < 1e-3, "{over:?} {trigger:?}");
+            assert!(over.velocity.norm() <= settings.max_spray_speed + 1e-3);
+            assert!(trigger.lead > 0.0);
+        }
+        assert!(
+            trajectory
+                .triggers
+                .windows(2)
+                .all(|pair| pair[0].time <= pair[1].time)
+        );
+
+        // Much faster than stopping at each dot
+        let cost = crate::optimization::KinematicCost {
+            max_velocity: Vector2::repeat(settings.max_speed),
+            max_acceleration: Vector2::repeat(settings.max_acceleration),
+            profile: Default::default(),
+            junction_deviation: 0.0,
+        };
+        let stopping = core::iter::once(start)
+            .chain(dots.iter().copied())
+            .collect::<Vec<_>>()
+            .windows(2)
+            .map(|leg| cost.time(None, leg[0], leg[1]) + cost.stopping_time(leg[0], leg[1]))
+            .sum::<f32>();
+        assert!(
+            trajectory.duration < 0.8 * stopping,
+            "{} {stopping}",
+            trajectory.duration
+        );
+    }
+
+    #[test]
+    fn hovers_for_early_triggers() {
+        let settings = TrajectorySettings {
+            paint_travel_time: 2.0,
+            ..settings()
+        };
+        let trajectory = settings.fly_through(Point2::origin(), &[Point2::new(0.1, 0.0)]);
+        let trigger = trajectory.triggers[0];
+        assert_eq!(trigger.time, 0.0);
+        assert_eq!(trigger.position, Point2::origin());
+        assert!(trajectory.waypoints[1].time > 1.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "spacing")]
+    fn spacing_is_positive() {
+        TrajectorySettings::new(4.0, 2.0, 3.0, 2.0, 0.05, 0.2, 0.0);
+    }
+
+    #[test]
+    fn turning_back_stays_close_to_the_legs() {
+        let settings = settings();
+        let dots = [Point2::new(4.0, 0.0), Point2::new(0.0, 0.5)];
+        let trajectory = settings.fly_through(Point2::origin(), &dots);
+        for waypoint in &trajectory.waypoints {
+            let [x, y] = [waypoint.position.x, waypoint.position.y];
+            assert!(
+                (-1.0..=5.0).contains(&x) && (-1.0..=1.5).contains(&y),
+                "{waypoint:?}"
+            );
+        }
+        // Nearly at rest where it turns back
+        let turn = trajectory
+            .waypoints
+            .iter()
+            .find(|waypoint| (waypoint.position - dots[0]).norm() < 1e-4)
+            .unwrap();
+        assert!(turn.velocity.norm() < 0.5, "{turn:?}");
+    }
+}
@@ -362,6 +362,7 @@ fn spawn_mission(
         /*plot_planner::screening::screen_fm(&grayscale, &image_in_world, &grid, &mut rng, |p| {
             path_recorder.push_point(plot_planner::path::Point::new(p, grid.point_size))
         });*/
+        let point_size = grid.point_size;
         use plot_planner::generation::Generator;
         let mut process =
             plot_planner::generation::FMScreeningGenerator::start(&image_in_world, (grid, rng));
@@ -372,18 +373,21 @@ fn spawn_mission(
 
         // OPTIMIZE
 
-        let characteristics = plot_planner::optimization::SpecificEnergyCost {
-            up: 1.5,
-            down: 0.1,
-            forward: 1.0,
-        };
+        let characteristics = plot_planner::optimization::SpecificEnergyCost::from_penalties(1.5, 0.1, 1.0);
         let optimizer = plot_planner::optimization::OptimizationSettings {
             specific_energy: characteristics,
+            penalty: plot_planner::optimization::DirectionChangePenalty(200.0),
             start: nalgebra::Point2::new(0., 0.0),
             include_start: true,
         };
 
-        let order = optimizer.optimize_points(&path_recorder.points);
+        let points = plot_planner::generation::PointSlice {
+            x: &path_recorder.points.x[..],
+            y: &path_recorder.points.y[..],
+        };
+        let mut tour = optimizer.optimize_points(points.clone());
+        optimizer.improve_points(points, &mut tour, &Default::default());
+        let order = tour.order;
 
         // we use a raw command queue to pass a FnOnce(&mut World) back to be
         // applied in a deferred manner.
@@ -400,8 +404,10 @@ fn spawn_mission(
                     Children::spawn(SpawnWith(
                         move |parent: &mut bevy::ecs::relationship::RelatedSpawner<ChildOf>| {
                             for item in order {
-                                let point = path_recorder.points[item];
-                                let pos = point.position;
+                                let pos = nalgebra::Point2::new(
+                                    path_recorder.points.x[item],
+                                    path_recorder.points.y[item],
+                                );
 
                                 parent.spawn((
                                     Mesh2d(col.clone()),
@@ -409,12 +415,12 @@ fn spawn_mission(
                                     // Bevy's 2D coordinates are x right, y up, rhs
                                     Transform::from_xyz(pos.x, size.y - pos.y, 0.0).with_scale(
                                         Vec3::new(
-                                            point.size / 2.,
-                                            point.size / 2.,
-                                            point.size / 2.,
+                                            point_size / 2.,
+                                            point_size / 2.,
+                                            point_size / 2.,
                                         ),
                                     ),
-                                    PointSize(point.size),
+                                    PointSize(point_size),
                                 ));
                             }
                         },